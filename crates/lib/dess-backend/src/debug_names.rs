@@ -0,0 +1,128 @@
+//! Human readable names of Vulkan enums for logs, HUDs and crash reports.
+
+use std::fmt;
+
+use ash::vk;
+
+macro_rules! debug_name {
+    ($name:ident, $ty:ty, { $($value:ident => $text:literal,)* }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct $name(pub $ty);
+
+        impl $name {
+            /// Name of the value, `None` for values unknown to the backend.
+            pub fn as_str(&self) -> Option<&'static str> {
+                match self.0 {
+                    $(<$ty>::$value => Some($text),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.as_str() {
+                    Some(name) => f.write_str(name),
+                    None => write!(f, "Unknown({})", self.0.as_raw()),
+                }
+            }
+        }
+    };
+}
+
+debug_name!(PresentModeName, vk::PresentModeKHR, {
+    IMMEDIATE => "Immediate",
+    MAILBOX => "Mailbox",
+    FIFO => "FIFO",
+    FIFO_RELAXED => "FIFO relaxed",
+    SHARED_DEMAND_REFRESH => "Shared demand refresh",
+    SHARED_CONTINUOUS_REFRESH => "Shared continuous refresh",
+});
+
+debug_name!(ColorSpaceName, vk::ColorSpaceKHR, {
+    SRGB_NONLINEAR => "sRGB nonlinear",
+    DISPLAY_P3_NONLINEAR_EXT => "Display P3 nonlinear",
+    EXTENDED_SRGB_LINEAR_EXT => "Extended sRGB linear",
+    EXTENDED_SRGB_NONLINEAR_EXT => "Extended sRGB nonlinear",
+    DISPLAY_P3_LINEAR_EXT => "Display P3 linear",
+    DCI_P3_NONLINEAR_EXT => "DCI-P3 nonlinear",
+    BT709_LINEAR_EXT => "BT.709 linear",
+    BT709_NONLINEAR_EXT => "BT.709 nonlinear",
+    BT2020_LINEAR_EXT => "BT.2020 linear",
+    HDR10_ST2084_EXT => "HDR10 ST.2084",
+    DOLBYVISION_EXT => "Dolby Vision",
+    HDR10_HLG_EXT => "HDR10 HLG",
+    ADOBERGB_LINEAR_EXT => "Adobe RGB linear",
+    ADOBERGB_NONLINEAR_EXT => "Adobe RGB nonlinear",
+    PASS_THROUGH_EXT => "Pass through",
+    DISPLAY_NATIVE_AMD => "Display native",
+});
+
+debug_name!(PhysicalDeviceTypeName, vk::PhysicalDeviceType, {
+    OTHER => "Other",
+    INTEGRATED_GPU => "Integrated GPU",
+    DISCRETE_GPU => "Discrete GPU",
+    VIRTUAL_GPU => "Virtual GPU",
+    CPU => "CPU",
+});
+
+debug_name!(FormatName, vk::Format, {
+    UNDEFINED => "UNDEFINED",
+    R8_UNORM => "R8_UNORM",
+    R8_SNORM => "R8_SNORM",
+    R8_UINT => "R8_UINT",
+    R8G8_UNORM => "R8G8_UNORM",
+    R8G8_SNORM => "R8G8_SNORM",
+    R8G8B8A8_UNORM => "R8G8B8A8_UNORM",
+    R8G8B8A8_SNORM => "R8G8B8A8_SNORM",
+    R8G8B8A8_UINT => "R8G8B8A8_UINT",
+    R8G8B8A8_SRGB => "R8G8B8A8_SRGB",
+    B8G8R8A8_UNORM => "B8G8R8A8_UNORM",
+    B8G8R8A8_SRGB => "B8G8R8A8_SRGB",
+    A2R10G10B10_UNORM_PACK32 => "A2R10G10B10_UNORM_PACK32",
+    A2B10G10R10_UNORM_PACK32 => "A2B10G10R10_UNORM_PACK32",
+    B10G11R11_UFLOAT_PACK32 => "B10G11R11_UFLOAT_PACK32",
+    E5B9G9R9_UFLOAT_PACK32 => "E5B9G9R9_UFLOAT_PACK32",
+    R16_UNORM => "R16_UNORM",
+    R16_UINT => "R16_UINT",
+    R16_SFLOAT => "R16_SFLOAT",
+    R16G16_UNORM => "R16G16_UNORM",
+    R16G16_SFLOAT => "R16G16_SFLOAT",
+    R16G16B16A16_UNORM => "R16G16B16A16_UNORM",
+    R16G16B16A16_SFLOAT => "R16G16B16A16_SFLOAT",
+    R32_UINT => "R32_UINT",
+    R32_SINT => "R32_SINT",
+    R32_SFLOAT => "R32_SFLOAT",
+    R32G32_UINT => "R32G32_UINT",
+    R32G32_SFLOAT => "R32G32_SFLOAT",
+    R32G32B32_SFLOAT => "R32G32B32_SFLOAT",
+    R32G32B32A32_UINT => "R32G32B32A32_UINT",
+    R32G32B32A32_SFLOAT => "R32G32B32A32_SFLOAT",
+    D16_UNORM => "D16_UNORM",
+    X8_D24_UNORM_PACK32 => "X8_D24_UNORM_PACK32",
+    D32_SFLOAT => "D32_SFLOAT",
+    S8_UINT => "S8_UINT",
+    D16_UNORM_S8_UINT => "D16_UNORM_S8_UINT",
+    D24_UNORM_S8_UINT => "D24_UNORM_S8_UINT",
+    D32_SFLOAT_S8_UINT => "D32_SFLOAT_S8_UINT",
+    BC1_RGB_UNORM_BLOCK => "BC1_RGB_UNORM_BLOCK",
+    BC1_RGB_SRGB_BLOCK => "BC1_RGB_SRGB_BLOCK",
+    BC1_RGBA_UNORM_BLOCK => "BC1_RGBA_UNORM_BLOCK",
+    BC1_RGBA_SRGB_BLOCK => "BC1_RGBA_SRGB_BLOCK",
+    BC3_UNORM_BLOCK => "BC3_UNORM_BLOCK",
+    BC3_SRGB_BLOCK => "BC3_SRGB_BLOCK",
+    BC4_UNORM_BLOCK => "BC4_UNORM_BLOCK",
+    BC4_SNORM_BLOCK => "BC4_SNORM_BLOCK",
+    BC5_UNORM_BLOCK => "BC5_UNORM_BLOCK",
+    BC5_SNORM_BLOCK => "BC5_SNORM_BLOCK",
+    BC6H_UFLOAT_BLOCK => "BC6H_UFLOAT_BLOCK",
+    BC6H_SFLOAT_BLOCK => "BC6H_SFLOAT_BLOCK",
+    BC7_UNORM_BLOCK => "BC7_UNORM_BLOCK",
+    BC7_SRGB_BLOCK => "BC7_SRGB_BLOCK",
+    ETC2_R8G8B8_UNORM_BLOCK => "ETC2_R8G8B8_UNORM_BLOCK",
+    ETC2_R8G8B8_SRGB_BLOCK => "ETC2_R8G8B8_SRGB_BLOCK",
+    ETC2_R8G8B8A8_UNORM_BLOCK => "ETC2_R8G8B8A8_UNORM_BLOCK",
+    ETC2_R8G8B8A8_SRGB_BLOCK => "ETC2_R8G8B8A8_SRGB_BLOCK",
+    ASTC_4X4_UNORM_BLOCK => "ASTC_4X4_UNORM_BLOCK",
+    ASTC_4X4_SRGB_BLOCK => "ASTC_4X4_SRGB_BLOCK",
+});
@@ -1,15 +1,73 @@
+use std::{fmt::Debug, mem};
+
 use ash::vk;
 use gpu_alloc_ash::AshMemoryDevice;
 use gpu_descriptor_ash::AshDescriptorDevice;
 
 use crate::{DescriptorAllocator, DescriptorSet, GpuMemory, GpuMemoryAllocator};
 
+type Closure = Box<dyn FnOnce() + Send>;
+
+/// Loaders of the extensions whose objects may be in drop lists, `None` when
+/// the extension isn't enabled.
+#[derive(Clone, Copy)]
+pub(crate) struct CleanupLoaders<'a> {
+    pub acceleration_structure: Option<&'a ash::khr::acceleration_structure::Device>,
+    #[cfg(feature = "video-decode")]
+    pub video_queue: Option<&'a ash::khr::video_queue::Device>,
+}
+
+/// Closures taken out of a drop list by cleanup. They may use the device,
+/// so they must be run after the allocator and frame locks are released.
+#[derive(Default)]
+#[must_use]
+pub(crate) struct Closures(Vec<Closure>);
+
+impl Closures {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn append(&mut self, mut other: Closures) {
+        self.0.append(&mut other.0);
+    }
+
+    pub fn run(self) {
+        self.0.into_iter().for_each(|f| f());
+    }
+}
+
+impl Debug for Closures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} closures", self.0.len())
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct DropList {
     images: Vec<vk::Image>,
+    image_views: Vec<vk::ImageView>,
     buffers: Vec<vk::Buffer>,
     memory: Vec<GpuMemory>,
     descriptors: Vec<DescriptorSet>,
+    descriptor_pools: Vec<vk::DescriptorPool>,
+    events: Vec<vk::Event>,
+    query_pools: Vec<vk::QueryPool>,
+    pipelines: Vec<vk::Pipeline>,
+    pipeline_layouts: Vec<vk::PipelineLayout>,
+    descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    semaphores: Vec<vk::Semaphore>,
+    fences: Vec<vk::Fence>,
+    samplers: Vec<vk::Sampler>,
+    render_passes: Vec<vk::RenderPass>,
+    framebuffers: Vec<vk::Framebuffer>,
+    acceleration_structures: Vec<vk::AccelerationStructureKHR>,
+    shader_modules: Vec<vk::ShaderModule>,
+    #[cfg(feature = "video-decode")]
+    video_session_parameters: Vec<vk::VideoSessionParametersKHR>,
+    #[cfg(feature = "video-decode")]
+    video_sessions: Vec<vk::VideoSessionKHR>,
+    closures: Closures,
 }
 
 impl DropList {
@@ -17,6 +75,10 @@ impl DropList {
         self.images.push(image);
     }
 
+    pub fn drop_image_view(&mut self, view: vk::ImageView) {
+        self.image_views.push(view);
+    }
+
     pub fn drop_buffer(&mut self, buffer: vk::Buffer) {
         self.buffers.push(buffer);
     }
@@ -25,22 +87,166 @@ impl DropList {
         self.descriptors.push(ds);
     }
 
+    pub fn drop_descriptor_pool(&mut self, pool: vk::DescriptorPool) {
+        self.descriptor_pools.push(pool);
+    }
+
     pub fn drop_memory(&mut self, memory: GpuMemory) {
         self.memory.push(memory);
     }
 
-    pub fn cleanup(
+    pub fn drop_event(&mut self, event: vk::Event) {
+        self.events.push(event);
+    }
+
+    pub fn drop_query_pool(&mut self, pool: vk::QueryPool) {
+        self.query_pools.push(pool);
+    }
+
+    pub fn drop_pipeline(&mut self, pipeline: vk::Pipeline) {
+        self.pipelines.push(pipeline);
+    }
+
+    pub fn drop_pipeline_layout(&mut self, layout: vk::PipelineLayout) {
+        self.pipeline_layouts.push(layout);
+    }
+
+    pub fn drop_descriptor_set_layout(&mut self, layout: vk::DescriptorSetLayout) {
+        self.descriptor_set_layouts.push(layout);
+    }
+
+    pub fn drop_semaphore(&mut self, semaphore: vk::Semaphore) {
+        self.semaphores.push(semaphore);
+    }
+
+    pub fn drop_fence(&mut self, fence: vk::Fence) {
+        self.fences.push(fence);
+    }
+
+    pub fn drop_sampler(&mut self, sampler: vk::Sampler) {
+        self.samplers.push(sampler);
+    }
+
+    pub fn drop_shader_module(&mut self, module: vk::ShaderModule) {
+        self.shader_modules.push(module);
+    }
+
+    pub fn drop_render_pass(&mut self, render_pass: vk::RenderPass) {
+        self.render_passes.push(render_pass);
+    }
+
+    pub fn drop_framebuffer(&mut self, framebuffer: vk::Framebuffer) {
+        self.framebuffers.push(framebuffer);
+    }
+
+    /// Destroyed before buffers, so the backing buffer of the structure may
+    /// be dropped in the same frame.
+    pub fn drop_acceleration_structure(&mut self, value: vk::AccelerationStructureKHR) {
+        self.acceleration_structures.push(value);
+    }
+
+    #[cfg(feature = "video-decode")]
+    pub fn drop_video_session_parameters(&mut self, parameters: vk::VideoSessionParametersKHR) {
+        self.video_session_parameters.push(parameters);
+    }
+
+    /// Destroyed after its parameters and before memory, so the session and
+    /// memory bound to it may be dropped in the same frame.
+    #[cfg(feature = "video-decode")]
+    pub fn drop_video_session(&mut self, session: vk::VideoSessionKHR) {
+        self.video_sessions.push(session);
+    }
+
+    /// Runs the closure after everything else is destroyed and the device
+    /// locks held during cleanup are released.
+    pub fn run_on_cleanup<F: FnOnce() + Send + 'static>(&mut self, f: F) {
+        self.closures.0.push(Box::new(f));
+    }
+
+    pub(crate) fn cleanup(
         &mut self,
         device: &ash::Device,
+        loaders: CleanupLoaders,
         memory_allocator: &mut GpuMemoryAllocator,
         descriptor_allocator: &mut DescriptorAllocator,
-    ) {
+    ) -> Closures {
+        if let Some(loader) = loaders.acceleration_structure {
+            self.acceleration_structures
+                .drain(..)
+                .for_each(|value| unsafe { loader.destroy_acceleration_structure(value, None) });
+        }
+        debug_assert!(
+            self.acceleration_structures.is_empty(),
+            "Acceleration structures exist without the extension"
+        );
+        #[cfg(feature = "video-decode")]
+        if let Some(loader) = loaders.video_queue {
+            let fp = loader.fp();
+            self.video_session_parameters
+                .drain(..)
+                .for_each(|parameters| unsafe {
+                    (fp.destroy_video_session_parameters_khr)(
+                        loader.device(),
+                        parameters,
+                        std::ptr::null(),
+                    )
+                });
+            self.video_sessions.drain(..).for_each(|session| unsafe {
+                (fp.destroy_video_session_khr)(loader.device(), session, std::ptr::null())
+            });
+        }
+        #[cfg(feature = "video-decode")]
+        debug_assert!(
+            self.video_sessions.is_empty() && self.video_session_parameters.is_empty(),
+            "Video sessions exist without the extension"
+        );
+        self.framebuffers.drain(..).for_each(|framebuffer| unsafe {
+            device.destroy_framebuffer(framebuffer, None);
+        });
+        self.image_views.drain(..).for_each(|view| unsafe {
+            device.destroy_image_view(view, None);
+        });
         self.images.drain(..).for_each(|image| unsafe {
             device.destroy_image(image, None);
         });
         self.buffers.drain(..).for_each(|buffer| unsafe {
             device.destroy_buffer(buffer, None);
         });
+        self.pipelines.drain(..).for_each(|pipeline| unsafe {
+            device.destroy_pipeline(pipeline, None);
+        });
+        self.pipeline_layouts.drain(..).for_each(|layout| unsafe {
+            device.destroy_pipeline_layout(layout, None);
+        });
+        self.descriptor_set_layouts
+            .drain(..)
+            .for_each(|layout| unsafe {
+                device.destroy_descriptor_set_layout(layout, None);
+            });
+        self.semaphores.drain(..).for_each(|semaphore| unsafe {
+            device.destroy_semaphore(semaphore, None);
+        });
+        self.fences.drain(..).for_each(|fence| unsafe {
+            device.destroy_fence(fence, None);
+        });
+        self.shader_modules.drain(..).for_each(|module| unsafe {
+            device.destroy_shader_module(module, None);
+        });
+        self.render_passes.drain(..).for_each(|render_pass| unsafe {
+            device.destroy_render_pass(render_pass, None);
+        });
+        self.samplers.drain(..).for_each(|sampler| unsafe {
+            device.destroy_sampler(sampler, None);
+        });
+        self.descriptor_pools.drain(..).for_each(|pool| unsafe {
+            device.destroy_descriptor_pool(pool, None);
+        });
+        self.events.drain(..).for_each(|event| unsafe {
+            device.destroy_event(event, None);
+        });
+        self.query_pools.drain(..).for_each(|pool| unsafe {
+            device.destroy_query_pool(pool, None);
+        });
         self.memory.drain(..).for_each(|memory| unsafe {
             memory_allocator.dealloc(AshMemoryDevice::wrap(device), memory)
         });
@@ -50,5 +256,6 @@ impl DropList {
                 self.descriptors.drain(..),
             )
         };
+        mem::take(&mut self.closures)
     }
 }
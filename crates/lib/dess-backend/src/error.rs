@@ -5,15 +5,99 @@ pub enum BackendError {
     #[error("Failed to load Vulkan")]
     LoadingError,
     #[error("Vulkan error: {0:?}")]
-    VulkanError(#[from] ash::vk::Result),
+    VulkanError(ash::vk::Result),
+    #[error("Wait timed out")]
+    Timeout,
+    #[error("Device lost")]
+    DeviceLost,
     #[error("Can't get display/window handle: {0:?}")]
     RawWindowHandleError(raw_window_handle::HandleError),
     #[error("Failed to find queue family")]
     NoSuitableQueue,
+    #[error("Device has no async compute queue")]
+    NoComputeQueue,
     #[error("Failed to allocate memory: {0:?} {1:?}")]
     MemoryAllocationFailed(gpu_alloc::AllocationError, gpu_alloc::Request),
+    #[error("Out of device memory: {0:?} {1:?}")]
+    OutOfDeviceMemory(gpu_alloc::Request, crate::vulkan::MemoryStats),
+    #[error(
+        "Device memory allocation limit of {limit} is almost reached ({remaining} left), use suballocated buffers"
+    )]
+    TooManyAllocations { limit: u32, remaining: u32 },
+    #[error("Failed to map memory: {0:?}")]
+    MemoryMapFailed(#[from] gpu_alloc::MapError),
     #[error("Failed to allocate descriptors {0:?}")]
     DescriptorAllocationFailed(#[from] gpu_descriptor::AllocationError),
+    #[error("Copy region {offset:?} {extent:?} doesn't match transfer granularity {granularity:?}")]
+    TransferGranularity {
+        offset: ash::vk::Offset3D,
+        extent: ash::vk::Extent3D,
+        granularity: ash::vk::Extent3D,
+    },
+    #[error("Surface doesn't report any formats")]
+    NoSurfaceFormats,
+    #[error("Format {0:?} isn't supported")]
+    UnsupportedFormat(ash::vk::Format),
+    #[error("Image {0:?} isn't supported")]
+    UnsupportedImage(crate::vulkan::ImageDesc),
+    #[error("Unsupported texture: {0}")]
+    UnsupportedTexture(&'static str),
+    #[cfg(feature = "ktx2")]
+    #[error("Failed to parse KTX2 texture: {0}")]
+    Ktx2ParseError(#[from] ktx2::ParseError),
+    #[error("Invalid vertex layout: {0}")]
+    InvalidVertexLayout(String),
+    #[error("Invalid descriptor set layout: {0}")]
+    InvalidDescriptorLayout(String),
+    #[error("Profiler error: {0}")]
+    ProfilerError(String),
+    #[error("Device extension {0:?} isn't enabled")]
+    ExtensionNotEnabled(&'static std::ffi::CStr),
+    #[error("Device feature {0} isn't enabled")]
+    FeatureNotEnabled(&'static str),
+    #[error("Command buffer can't be used as {0:?}")]
+    InvalidCommandBufferUsage(crate::vulkan::CommandBufferUsage),
+    #[error("Sampler LOD bias {bias} exceeds maxSamplerLodBias {max}")]
+    InvalidSamplerLodBias { bias: f32, max: f32 },
+    #[error("Invalid blit: {0}")]
+    InvalidBlit(&'static str),
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Workgroup count {count:?} exceeds maxComputeWorkGroupCount {max:?}")]
+    WorkGroupCountExceeded { count: [u32; 3], max: [u32; 3] },
+    #[error("Incompatible pipeline layout: {0}")]
+    IncompatiblePipelineLayout(String),
+    #[error("Attachment size {size:?} exceeds framebuffer limit {max:?} (width, height, layers)")]
+    ImageSizeExceedsLimit {
+        size: (u32, u32, u32),
+        max: (u32, u32, u32),
+    },
+    #[error("View mask {mask:#b} uses views beyond maxMultiviewViewCount {max_views}")]
+    InvalidViewMask { mask: u32, max_views: u32 },
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Image has zero size")]
+    EmptyImage,
+    #[error("Pixel data of {len} bytes isn't a multiple of {stride} bytes per pixel")]
+    PixelDataStride { len: usize, stride: usize },
+    #[error("Expected {expected} bytes of pixel data, got {actual}")]
+    PixelDataSize { expected: usize, actual: usize },
+    #[error("Suballocation of {size} bytes doesn't fit into {capacity} byte buffer")]
+    SuballocationFailed { size: u64, capacity: u64 },
+    #[error("Frame fences didn't signal in time: {0:?}")]
+    FrameTimeout(Vec<&'static str>),
+    #[error("Frame slot is still held by a DeviceFrameGuard")]
+    FrameInUse,
+}
+
+impl From<ash::vk::Result> for BackendError {
+    fn from(value: ash::vk::Result) -> Self {
+        match value {
+            ash::vk::Result::TIMEOUT => Self::Timeout,
+            ash::vk::Result::ERROR_DEVICE_LOST => Self::DeviceLost,
+            value => Self::VulkanError(value),
+        }
+    }
 }
 
 impl From<ash::LoadingError> for BackendError {
@@ -1,5 +1,8 @@
+pub mod debug_names;
 mod droplist;
 mod error;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 pub mod vulkan;
 pub use ash::vk;
 pub use droplist::*;
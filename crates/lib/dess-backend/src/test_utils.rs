@@ -0,0 +1,89 @@
+//! Headless device for tests. Machines without Vulkan can't run them, so
+//! tests should skip through [`test_device_or_skip`]. Set
+//! `VULKAN_TEST_REQUIRE_DEVICE=1` to fail instead, e.g. in CI with a software
+//! driver installed.
+
+use std::{env, sync::Arc};
+
+use ash::vk;
+use log::warn;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, InstanceBuilder},
+};
+
+/// Creates instance without surface extensions and a device on the first
+/// physical device with a graphics and compute queue. Validation is enabled
+/// with `VULKAN_TEST_DEBUG=1`.
+pub fn create_test_device() -> Result<Arc<Device>, BackendError> {
+    let debug = env::var("VULKAN_TEST_DEBUG").is_ok_and(|value| value == "1");
+    create_device(InstanceBuilder::default().debug(debug))
+}
+
+/// Test device with validation and synchronization validation required,
+/// messages are counted by [`Instance::debug_errors`] and
+/// [`Instance::debug_warnings`]. `None` when Vulkan or the validation layer
+/// isn't available.
+///
+/// [`Instance::debug_errors`]: crate::vulkan::Instance::debug_errors
+/// [`Instance::debug_warnings`]: crate::vulkan::Instance::debug_warnings
+pub fn validated_test_device_or_skip() -> Option<Arc<Device>> {
+    let builder = InstanceBuilder::default()
+        .debug(true)
+        .synchronization_validation(true)
+        .require_debug(true);
+    match create_device(builder) {
+        Err(
+            err @ BackendError::VulkanError(
+                vk::Result::ERROR_LAYER_NOT_PRESENT | vk::Result::ERROR_EXTENSION_NOT_PRESENT,
+            ),
+        ) => skip(format_args!("validation layer isn't available: {err}")),
+        result => skip_unavailable(result),
+    }
+}
+
+fn create_device(builder: InstanceBuilder) -> Result<Arc<Device>, BackendError> {
+    let instance = builder.title("dess-backend tests").build()?;
+    let pdevice = instance
+        .get_physical_devices()?
+        .into_iter()
+        .find(|pdevice| {
+            pdevice.queue_families.iter().any(|queue| {
+                queue
+                    .properties
+                    .queue_flags
+                    .contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+        })
+        .ok_or(BackendError::NoSuitableQueue)?;
+    Device::new(instance, pdevice)
+}
+
+/// Test device, or `None` with a warning when there is no Vulkan loader or
+/// driver. Other errors panic.
+pub fn test_device_or_skip() -> Option<Arc<Device>> {
+    skip_unavailable(create_test_device())
+}
+
+fn skip_unavailable(result: Result<Arc<Device>, BackendError>) -> Option<Arc<Device>> {
+    match result {
+        Ok(device) => Some(device),
+        Err(
+            err @ (BackendError::LoadingError
+            | BackendError::NoSuitableQueue
+            | BackendError::VulkanError(
+                vk::Result::ERROR_INCOMPATIBLE_DRIVER | vk::Result::ERROR_INITIALIZATION_FAILED,
+            )),
+        ) => skip(format_args!("Vulkan isn't available: {err}")),
+        Err(err) => panic!("Failed to create test device: {err}"),
+    }
+}
+
+fn skip(reason: std::fmt::Arguments) -> Option<Arc<Device>> {
+    if env::var("VULKAN_TEST_REQUIRE_DEVICE").is_ok_and(|value| value == "1") {
+        panic!("Test device is required, but {reason}");
+    }
+    warn!("Skipping test, {reason}");
+    None
+}
@@ -0,0 +1,128 @@
+use std::{fmt::Debug, sync::Arc};
+
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{Buffer, BufferDesc, Device, Frame},
+};
+
+pub struct AccelerationStructure {
+    pub raw: vk::AccelerationStructureKHR,
+    pub ty: vk::AccelerationStructureTypeKHR,
+    pub size: u64,
+    /// Buffer allocated for the structure, `None` when storage was provided
+    /// by the caller.
+    pub backing_buffer: Option<Buffer>,
+    device: Arc<Device>,
+}
+
+impl Debug for AccelerationStructure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccelerationStructure")
+            .field("raw", &self.raw)
+            .field("ty", &self.ty)
+            .field("size", &self.size)
+            .field("backing_buffer", &self.backing_buffer)
+            .finish()
+    }
+}
+
+impl AccelerationStructure {
+    pub fn device_address(&self) -> vk::DeviceAddress {
+        let info = vk::AccelerationStructureDeviceAddressInfoKHR::default()
+            .acceleration_structure(self.raw);
+        unsafe {
+            self.device
+                .acceleration_structure_loader()
+                .expect("Acceleration structure exists, so the extension is enabled")
+                .get_acceleration_structure_device_address(&info)
+        }
+    }
+}
+
+impl Device {
+    pub(crate) fn acceleration_structure_loader(
+        &self,
+    ) -> Result<&ash::khr::acceleration_structure::Device, BackendError> {
+        self.acceleration_structure
+            .as_ref()
+            .ok_or(BackendError::ExtensionNotEnabled(
+                ash::khr::acceleration_structure::NAME,
+            ))
+    }
+
+    /// Creates acceleration structure together with its backing buffer.
+    pub fn create_acceleration_structure(
+        self: &Arc<Self>,
+        size: u64,
+        ty: vk::AccelerationStructureTypeKHR,
+    ) -> Result<AccelerationStructure, BackendError> {
+        self.acceleration_structure_loader()?;
+        let buffer = self.create_buffer(BufferDesc::new(
+            size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        ))?;
+        let mut structure =
+            self.create_acceleration_structure_from_buffer(buffer.raw, 0, size, ty)?;
+        structure.backing_buffer = Some(buffer);
+        Ok(structure)
+    }
+
+    /// Creates acceleration structure in caller provided storage. The buffer
+    /// must outlive the structure.
+    pub fn create_acceleration_structure_from_buffer(
+        self: &Arc<Self>,
+        buffer: vk::Buffer,
+        offset: u64,
+        size: u64,
+        ty: vk::AccelerationStructureTypeKHR,
+    ) -> Result<AccelerationStructure, BackendError> {
+        let loader = self.acceleration_structure_loader()?;
+        let info = vk::AccelerationStructureCreateInfoKHR::default()
+            .buffer(buffer)
+            .offset(offset)
+            .size(size)
+            .ty(ty);
+        let raw = unsafe { loader.create_acceleration_structure(&info, None) }?;
+        Ok(AccelerationStructure {
+            raw,
+            ty,
+            size,
+            backing_buffer: None,
+            device: self.clone(),
+        })
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Records all builds with one command, so the driver can schedule them
+    /// together. Every build info needs a matching slice of range infos.
+    pub fn build_acceleration_structures_batch(
+        &self,
+        build_infos: &[vk::AccelerationStructureBuildGeometryInfoKHR],
+        range_infos: &[&[vk::AccelerationStructureBuildRangeInfoKHR]],
+    ) -> Result<(), BackendError> {
+        assert_eq!(
+            build_infos.len(),
+            range_infos.len(),
+            "Every build needs its range infos"
+        );
+        if build_infos.is_empty() {
+            return Ok(());
+        }
+        let loader = self.device.acceleration_structure_loader()?;
+        unsafe {
+            loader.cmd_build_acceleration_structures(self.main_cb().raw(), build_infos, range_infos)
+        };
+        Ok(())
+    }
+}
+
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        self.device
+            .with_drop_list(|drop_list| drop_list.drop_acceleration_structure(self.raw));
+    }
+}
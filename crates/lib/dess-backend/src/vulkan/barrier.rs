@@ -0,0 +1,367 @@
+use ash::vk;
+
+use crate::{BackendError, vulkan::Frame};
+
+/// Layout together with the pipeline stages and accesses an image is used with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageState {
+    pub layout: vk::ImageLayout,
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
+impl ImageState {
+    pub const UNDEFINED: Self = Self::new(
+        vk::ImageLayout::UNDEFINED,
+        vk::PipelineStageFlags2::TOP_OF_PIPE,
+        vk::AccessFlags2::NONE,
+    );
+    pub const TRANSFER_SRC: Self = Self::new(
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        vk::PipelineStageFlags2::TRANSFER,
+        vk::AccessFlags2::TRANSFER_READ,
+    );
+    pub const TRANSFER_DST: Self = Self::new(
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::PipelineStageFlags2::TRANSFER,
+        vk::AccessFlags2::TRANSFER_WRITE,
+    );
+    pub const SHADER_READ: Self = Self::new(
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw()
+                | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw(),
+        ),
+        vk::AccessFlags2::SHADER_SAMPLED_READ,
+    );
+    pub const COLOR_ATTACHMENT: Self = Self::new(
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw(),
+        ),
+    );
+    pub const DEPTH_ATTACHMENT: Self = Self::new(
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+        vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS.as_raw()
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS.as_raw(),
+        ),
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw(),
+        ),
+    );
+    pub const STORAGE: Self = Self::new(
+        vk::ImageLayout::GENERAL,
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::SHADER_STORAGE_READ.as_raw()
+                | vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw(),
+        ),
+    );
+    pub const PRESENT: Self = Self::new(
+        vk::ImageLayout::PRESENT_SRC_KHR,
+        vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+        vk::AccessFlags2::NONE,
+    );
+
+    pub const fn new(
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags2,
+        access: vk::AccessFlags2,
+    ) -> Self {
+        Self {
+            layout,
+            stage,
+            access,
+        }
+    }
+}
+
+/// Pipeline stages and accesses a buffer is used with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferState {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
+impl BufferState {
+    pub const NONE: Self = Self::new(vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE);
+    pub const TRANSFER_SRC: Self = Self::new(
+        vk::PipelineStageFlags2::TRANSFER,
+        vk::AccessFlags2::TRANSFER_READ,
+    );
+    pub const TRANSFER_DST: Self = Self::new(
+        vk::PipelineStageFlags2::TRANSFER,
+        vk::AccessFlags2::TRANSFER_WRITE,
+    );
+    pub const VERTEX: Self = Self::new(
+        vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+        vk::AccessFlags2::VERTEX_ATTRIBUTE_READ,
+    );
+    pub const INDEX: Self = Self::new(
+        vk::PipelineStageFlags2::INDEX_INPUT,
+        vk::AccessFlags2::INDEX_READ,
+    );
+    pub const INDIRECT: Self = Self::new(
+        vk::PipelineStageFlags2::DRAW_INDIRECT,
+        vk::AccessFlags2::INDIRECT_COMMAND_READ,
+    );
+    pub const UNIFORM: Self = Self::new(
+        vk::PipelineStageFlags2::from_raw(
+            vk::PipelineStageFlags2::VERTEX_SHADER.as_raw()
+                | vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw()
+                | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw(),
+        ),
+        vk::AccessFlags2::UNIFORM_READ,
+    );
+    pub const COMPUTE_READ: Self = Self::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::SHADER_STORAGE_READ,
+    );
+    pub const COMPUTE_WRITE: Self = Self::new(
+        vk::PipelineStageFlags2::COMPUTE_SHADER,
+        vk::AccessFlags2::SHADER_STORAGE_WRITE,
+    );
+
+    pub const fn new(stage: vk::PipelineStageFlags2, access: vk::AccessFlags2) -> Self {
+        Self { stage, access }
+    }
+}
+
+pub(crate) fn buffer_barrier(
+    buffer: vk::Buffer,
+    from: BufferState,
+    to: BufferState,
+) -> vk::BufferMemoryBarrier2<'static> {
+    vk::BufferMemoryBarrier2::default()
+        .buffer(buffer)
+        .size(vk::WHOLE_SIZE)
+        .src_stage_mask(from.stage)
+        .src_access_mask(from.access)
+        .dst_stage_mask(to.stage)
+        .dst_access_mask(to.access)
+}
+
+/// Subresource range covering all mips and layers of an image.
+pub fn full_subresource_range(aspect: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange::default()
+        .aspect_mask(aspect)
+        .base_mip_level(0)
+        .level_count(vk::REMAINING_MIP_LEVELS)
+        .base_array_layer(0)
+        .layer_count(vk::REMAINING_ARRAY_LAYERS)
+}
+
+pub(crate) fn image_barrier(
+    image: vk::Image,
+    range: vk::ImageSubresourceRange,
+    from: ImageState,
+    to: ImageState,
+) -> vk::ImageMemoryBarrier2<'static> {
+    vk::ImageMemoryBarrier2::default()
+        .image(image)
+        .subresource_range(range)
+        .old_layout(from.layout)
+        .src_stage_mask(from.stage)
+        .src_access_mask(from.access)
+        .new_layout(to.layout)
+        .dst_stage_mask(to.stage)
+        .dst_access_mask(to.access)
+}
+
+pub(crate) fn cmd_transition_image(
+    device: &ash::Device,
+    cb: vk::CommandBuffer,
+    image: vk::Image,
+    range: vk::ImageSubresourceRange,
+    from: ImageState,
+    to: ImageState,
+) {
+    let barrier = [image_barrier(image, range, from, to)];
+    let info = vk::DependencyInfo::default().image_memory_barriers(&barrier);
+    unsafe { device.cmd_pipeline_barrier2(cb, &info) };
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageTransition {
+    pub image: vk::Image,
+    pub range: vk::ImageSubresourceRange,
+    pub from: ImageState,
+    pub to: ImageState,
+}
+
+impl<'a> Frame<'a> {
+    /// Records all transitions with a single barrier command into the main
+    /// command buffer of the frame.
+    pub fn transition_images_batch(
+        &self,
+        transitions: &[ImageTransition],
+    ) -> Result<(), BackendError> {
+        if transitions.is_empty() {
+            return Ok(());
+        }
+        let barriers = transitions
+            .iter()
+            .map(|transition| {
+                image_barrier(
+                    transition.image,
+                    transition.range,
+                    transition.from,
+                    transition.to,
+                )
+            })
+            .collect::<Vec<_>>();
+        let info = vk::DependencyInfo::default().image_memory_barriers(&barriers);
+        unsafe {
+            self.device
+                .raw
+                .cmd_pipeline_barrier2(self.main_cb().raw(), &info)
+        };
+        Ok(())
+    }
+
+    /// Records transitions as the last commands of the main command buffer
+    /// and ends it like [`Frame::end_main_cb`], e.g. to leave attachments
+    /// ready for sampling in the next frame.
+    pub fn end_with_image_transitions(
+        &self,
+        transitions: &[ImageTransition],
+    ) -> Result<(), BackendError> {
+        self.transition_images_batch(transitions)?;
+        self.end_main_cb()
+    }
+
+    /// Records a layout transition into the main command buffer of the frame.
+    pub fn transition_image_layout(
+        &self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        from: ImageState,
+        to: ImageState,
+    ) {
+        cmd_transition_image(
+            &self.device.raw,
+            self.main_cb().raw(),
+            image,
+            range,
+            from,
+            to,
+        );
+    }
+
+    /// Discards color image contents and prepares it to be a copy destination.
+    pub fn transition_to_transfer_dst(&self, image: vk::Image) {
+        self.transition_image_layout(
+            image,
+            full_subresource_range(vk::ImageAspectFlags::COLOR),
+            ImageState::UNDEFINED,
+            ImageState::TRANSFER_DST,
+        );
+    }
+
+    /// Makes color image filled by transfer commands available to shaders.
+    pub fn transition_to_shader_read(&self, image: vk::Image) {
+        self.transition_image_layout(
+            image,
+            full_subresource_range(vk::ImageAspectFlags::COLOR),
+            ImageState::TRANSFER_DST,
+            ImageState::SHADER_READ,
+        );
+    }
+
+    /// Discards color image contents and prepares it for rendering.
+    pub fn transition_to_color_attachment(&self, image: vk::Image) {
+        self.transition_image_layout(
+            image,
+            full_subresource_range(vk::ImageAspectFlags::COLOR),
+            ImageState::UNDEFINED,
+            ImageState::COLOR_ATTACHMENT,
+        );
+    }
+
+    /// Makes rendered color image available to shaders.
+    pub fn transition_color_attachment_to_shader_read(&self, image: vk::Image) {
+        self.transition_image_layout(
+            image,
+            full_subresource_range(vk::ImageAspectFlags::COLOR),
+            ImageState::COLOR_ATTACHMENT,
+            ImageState::SHADER_READ,
+        );
+    }
+
+    /// Orders execution of `dst_stage` after `src_stage` without making any
+    /// memory visible, for writes that are already ordered like atomics.
+    pub fn execution_barrier(
+        &self,
+        src_stage: vk::PipelineStageFlags2,
+        dst_stage: vk::PipelineStageFlags2,
+    ) {
+        let barrier = [vk::MemoryBarrier2::default()
+            .src_stage_mask(src_stage)
+            .dst_stage_mask(dst_stage)];
+        let info = vk::DependencyInfo::default().memory_barriers(&barrier);
+        unsafe {
+            self.device
+                .raw
+                .cmd_pipeline_barrier2(self.main_cb().raw(), &info)
+        };
+    }
+
+    /// Makes storage buffer writes of compute shaders visible to following
+    /// compute dispatches.
+    pub fn after_compute_write_before_compute_read(&self, buffer: vk::Buffer, size: u64) {
+        let barrier = [buffer_barrier(
+            buffer,
+            BufferState::COMPUTE_WRITE,
+            BufferState::COMPUTE_READ,
+        )
+        .size(size)];
+        let info = vk::DependencyInfo::default().buffer_memory_barriers(&barrier);
+        unsafe {
+            self.device
+                .raw
+                .cmd_pipeline_barrier2(self.main_cb().raw(), &info)
+        };
+    }
+
+    /// Makes storage image written by compute shaders available for sampling
+    /// in fragment shaders.
+    pub fn after_compute_write_before_fragment_read_image(
+        &self,
+        image: vk::Image,
+        subresource: vk::ImageSubresourceRange,
+    ) {
+        self.transition_image_layout(
+            image,
+            subresource,
+            ImageState::new(
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+            ImageState::new(
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                vk::AccessFlags2::SHADER_SAMPLED_READ,
+            ),
+        );
+    }
+
+    /// Makes image filled by transfer commands available for sampling in
+    /// fragment and compute shaders.
+    pub fn after_transfer_before_shader_read(
+        &self,
+        image: vk::Image,
+        subresource: vk::ImageSubresourceRange,
+    ) {
+        self.transition_image_layout(
+            image,
+            subresource,
+            ImageState::TRANSFER_DST,
+            ImageState::SHADER_READ,
+        );
+    }
+}
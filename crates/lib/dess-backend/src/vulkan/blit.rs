@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, atomic::Ordering},
+};
+
+use ash::vk;
+use gpu_descriptor::DescriptorTotalCount;
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError,
+    vulkan::{
+        Device, Frame, GraphicsPipelineBuilder, Image, ImageState, SamplerDesc, format_aspect,
+        full_subresource_range,
+    },
+};
+
+const VERTEX_SHADER: &[u8] = include_bytes!("shaders/blit.vert.spv");
+const FRAGMENT_SHADER: &[u8] = include_bytes!("shaders/blit.frag.spv");
+
+pub(crate) fn spirv_words(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect()
+}
+
+/// Image the blit pass renders into.
+#[derive(Debug, Clone, Copy)]
+pub struct BlitTarget {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    /// State to leave the image in, e.g. [`ImageState::PRESENT`] for swapchain images.
+    pub final_state: ImageState,
+}
+
+/// Draws a sampled image over the whole target with a fullscreen triangle.
+pub struct BlitPass {
+    vertex: vk::ShaderModule,
+    fragment: vk::ShaderModule,
+    fragment_entry: &'static std::ffi::CStr,
+    set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    pipelines: Mutex<HashMap<vk::Format, vk::Pipeline>>,
+    device: Arc<Device>,
+}
+
+impl Debug for BlitPass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlitPass")
+            .field("layout", &self.layout)
+            .field("pipelines", &self.pipelines.lock())
+            .finish()
+    }
+}
+
+impl BlitPass {
+    pub fn new(device: &Arc<Device>) -> Result<Self, BackendError> {
+        let fragment = device.create_shader_module(&spirv_words(FRAGMENT_SHADER))?;
+        Self::with_fragment_shader(device, fragment, c"fs_main")
+    }
+
+    /// Uses custom fragment shader, e.g. for tonemapping. It gets the source
+    /// texture at binding 0 and the sampler at binding 1 of set 0, and UV at
+    /// location 0. The pass takes ownership of the module.
+    pub fn with_fragment_shader(
+        device: &Arc<Device>,
+        fragment: vk::ShaderModule,
+        entry: &'static std::ffi::CStr,
+    ) -> Result<Self, BackendError> {
+        // Releases whatever was created so far through `Drop` if anything
+        // below fails, destroying null handles is a no-op.
+        let mut pass = Self {
+            vertex: vk::ShaderModule::null(),
+            fragment,
+            fragment_entry: entry,
+            set_layout: vk::DescriptorSetLayout::null(),
+            layout: vk::PipelineLayout::null(),
+            pipelines: Mutex::default(),
+            device: device.clone(),
+        };
+        pass.vertex = device.create_shader_module(&spirv_words(VERTEX_SHADER))?;
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
+        let info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+        pass.set_layout = unsafe { device.raw.create_descriptor_set_layout(&info, None) }?;
+        let set_layouts = [pass.set_layout];
+        let info = vk::PipelineLayoutCreateInfo::default().set_layouts(&set_layouts);
+        pass.layout = unsafe { device.raw.create_pipeline_layout(&info, None) }?;
+        Ok(pass)
+    }
+
+    fn pipeline(&self, format: vk::Format) -> Result<vk::Pipeline, BackendError> {
+        let mut pipelines = self.pipelines.lock();
+        if let Some(pipeline) = pipelines.get(&format) {
+            return Ok(*pipeline);
+        }
+        let pipeline = GraphicsPipelineBuilder::new(self.layout)
+            .vertex_shader(self.vertex, c"vs_main")
+            .fragment_shader(self.fragment, self.fragment_entry)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .color_formats(&[format])
+            .build(&self.device, None)?;
+        pipelines.insert(format, pipeline);
+        Ok(pipeline)
+    }
+
+    /// Records blit into the main command buffer of the frame. Source image
+    /// must be in [`ImageState::SHADER_READ`], previous contents of the target
+    /// are discarded. Does nothing for zero-sized targets.
+    pub fn run(
+        &self,
+        frame: &Frame,
+        src_view: vk::ImageView,
+        sampler: SamplerDesc,
+        dst: &BlitTarget,
+    ) -> Result<(), BackendError> {
+        if dst.extent.width == 0 || dst.extent.height == 0 {
+            return Ok(());
+        }
+        let device = &self.device;
+        let pipeline = self.pipeline(dst.format)?;
+        let sampler = device.get_or_create_sampler(sampler)?;
+        let set = device.allocate_descriptor_set(
+            self.set_layout,
+            DescriptorTotalCount {
+                sampled_image: 1,
+                sampler: 1,
+                ..Default::default()
+            },
+            false,
+        )?;
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_view(src_view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let sampler_info = [vk::DescriptorImageInfo::default().sampler(sampler)];
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set.raw())
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set.raw())
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&sampler_info),
+        ];
+        unsafe { device.raw.update_descriptor_sets(&writes, &[]) };
+
+        let cb = frame.main_cb().raw();
+        let range = full_subresource_range(vk::ImageAspectFlags::COLOR);
+        frame.transition_image_layout(
+            dst.image,
+            range,
+            ImageState::UNDEFINED,
+            ImageState::COLOR_ATTACHMENT,
+        );
+        let attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(dst.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+        let area = vk::Rect2D::default().extent(dst.extent);
+        let info = vk::RenderingInfo::default()
+            .render_area(area)
+            .layer_count(1)
+            .color_attachments(&attachments);
+        let viewport = vk::Viewport::default()
+            .width(dst.extent.width as f32)
+            .height(dst.extent.height as f32)
+            .max_depth(1.0);
+        unsafe {
+            device.raw.cmd_begin_rendering(cb, &info);
+            device.raw.cmd_set_viewport(cb, 0, &[viewport]);
+            device.raw.cmd_set_scissor(cb, 0, &[area]);
+            device
+                .raw
+                .cmd_bind_pipeline(cb, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.raw.cmd_bind_descriptor_sets(
+                cb,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[*set.raw()],
+                &[],
+            );
+            device.raw.cmd_draw(cb, 3, 1, 0, 0);
+            device.raw.cmd_end_rendering(cb);
+        }
+        frame.transition_image_layout(
+            dst.image,
+            range,
+            ImageState::COLOR_ATTACHMENT,
+            dst.final_state,
+        );
+        device.with_drop_list(|drop_list| drop_list.drop_descriptor_set(set));
+        Ok(())
+    }
+}
+
+/// Blit region covering whole mip levels of the first layer.
+pub fn mip_blit_region(src: &Image, src_mip: u32, dst: &Image, dst_mip: u32) -> vk::ImageBlit {
+    let offsets = |image: &Image, mip: u32| {
+        let extent = image.desc.mip_extent(mip);
+        [
+            vk::Offset3D::default(),
+            vk::Offset3D {
+                x: extent.width as i32,
+                y: extent.height as i32,
+                z: extent.depth as i32,
+            },
+        ]
+    };
+    let subresource = |image: &Image, mip: u32| {
+        vk::ImageSubresourceLayers::default()
+            .aspect_mask(format_aspect(image.format()))
+            .mip_level(mip)
+            .layer_count(1)
+    };
+    vk::ImageBlit::default()
+        .src_subresource(subresource(src, src_mip))
+        .src_offsets(offsets(src, src_mip))
+        .dst_subresource(subresource(dst, dst_mip))
+        .dst_offsets(offsets(dst, dst_mip))
+}
+
+impl<'a> Frame<'a> {
+    /// Blits regions from `src` in `TRANSFER_SRC_OPTIMAL` layout to `dst` in
+    /// `TRANSFER_DST_OPTIMAL`. Linear filter needs a format with linear
+    /// filtering support, depth and stencil formats only allow nearest.
+    pub fn blit_image(
+        &self,
+        src: &Image,
+        dst: &Image,
+        regions: &[vk::ImageBlit],
+        filter: vk::Filter,
+    ) -> Result<(), BackendError> {
+        let src_features = self.device.format_features(src.format());
+        if !src_features.contains(vk::FormatFeatureFlags::BLIT_SRC) {
+            return Err(BackendError::UnsupportedFormat(src.format()));
+        }
+        if !self
+            .device
+            .format_features(dst.format())
+            .contains(vk::FormatFeatureFlags::BLIT_DST)
+        {
+            return Err(BackendError::UnsupportedFormat(dst.format()));
+        }
+        let src_aspect = format_aspect(src.format());
+        if filter == vk::Filter::LINEAR {
+            if src_aspect != vk::ImageAspectFlags::COLOR {
+                return Err(BackendError::InvalidBlit(
+                    "depth and stencil can't be blitted with linear filter",
+                ));
+            }
+            if !src_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
+                return Err(BackendError::UnsupportedFormat(src.format()));
+            }
+        }
+        let dst_aspect = format_aspect(dst.format());
+        if regions.iter().any(|region| {
+            region.src_subresource.aspect_mask != src_aspect
+                || region.dst_subresource.aspect_mask != dst_aspect
+        }) {
+            return Err(BackendError::InvalidBlit(
+                "region aspect doesn't match image format",
+            ));
+        }
+        unsafe {
+            self.device.raw.cmd_blit_image(
+                self.main_cb().raw(),
+                src.raw,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst.raw,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                regions,
+                filter,
+            )
+        };
+        Ok(())
+    }
+
+    /// Resolves multisampled `src` into single sample `dst` outside of
+    /// rendering, for when attachment resolve isn't an option.
+    pub fn resolve_image(
+        &self,
+        src: vk::Image,
+        src_layout: vk::ImageLayout,
+        dst: vk::Image,
+        dst_layout: vk::ImageLayout,
+        regions: &[vk::ImageResolve],
+    ) {
+        debug_assert!(
+            !self.rendering.load(Ordering::Relaxed),
+            "Images can't be resolved while rendering"
+        );
+        unsafe {
+            self.device.raw.cmd_resolve_image(
+                self.main_cb().raw(),
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                regions,
+            )
+        };
+    }
+
+    /// Blits whole `src` in `src_layout` onto the acquired swapchain image
+    /// and leaves it in `PRESENT_SRC_KHR`, ready to be presented. Previous
+    /// contents of the swapchain image are discarded. `src` must already be
+    /// transitioned to `src_layout` for transfer reads.
+    pub fn blit_to_swapchain(
+        &self,
+        src: vk::Image,
+        src_layout: vk::ImageLayout,
+        src_extent: vk::Extent2D,
+        swapchain_image: vk::Image,
+        dst_extent: vk::Extent2D,
+        filter: vk::Filter,
+    ) {
+        debug_assert!(
+            !self.rendering.load(Ordering::Relaxed),
+            "Images can't be blitted while rendering"
+        );
+        let range = full_subresource_range(vk::ImageAspectFlags::COLOR);
+        self.transition_image_layout(
+            swapchain_image,
+            range,
+            ImageState::UNDEFINED,
+            ImageState::TRANSFER_DST,
+        );
+        let subresource = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+        let corner = |extent: vk::Extent2D| vk::Offset3D {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: 1,
+        };
+        let region = vk::ImageBlit::default()
+            .src_subresource(subresource)
+            .src_offsets([vk::Offset3D::default(), corner(src_extent)])
+            .dst_subresource(subresource)
+            .dst_offsets([vk::Offset3D::default(), corner(dst_extent)]);
+        unsafe {
+            self.device.raw.cmd_blit_image(
+                self.main_cb().raw(),
+                src,
+                src_layout,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                filter,
+            )
+        };
+        self.transition_image_layout(
+            swapchain_image,
+            range,
+            ImageState::TRANSFER_DST,
+            ImageState::PRESENT,
+        );
+    }
+}
+
+impl Drop for BlitPass {
+    fn drop(&mut self) {
+        let (vertex, fragment) = (self.vertex, self.fragment);
+        let (layout, set_layout) = (self.layout, self.set_layout);
+        let pipelines = self
+            .pipelines
+            .get_mut()
+            .drain()
+            .map(|(_, pipeline)| pipeline);
+        self.device.with_drop_list(|drop_list| {
+            pipelines.for_each(|pipeline| drop_list.drop_pipeline(pipeline));
+            drop_list.drop_shader_module(vertex);
+            drop_list.drop_shader_module(fragment);
+            drop_list.drop_pipeline_layout(layout);
+            drop_list.drop_descriptor_set_layout(set_layout);
+        });
+    }
+}
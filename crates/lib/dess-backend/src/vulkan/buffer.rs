@@ -0,0 +1,355 @@
+use std::{fmt::Debug, sync::Arc};
+
+use ash::vk;
+use gpu_alloc_ash::AshMemoryDevice;
+
+use crate::{
+    BackendError, GpuMemory,
+    vulkan::{Device, as_bytes},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BufferDesc {
+    pub size: u64,
+    pub usage: vk::BufferUsageFlags,
+    pub memory: gpu_alloc::UsageFlags,
+    /// Fill the buffer with zeroes right after creation. This costs a blocking
+    /// one-shot submit per buffer, so it's off by default.
+    pub zero_init: bool,
+    /// Residency priority from 0.0 to 1.0, see
+    /// [`Device::allocate_memory_with_priority`].
+    pub priority: Option<f32>,
+}
+
+impl BufferDesc {
+    pub fn new(size: u64, usage: vk::BufferUsageFlags) -> Self {
+        Self {
+            size,
+            usage,
+            memory: gpu_alloc::UsageFlags::FAST_DEVICE_ACCESS,
+            zero_init: false,
+            priority: None,
+        }
+    }
+
+    pub fn memory(mut self, value: gpu_alloc::UsageFlags) -> Self {
+        self.memory = value;
+        self
+    }
+
+    pub fn zero_init(mut self, value: bool) -> Self {
+        self.zero_init = value;
+        self
+    }
+
+    pub fn priority(mut self, value: f32) -> Self {
+        self.priority = Some(value);
+        self
+    }
+}
+
+pub struct Buffer {
+    pub raw: vk::Buffer,
+    pub desc: BufferDesc,
+    memory: Option<GpuMemory>,
+    pub(crate) device: Arc<Device>,
+}
+
+impl Debug for Buffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Buffer")
+            .field("raw", &self.raw)
+            .field("desc", &self.desc)
+            .finish()
+    }
+}
+
+impl Buffer {
+    /// Writes data into host visible buffer. Caller must make sure the GPU is
+    /// not using this region.
+    pub fn write(&mut self, offset: u64, data: &[u8]) -> Result<(), BackendError> {
+        if let Some(memory) = &mut self.memory {
+            unsafe { memory.write_bytes(AshMemoryDevice::wrap(&self.device.raw), offset, data) }?;
+        }
+        Ok(())
+    }
+
+    /// Reads data from host visible buffer, non-coherent memory is
+    /// invalidated first. Caller must make sure the GPU finished writing this
+    /// region.
+    pub fn read(&mut self, offset: u64, data: &mut [u8]) -> Result<(), BackendError> {
+        if let Some(memory) = &mut self.memory {
+            unsafe { memory.read_bytes(AshMemoryDevice::wrap(&self.device.raw), offset, data) }?;
+        }
+        Ok(())
+    }
+
+    /// Writes value at the start of a host visible buffer, see
+    /// [`Device::create_uniform_buffer`].
+    pub fn write_uniform<T: Copy>(&mut self, value: &T) -> Result<(), BackendError> {
+        self.write(0, as_bytes(std::slice::from_ref(value)))
+    }
+
+    /// Buffer must be created with `SHADER_DEVICE_ADDRESS` usage.
+    pub fn device_address(&self) -> vk::DeviceAddress {
+        self.device.buffer_device_address(self.raw)
+    }
+}
+
+impl Device {
+    pub fn create_buffer(self: &Arc<Self>, desc: BufferDesc) -> Result<Buffer, BackendError> {
+        if desc.size == 0 {
+            return Err(BackendError::InvalidArgument(
+                "Buffer size must not be zero".into(),
+            ));
+        }
+        let mut usage = desc.usage;
+        if desc.zero_init {
+            usage |= vk::BufferUsageFlags::TRANSFER_DST;
+        }
+        let info = vk::BufferCreateInfo::default()
+            .size(desc.size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let raw = unsafe { self.raw.create_buffer(&info, None) }?;
+        let requirements = unsafe { self.raw.get_buffer_memory_requirements(raw) };
+        let mut memory_usage = desc.memory;
+        if usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+            memory_usage |= gpu_alloc::UsageFlags::DEVICE_ADDRESS;
+        }
+        let request = gpu_alloc::Request {
+            size: requirements.size,
+            align_mask: requirements.alignment - 1,
+            usage: memory_usage,
+            memory_types: requirements.memory_type_bits,
+        };
+        let memory = match self.allocate_memory_with_priority(request, desc.priority) {
+            Ok(memory) => memory,
+            Err(err) => {
+                unsafe { self.raw.destroy_buffer(raw, None) };
+                return Err(err);
+            }
+        };
+        let bind_result = unsafe {
+            self.raw
+                .bind_buffer_memory(raw, *memory.memory(), memory.offset())
+        };
+        let buffer = Buffer {
+            raw,
+            desc,
+            memory: Some(memory),
+            device: self.clone(),
+        };
+        bind_result?;
+        if desc.zero_init {
+            self.immediate_submit(|device, cb| unsafe {
+                device.cmd_fill_buffer(cb, raw, 0, vk::WHOLE_SIZE, 0);
+                let barrier = [vk::MemoryBarrier2::default()
+                    .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .dst_access_mask(
+                        vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+                    )];
+                let info = vk::DependencyInfo::default().memory_barriers(&barrier);
+                device.cmd_pipeline_barrier2(cb, &info);
+            })?;
+        }
+        Ok(buffer)
+    }
+
+    /// Creates device local buffer that shaders write to and access by
+    /// address, `extra_usage` is added to
+    /// `STORAGE_BUFFER | SHADER_DEVICE_ADDRESS | TRANSFER_DST`.
+    pub fn create_storage_buffer(
+        self: &Arc<Self>,
+        size: u64,
+        extra_usage: vk::BufferUsageFlags,
+    ) -> Result<Buffer, BackendError> {
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+            | vk::BufferUsageFlags::TRANSFER_DST
+            | extra_usage;
+        self.create_buffer(BufferDesc::new(size, usage))
+    }
+
+    /// Creates host visible uniform buffer for `T`, padded to
+    /// `minUniformBufferOffsetAlignment`. Device local memory is preferred
+    /// when it's host visible, e.g. on integrated GPUs or with resizable BAR.
+    pub fn create_uniform_buffer<T: Copy>(
+        self: &Arc<Self>,
+        value: Option<&T>,
+    ) -> Result<Buffer, BackendError> {
+        let alignment = self
+            .pdevice
+            .properties
+            .limits
+            .min_uniform_buffer_offset_alignment;
+        let size = (size_of::<T>() as u64).next_multiple_of(alignment.max(1));
+        let desc = BufferDesc::new(size, vk::BufferUsageFlags::UNIFORM_BUFFER).memory(
+            gpu_alloc::UsageFlags::HOST_ACCESS
+                | gpu_alloc::UsageFlags::UPLOAD
+                | gpu_alloc::UsageFlags::FAST_DEVICE_ACCESS,
+        );
+        let mut buffer = self.create_buffer(desc)?;
+        if let Some(value) = value {
+            buffer.write_uniform(value)?;
+        }
+        Ok(buffer)
+    }
+
+    pub fn buffer_device_address(&self, buffer: vk::Buffer) -> vk::DeviceAddress {
+        let info = vk::BufferDeviceAddressInfo::default().buffer(buffer);
+        unsafe { self.raw.get_buffer_device_address(&info) }
+    }
+}
+
+/// Range of a shared buffer, e.g. vertices of one mesh.
+#[derive(Debug, Clone)]
+pub struct BufferSlice {
+    pub buffer: Arc<Buffer>,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl BufferSlice {
+    pub fn new(buffer: Arc<Buffer>, offset: u64, size: u64) -> Self {
+        Self {
+            buffer,
+            offset,
+            size,
+        }
+    }
+
+    pub fn whole(buffer: Arc<Buffer>) -> Self {
+        let size = buffer.desc.size;
+        Self::new(buffer, 0, size)
+    }
+}
+
+impl Device {
+    /// Creates device local buffer and fills it through a staging buffer.
+    /// Blocks until the upload is finished, meant for small static data like
+    /// index buffers of built-in meshes.
+    pub fn create_buffer_with_data(
+        self: &Arc<Self>,
+        usage: vk::BufferUsageFlags,
+        data: &[u8],
+    ) -> Result<Buffer, BackendError> {
+        let buffer = self.create_buffer(BufferDesc::new(
+            data.len() as u64,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+        ))?;
+        self.upload_with_staging(data, |device, cb, staging| unsafe {
+            let region = vk::BufferCopy::default().size(data.len() as u64);
+            device.cmd_copy_buffer(cb, staging, buffer.raw, &[region]);
+            let barrier = [vk::MemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ)];
+            let info = vk::DependencyInfo::default().memory_barriers(&barrier);
+            device.cmd_pipeline_barrier2(cb, &info);
+        })?;
+        Ok(buffer)
+    }
+
+    /// Creates a buffer holding `data`, see [`Device::create_buffer_with_data`].
+    pub fn create_buffer_from_data(
+        self: &Arc<Self>,
+        data: &[u8],
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Buffer, BackendError> {
+        self.create_buffer_with_data(usage, data)
+    }
+}
+
+impl Device {
+    /// Copies data into a temporary host visible buffer and records commands
+    /// that read from it in a one-shot submit.
+    pub(crate) fn upload_with_staging<F: FnOnce(&ash::Device, vk::CommandBuffer, vk::Buffer)>(
+        self: &Arc<Self>,
+        data: &[u8],
+        cb: F,
+    ) -> Result<(), BackendError> {
+        let mut staging = self.create_buffer(
+            BufferDesc::new(data.len() as u64, vk::BufferUsageFlags::TRANSFER_SRC).memory(
+                gpu_alloc::UsageFlags::HOST_ACCESS
+                    | gpu_alloc::UsageFlags::UPLOAD
+                    | gpu_alloc::UsageFlags::TRANSIENT,
+            ),
+        )?;
+        staging.write(0, data)?;
+        self.immediate_submit(|device, command_buffer| cb(device, command_buffer, staging.raw))
+    }
+
+    /// Records commands that write `size` bytes into a temporary host visible
+    /// buffer in a one-shot submit and returns its contents.
+    pub(crate) fn readback_with_staging<F: FnOnce(&ash::Device, vk::CommandBuffer, vk::Buffer)>(
+        self: &Arc<Self>,
+        size: u64,
+        cb: F,
+    ) -> Result<Vec<u8>, BackendError> {
+        let mut staging = self.create_buffer(
+            BufferDesc::new(size, vk::BufferUsageFlags::TRANSFER_DST).memory(
+                gpu_alloc::UsageFlags::HOST_ACCESS
+                    | gpu_alloc::UsageFlags::DOWNLOAD
+                    | gpu_alloc::UsageFlags::TRANSIENT,
+            ),
+        )?;
+        self.immediate_submit(|device, command_buffer| {
+            cb(device, command_buffer, staging.raw);
+            let barrier = [vk::MemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                .dst_access_mask(vk::AccessFlags2::HOST_READ)];
+            let info = vk::DependencyInfo::default().memory_barriers(&barrier);
+            unsafe { device.cmd_pipeline_barrier2(command_buffer, &info) };
+        })?;
+        let mut data = vec![0; size as usize];
+        staging.read(0, &mut data)?;
+        Ok(data)
+    }
+
+    /// Copies buffer region to the CPU, waiting for the copy to finish. Any
+    /// GPU work writing the region must be submitted before. `src` must be
+    /// created with `TRANSFER_SRC` usage.
+    pub fn readback_buffer(
+        self: &Arc<Self>,
+        src: &Buffer,
+        offset: u64,
+        size: u64,
+    ) -> Result<Vec<u8>, BackendError> {
+        if offset
+            .checked_add(size)
+            .is_none_or(|end| end > src.desc.size)
+        {
+            return Err(BackendError::InvalidArgument(format!(
+                "Readback of {size} bytes at {offset} is out of buffer bounds ({})",
+                src.desc.size
+            )));
+        }
+        self.readback_with_staging(size, |device, cb, staging| unsafe {
+            let barrier = [vk::MemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+                .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)];
+            let info = vk::DependencyInfo::default().memory_barriers(&barrier);
+            device.cmd_pipeline_barrier2(cb, &info);
+            let region = vk::BufferCopy::default().src_offset(offset).size(size);
+            device.cmd_copy_buffer(cb, src.raw, staging, &[region]);
+        })
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.device
+            .with_drop_list(|drop_list| drop_list.drop_buffer(self.raw));
+        if let Some(memory) = self.memory.take() {
+            self.device.free_memory(memory);
+        }
+    }
+}
@@ -0,0 +1,607 @@
+use std::{fmt::Debug, slice, sync::Arc};
+
+use ash::vk;
+use gpu_descriptor::{
+    CreatePoolError, DescriptorDevice, DescriptorPoolCreateFlags, DescriptorTotalCount,
+    DeviceAllocationError,
+};
+use gpu_descriptor_ash::AshDescriptorDevice;
+
+use crate::{BackendError, DescriptorSet, vulkan::Device};
+
+#[derive(Debug, Clone, Default)]
+pub struct DescriptorSetLayoutBuilder {
+    bindings: Vec<vk::DescriptorSetLayoutBinding<'static>>,
+    flags: Vec<vk::DescriptorBindingFlags>,
+}
+
+impl DescriptorSetLayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn binding(
+        self,
+        binding: u32,
+        ty: vk::DescriptorType,
+        count: u32,
+        stages: vk::ShaderStageFlags,
+    ) -> Self {
+        self.binding_with_flags(
+            binding,
+            ty,
+            count,
+            stages,
+            vk::DescriptorBindingFlags::empty(),
+        )
+    }
+
+    /// Adds binding with descriptor indexing flags. `PARTIALLY_BOUND` allows
+    /// leaving descriptors unwritten, with `VARIABLE_DESCRIPTOR_COUNT` the
+    /// count is an upper bound and the actual size is set at allocation.
+    pub fn binding_with_flags(
+        mut self,
+        binding: u32,
+        ty: vk::DescriptorType,
+        count: u32,
+        stages: vk::ShaderStageFlags,
+        flags: vk::DescriptorBindingFlags,
+    ) -> Self {
+        self.bindings.push(
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(count)
+                .stage_flags(stages),
+        );
+        self.flags.push(flags);
+        self
+    }
+
+    /// Descriptor counts of one set with this layout, to pass to
+    /// [`Device::allocate_descriptors`]. Variable count bindings count with
+    /// their maximum.
+    pub fn descriptor_count(&self) -> DescriptorTotalCount {
+        let mut counts = DescriptorTotalCount::default();
+        for binding in &self.bindings {
+            add_descriptors(
+                &mut counts,
+                binding.descriptor_type,
+                binding.descriptor_count,
+            );
+        }
+        counts
+    }
+
+    fn validate(&self) -> Result<(), BackendError> {
+        let max_binding = self
+            .bindings
+            .iter()
+            .map(|binding| binding.binding)
+            .max()
+            .unwrap_or_default();
+        for (index, binding) in self.bindings.iter().enumerate() {
+            if self.bindings[..index]
+                .iter()
+                .any(|other| other.binding == binding.binding)
+            {
+                return Err(BackendError::InvalidDescriptorLayout(format!(
+                    "binding {} is declared twice",
+                    binding.binding
+                )));
+            }
+            if self.flags[index].contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)
+                && binding.binding != max_binding
+            {
+                return Err(BackendError::InvalidDescriptorLayout(format!(
+                    "binding {} has variable count but isn't the last one",
+                    binding.binding
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build(&self, device: &Arc<Device>) -> Result<DescriptorSetLayout, BackendError> {
+        self.validate()?;
+        let update_after_bind = self
+            .flags
+            .iter()
+            .any(|flags| flags.contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND));
+        let mut binding_flags =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&self.flags);
+        let mut info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(&self.bindings)
+            .push_next(&mut binding_flags);
+        if update_after_bind {
+            info = info.flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL);
+        }
+        let raw = unsafe { device.raw.create_descriptor_set_layout(&info, None) }?;
+        let variable_binding = self
+            .bindings
+            .iter()
+            .zip(&self.flags)
+            .find(|(_, flags)| {
+                flags.contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)
+            })
+            .map(|(binding, _)| *binding);
+        Ok(DescriptorSetLayout {
+            raw,
+            bindings: self.bindings.clone(),
+            variable_binding,
+            update_after_bind,
+            device: device.clone(),
+        })
+    }
+}
+
+pub struct DescriptorSetLayout {
+    pub raw: vk::DescriptorSetLayout,
+    bindings: Vec<vk::DescriptorSetLayoutBinding<'static>>,
+    variable_binding: Option<vk::DescriptorSetLayoutBinding<'static>>,
+    update_after_bind: bool,
+    device: Arc<Device>,
+}
+
+impl Debug for DescriptorSetLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DescriptorSetLayout")
+            .field("raw", &self.raw)
+            .field("bindings", &self.bindings)
+            .finish()
+    }
+}
+
+fn add_descriptors(counts: &mut DescriptorTotalCount, ty: vk::DescriptorType, count: u32) {
+    let total = match ty {
+        vk::DescriptorType::SAMPLER => &mut counts.sampler,
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER => &mut counts.combined_image_sampler,
+        vk::DescriptorType::SAMPLED_IMAGE => &mut counts.sampled_image,
+        vk::DescriptorType::STORAGE_IMAGE => &mut counts.storage_image,
+        vk::DescriptorType::UNIFORM_TEXEL_BUFFER => &mut counts.uniform_texel_buffer,
+        vk::DescriptorType::STORAGE_TEXEL_BUFFER => &mut counts.storage_texel_buffer,
+        vk::DescriptorType::UNIFORM_BUFFER => &mut counts.uniform_buffer,
+        vk::DescriptorType::STORAGE_BUFFER => &mut counts.storage_buffer,
+        vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC => &mut counts.uniform_buffer_dynamic,
+        vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => &mut counts.storage_buffer_dynamic,
+        vk::DescriptorType::INPUT_ATTACHMENT => &mut counts.input_attachment,
+        vk::DescriptorType::ACCELERATION_STRUCTURE_KHR => &mut counts.acceleration_structure,
+        _ => return,
+    };
+    *total += count;
+}
+
+fn pool_sizes(counts: &DescriptorTotalCount) -> Vec<vk::DescriptorPoolSize> {
+    [
+        (vk::DescriptorType::SAMPLER, counts.sampler),
+        (
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            counts.combined_image_sampler,
+        ),
+        (vk::DescriptorType::SAMPLED_IMAGE, counts.sampled_image),
+        (vk::DescriptorType::STORAGE_IMAGE, counts.storage_image),
+        (
+            vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            counts.uniform_texel_buffer,
+        ),
+        (
+            vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            counts.storage_texel_buffer,
+        ),
+        (vk::DescriptorType::UNIFORM_BUFFER, counts.uniform_buffer),
+        (vk::DescriptorType::STORAGE_BUFFER, counts.storage_buffer),
+        (
+            vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+            counts.uniform_buffer_dynamic,
+        ),
+        (
+            vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            counts.storage_buffer_dynamic,
+        ),
+        (
+            vk::DescriptorType::INPUT_ATTACHMENT,
+            counts.input_attachment,
+        ),
+        (
+            vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            counts.acceleration_structure,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, count)| *count > 0)
+    .map(|(ty, count)| {
+        vk::DescriptorPoolSize::default()
+            .ty(ty)
+            .descriptor_count(count)
+    })
+    .collect()
+}
+
+impl DescriptorSetLayout {
+    /// Descriptor counts of a set, the variable binding counts as
+    /// `variable_count` descriptors.
+    fn descriptor_count(&self, variable_count: Option<u32>) -> DescriptorTotalCount {
+        let mut counts = DescriptorTotalCount::default();
+        for binding in &self.bindings {
+            let count = match (variable_count, self.variable_binding) {
+                (Some(count), Some(variable)) if variable.binding == binding.binding => count,
+                _ => binding.descriptor_count,
+            };
+            add_descriptors(&mut counts, binding.descriptor_type, count);
+        }
+        counts
+    }
+
+    /// Allocates sets from the shared descriptor allocator. Variable count
+    /// bindings get their maximum count.
+    pub fn allocate(&self, count: u32) -> Result<Vec<DescriptorSet>, BackendError> {
+        match self.variable_binding {
+            // Without a variable count the binding gets no descriptors at all.
+            Some(variable) => self.device.allocate_descriptors_variable_count(
+                self.raw,
+                self.descriptor_count(None),
+                count,
+                variable.descriptor_count,
+                self.update_after_bind,
+            ),
+            None => self.device.allocate_descriptors(
+                self.raw,
+                self.descriptor_count(None),
+                count,
+                self.update_after_bind,
+            ),
+        }
+    }
+
+    /// Allocates a set with `variable_count` descriptors in the variable count
+    /// binding. Such sets are usually large and long-lived, so each one gets
+    /// its own pool.
+    pub fn allocate_variable(
+        &self,
+        variable_count: u32,
+    ) -> Result<VariableDescriptorSet, BackendError> {
+        let sizes = pool_sizes(&self.descriptor_count(Some(variable_count)));
+        let mut pool_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(1)
+            .pool_sizes(&sizes);
+        if self.update_after_bind {
+            pool_info = pool_info.flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND);
+        }
+        let pool = unsafe { self.device.raw.create_descriptor_pool(&pool_info, None) }?;
+        let counts = [variable_count];
+        let mut variable_info = vk::DescriptorSetVariableDescriptorCountAllocateInfo::default()
+            .descriptor_counts(&counts);
+        let layouts = [self.raw];
+        let mut info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        if self.variable_binding.is_some() {
+            info = info.push_next(&mut variable_info);
+        }
+        match unsafe { self.device.raw.allocate_descriptor_sets(&info) } {
+            Ok(sets) => Ok(VariableDescriptorSet {
+                raw: sets[0],
+                pool,
+                count: variable_count,
+                device: self.device.clone(),
+            }),
+            Err(err) => {
+                unsafe { self.device.raw.destroy_descriptor_pool(pool, None) };
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        self.device
+            .with_drop_list(|drop_list| drop_list.drop_descriptor_set_layout(self.raw));
+    }
+}
+
+impl Device {
+    /// Creates descriptor pool with exact sizes, bypassing the shared
+    /// allocator. Caller owns the pool and should release it through the drop
+    /// list.
+    pub fn create_descriptor_pool_custom(
+        &self,
+        pool_sizes: &[vk::DescriptorPoolSize],
+        max_sets: u32,
+        flags: vk::DescriptorPoolCreateFlags,
+    ) -> Result<vk::DescriptorPool, BackendError> {
+        let info = vk::DescriptorPoolCreateInfo::default()
+            .pool_sizes(pool_sizes)
+            .max_sets(max_sets)
+            .flags(flags);
+        Ok(unsafe { self.raw.create_descriptor_pool(&info, None) }?)
+    }
+
+    /// Allocates a single set from a pool created with
+    /// [`Device::create_descriptor_pool_custom`].
+    pub fn allocate_from_pool(
+        &self,
+        pool: vk::DescriptorPool,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, BackendError> {
+        let layouts = [layout];
+        let info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+        Ok(unsafe { self.raw.allocate_descriptor_sets(&info) }?[0])
+    }
+
+    /// Creates layout with an input attachment binding per attachment, binding
+    /// `i` matches `input_attachment_index = i` in shaders.
+    pub fn create_input_attachment_layout(
+        self: &Arc<Self>,
+        attachment_count: u32,
+        stages: vk::ShaderStageFlags,
+    ) -> Result<DescriptorSetLayout, BackendError> {
+        (0..attachment_count)
+            .fold(DescriptorSetLayoutBuilder::new(), |builder, binding| {
+                builder.binding(binding, vk::DescriptorType::INPUT_ATTACHMENT, 1, stages)
+            })
+            .build(self)
+    }
+
+    /// Writes views into a set with [`Device::create_input_attachment_layout`],
+    /// starting from binding 0.
+    pub fn write_input_attachment_descriptors(
+        &self,
+        set: vk::DescriptorSet,
+        images: &[(vk::ImageView, vk::ImageLayout)],
+    ) {
+        for (binding, (view, layout)) in (0..).zip(images) {
+            DescriptorWriter::new(set, binding, 0)
+                .input_attachment(*view, *layout)
+                .write(self);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DescriptorInfo {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+/// Collects descriptors for a binding of a set, every added descriptor goes
+/// to the next array element.
+#[derive(Debug, Clone)]
+pub struct DescriptorWriter {
+    set: vk::DescriptorSet,
+    binding: u32,
+    array_element: u32,
+    descriptors: Vec<(vk::DescriptorType, DescriptorInfo)>,
+}
+
+impl DescriptorWriter {
+    pub fn new(set: vk::DescriptorSet, binding: u32, array_element: u32) -> Self {
+        Self {
+            set,
+            binding,
+            array_element,
+            descriptors: Vec::new(),
+        }
+    }
+
+    fn buffer(
+        mut self,
+        ty: vk::DescriptorType,
+        buffer: vk::Buffer,
+        offset: u64,
+        range: u64,
+    ) -> Self {
+        let info = vk::DescriptorBufferInfo::default()
+            .buffer(buffer)
+            .offset(offset)
+            .range(range);
+        self.descriptors.push((ty, DescriptorInfo::Buffer(info)));
+        self
+    }
+
+    fn image(mut self, ty: vk::DescriptorType, info: vk::DescriptorImageInfo) -> Self {
+        self.descriptors.push((ty, DescriptorInfo::Image(info)));
+        self
+    }
+
+    pub fn uniform_buffer(self, buffer: vk::Buffer, offset: u64, range: u64) -> Self {
+        self.buffer(vk::DescriptorType::UNIFORM_BUFFER, buffer, offset, range)
+    }
+
+    pub fn storage_buffer(self, buffer: vk::Buffer, offset: u64, range: u64) -> Self {
+        self.buffer(vk::DescriptorType::STORAGE_BUFFER, buffer, offset, range)
+    }
+
+    pub fn sampled_image(self, view: vk::ImageView, layout: vk::ImageLayout) -> Self {
+        let info = vk::DescriptorImageInfo::default()
+            .image_view(view)
+            .image_layout(layout);
+        self.image(vk::DescriptorType::SAMPLED_IMAGE, info)
+    }
+
+    pub fn combined_image_sampler(
+        self,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        let info = vk::DescriptorImageInfo::default()
+            .image_view(view)
+            .sampler(sampler)
+            .image_layout(layout);
+        self.image(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, info)
+    }
+
+    pub fn storage_image(self, view: vk::ImageView, layout: vk::ImageLayout) -> Self {
+        let info = vk::DescriptorImageInfo::default()
+            .image_view(view)
+            .image_layout(layout);
+        self.image(vk::DescriptorType::STORAGE_IMAGE, info)
+    }
+
+    pub fn input_attachment(self, view: vk::ImageView, layout: vk::ImageLayout) -> Self {
+        let info = vk::DescriptorImageInfo::default()
+            .image_view(view)
+            .image_layout(layout);
+        self.image(vk::DescriptorType::INPUT_ATTACHMENT, info)
+    }
+
+    pub fn write(&self, device: &Device) {
+        let writes = self
+            .descriptors
+            .iter()
+            .zip(self.array_element..)
+            .map(|((ty, info), array_element)| {
+                let write = vk::WriteDescriptorSet::default()
+                    .dst_set(self.set)
+                    .dst_binding(self.binding)
+                    .dst_array_element(array_element)
+                    .descriptor_type(*ty);
+                match info {
+                    DescriptorInfo::Buffer(info) => write.buffer_info(slice::from_ref(info)),
+                    DescriptorInfo::Image(info) => write.image_info(slice::from_ref(info)),
+                }
+            })
+            .collect::<Vec<_>>();
+        unsafe { device.raw.update_descriptor_sets(&writes, &[]) };
+    }
+}
+
+/// Descriptor device for the shared allocator which sets the variable
+/// descriptor count of every allocated set.
+pub(crate) struct VariableCountDescriptorDevice<'a> {
+    pub raw: &'a ash::Device,
+    pub variable_count: u32,
+}
+
+impl DescriptorDevice<vk::DescriptorSetLayout, vk::DescriptorPool, vk::DescriptorSet>
+    for VariableCountDescriptorDevice<'_>
+{
+    unsafe fn create_descriptor_pool(
+        &self,
+        descriptor_count: &DescriptorTotalCount,
+        max_sets: u32,
+        flags: DescriptorPoolCreateFlags,
+    ) -> Result<vk::DescriptorPool, CreatePoolError> {
+        unsafe {
+            AshDescriptorDevice::wrap(self.raw).create_descriptor_pool(
+                descriptor_count,
+                max_sets,
+                flags,
+            )
+        }
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, pool: vk::DescriptorPool) {
+        unsafe { AshDescriptorDevice::wrap(self.raw).destroy_descriptor_pool(pool) }
+    }
+
+    unsafe fn alloc_descriptor_sets<'a>(
+        &self,
+        pool: &mut vk::DescriptorPool,
+        layouts: impl ExactSizeIterator<Item = &'a vk::DescriptorSetLayout>,
+        sets: &mut impl Extend<vk::DescriptorSet>,
+    ) -> Result<(), DeviceAllocationError> {
+        let layouts = layouts.copied().collect::<Vec<_>>();
+        let counts = vec![self.variable_count; layouts.len()];
+        let mut variable_info = vk::DescriptorSetVariableDescriptorCountAllocateInfo::default()
+            .descriptor_counts(&counts);
+        let info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(*pool)
+            .set_layouts(&layouts)
+            .push_next(&mut variable_info);
+        match unsafe { self.raw.allocate_descriptor_sets(&info) } {
+            Ok(allocated) => {
+                sets.extend(allocated);
+                Ok(())
+            }
+            Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY) => {
+                Err(DeviceAllocationError::OutOfDeviceMemory)
+            }
+            Err(vk::Result::ERROR_FRAGMENTED_POOL) => Err(DeviceAllocationError::FragmentedPool),
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) => {
+                Err(DeviceAllocationError::OutOfPoolMemory)
+            }
+            Err(_) => Err(DeviceAllocationError::OutOfHostMemory),
+        }
+    }
+
+    unsafe fn dealloc_descriptor_sets(
+        &self,
+        pool: &mut vk::DescriptorPool,
+        sets: impl Iterator<Item = vk::DescriptorSet>,
+    ) {
+        unsafe { AshDescriptorDevice::wrap(self.raw).dealloc_descriptor_sets(pool, sets) }
+    }
+}
+
+pub struct VariableDescriptorSet {
+    pub raw: vk::DescriptorSet,
+    pub count: u32,
+    pool: vk::DescriptorPool,
+    device: Arc<Device>,
+}
+
+impl Debug for VariableDescriptorSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VariableDescriptorSet")
+            .field("raw", &self.raw)
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+impl Drop for VariableDescriptorSet {
+    fn drop(&mut self) {
+        self.device
+            .with_drop_list(|drop_list| drop_list.drop_descriptor_pool(self.pool));
+    }
+}
+
+/// Descriptor set for each frame in flight. Sets pointing at per-frame data
+/// are rewritten every frame, writing into the set of the frame being
+/// recorded never touches a set the GPU still reads.
+pub struct FrameDescriptorSets {
+    sets: Vec<DescriptorSet>,
+    device: Arc<Device>,
+}
+
+impl Debug for FrameDescriptorSets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameDescriptorSets")
+            .field("sets", &self.sets)
+            .finish()
+    }
+}
+
+impl FrameDescriptorSets {
+    pub fn new(layout: &DescriptorSetLayout) -> Result<Self, BackendError> {
+        Ok(Self {
+            sets: layout.allocate(2)?,
+            device: layout.device.clone(),
+        })
+    }
+
+    /// Set of the current frame, valid to bind and write while the frame is
+    /// recorded.
+    pub fn current(&self) -> vk::DescriptorSet {
+        *self.sets[(self.device.frame_index() % 2) as usize].raw()
+    }
+
+    /// Starts writing into the set of the current frame.
+    pub fn writer(&self, binding: u32, array_element: u32) -> DescriptorWriter {
+        DescriptorWriter::new(self.current(), binding, array_element)
+    }
+}
+
+impl Drop for FrameDescriptorSets {
+    fn drop(&mut self) {
+        self.device.with_drop_list(|drop_list| {
+            self.sets
+                .drain(..)
+                .for_each(|set| drop_list.drop_descriptor_set(set))
+        });
+    }
+}
@@ -1,24 +1,57 @@
-use std::{collections::HashMap, fmt::Debug, mem, sync::Arc};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    fmt::{self, Debug, Display, Write},
+    mem::{self, ManuallyDrop},
+    ptr::NonNull,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 
 use ash::vk;
 use gpu_alloc_ash::AshMemoryDevice;
 use gpu_descriptor::{DescriptorSetLayoutCreateFlags, DescriptorTotalCount};
 use gpu_descriptor_ash::AshDescriptorDevice;
-use log::info;
+use log::{error, info, warn};
 use parking_lot::Mutex;
 
+#[cfg(feature = "video-decode")]
+use crate::vulkan::video::VideoDecode;
 use crate::{
     BackendError, DescriptorAllocator, DescriptorSet, GpuMemory, GpuMemoryAllocator,
-    droplist::DropList,
-    vulkan::{Instance, PhysicalDevice},
+    droplist::{CleanupLoaders, Closures, DropList},
+    vulkan::{
+        BackendKind, DebugLabelProfiler, Fence, Instance, NoopProfiler, PhysicalDevice, Profiler,
+        SamplerLod, Semaphore, descriptor::VariableCountDescriptorDevice,
+    },
 };
 
 #[derive(Debug, Clone, Copy)]
-struct Queue {
-    raw: vk::Queue,
+pub(crate) struct Queue {
+    pub raw: vk::Queue,
     pub queue_family_index: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueKind {
+    Main,
+    Transfer,
+    /// Async compute, a compute family without graphics.
+    Compute,
+    Video,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueueInfo {
+    pub raw: vk::Queue,
+    pub family_index: u32,
+    pub kind: QueueKind,
+    pub family_properties: vk::QueueFamilyProperties,
+}
+
 impl Queue {
     fn new(raw: vk::Queue, queue_family_index: u32) -> Self {
         Self {
@@ -30,14 +63,51 @@ impl Queue {
 
 pub struct Device {
     pub raw: ash::Device,
-    pdevice: PhysicalDevice,
-    instance: Arc<Instance>,
-    main_queue: Queue,
+    pub(crate) debug_utils: Option<ash::ext::debug_utils::Device>,
+    pub(crate) debug_marker: Option<ash::ext::debug_marker::Device>,
+    pub(crate) acceleration_structure: Option<ash::khr::acceleration_structure::Device>,
+    pub(crate) mesh_shader: Option<ash::ext::mesh_shader::Device>,
+    pageable_memory: Option<ash::ext::pageable_device_local_memory::Device>,
+    pub(crate) local_read: Option<ash::khr::dynamic_rendering_local_read::Device>,
+    #[cfg(target_os = "linux")]
+    pub(crate) external_semaphore: Option<ash::khr::external_semaphore_fd::Device>,
+    #[cfg(target_os = "windows")]
+    pub(crate) external_semaphore: Option<ash::khr::external_semaphore_win32::Device>,
+    pub(crate) pdevice: PhysicalDevice,
+    pub(crate) instance: Arc<Instance>,
+    pub(crate) main_queue: Queue,
+    pub(crate) transfer_queue: Option<Queue>,
+    pub(crate) compute_queue: Option<Queue>,
+    #[cfg(feature = "video-decode")]
+    pub(crate) video_decode: Option<VideoDecode>,
+    extensions: Vec<&'static CStr>,
+    pub(crate) features: vk::PhysicalDeviceFeatures,
+    pub(crate) custom_border_color_without_format: bool,
     current_drop_list: Mutex<DropList>,
     memory_allocator: Mutex<GpuMemoryAllocator>,
     descriptor_allocator: Mutex<DescriptorAllocator>,
     frames: [Mutex<Arc<DeviceFrame>>; 2],
-    samplers: HashMap<SamplerDesc, vk::Sampler>,
+    samplers: Mutex<HashMap<SamplerDesc, vk::Sampler>>,
+    pub(crate) sampler_count: AtomicU32,
+    /// Layouts created with `Device::create_pipeline_layout`, used to check
+    /// descriptor set binds and push constants in debug builds.
+    #[cfg(debug_assertions)]
+    pub(crate) pipeline_layouts:
+        Mutex<HashMap<vk::PipelineLayout, super::pipeline::PipelineLayoutKey>>,
+    pub(crate) submit_lock: Mutex<()>,
+    immediate: Mutex<(vk::CommandPool, CommandBuffer)>,
+    frame_timeout: Duration,
+    memory_pressure_callback: Option<MemoryPressureCallback>,
+    on_begin_frame: Option<FrameCallback>,
+    on_end_frame: Option<FrameCallback>,
+    frame_index: AtomicU64,
+    last_frame_gpu_ticks: Mutex<Option<u64>>,
+    // Dropped manually before the device is destroyed.
+    profiler: ManuallyDrop<Box<dyn Profiler>>,
+    #[cfg(feature = "renderdoc")]
+    pub(crate) renderdoc: Option<crate::vulkan::RenderDocCapture>,
+    allocation_count: AtomicUsize,
+    allocated_bytes: AtomicU64,
 }
 
 impl Debug for Device {
@@ -47,133 +117,986 @@ impl Debug for Device {
             .field("pdevice", &self.pdevice)
             .field("instance", &self.instance)
             .field("main_queue", &self.main_queue)
+            .field("transfer_queue", &self.transfer_queue)
+            .field("compute_queue", &self.compute_queue)
             .finish()
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandBufferUsage {
+    /// Submitted once, then reset together with its pool.
+    OneTimeSubmit,
+    /// Submitted several times after a single recording.
+    Reusable,
+    /// Like `Reusable`, but may be pending on the GPU several times at once.
+    Simultaneous,
+}
+
+impl CommandBufferUsage {
+    fn flags(self) -> vk::CommandBufferUsageFlags {
+        match self {
+            Self::OneTimeSubmit => vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            Self::Reusable => vk::CommandBufferUsageFlags::empty(),
+            Self::Simultaneous => vk::CommandBufferUsageFlags::SIMULTANEOUS_USE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct CommandBuffer {
     cb: vk::CommandBuffer,
-    fence: vk::Fence,
+    fence: Arc<Fence>,
+    reusable: bool,
 }
 
 impl CommandBuffer {
     fn new(device: &ash::Device, pool: vk::CommandPool) -> Result<Self, BackendError> {
+        Self::allocate(device, pool, false)
+    }
+
+    /// Allocates a command buffer meant to be recorded once and submitted
+    /// many times, so its pool must not be reset with every frame. Caller
+    /// owns the buffer and releases it with [`CommandBuffer::free`].
+    pub fn new_reusable(device: &ash::Device, pool: vk::CommandPool) -> Result<Self, BackendError> {
+        Self::allocate(device, pool, true)
+    }
+
+    fn allocate(
+        device: &ash::Device,
+        pool: vk::CommandPool,
+        reusable: bool,
+    ) -> Result<Self, BackendError> {
         let cb_info = vk::CommandBufferAllocateInfo::default()
             .command_buffer_count(1)
             .command_pool(pool)
             .level(vk::CommandBufferLevel::PRIMARY);
         let cb = unsafe { device.allocate_command_buffers(&cb_info) }?[0];
-        let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
-        let fence = unsafe { device.create_fence(&fence_info, None) }?;
-        Ok(Self { cb, fence })
+        // Unsignaled until submitted, frames only wait on fences of submitted
+        // buffers.
+        let fence = Arc::new(Fence::device_owned(device, false)?);
+        Ok(Self {
+            cb,
+            fence,
+            reusable,
+        })
+    }
+
+    pub fn raw(&self) -> vk::CommandBuffer {
+        self.cb
     }
 
-    pub fn free(&self, device: &ash::Device) {
-        unsafe { device.destroy_fence(self.fence, None) };
+    /// Frame and immediate command buffers are reset with their pool and
+    /// only accept [`CommandBufferUsage::OneTimeSubmit`], reusable ones
+    /// accept everything else.
+    pub fn begin(
+        &self,
+        device: &ash::Device,
+        usage: CommandBufferUsage,
+    ) -> Result<(), BackendError> {
+        if self.reusable == (usage == CommandBufferUsage::OneTimeSubmit) {
+            return Err(BackendError::InvalidCommandBufferUsage(usage));
+        }
+        let info = vk::CommandBufferBeginInfo::default().flags(usage.flags());
+        unsafe { device.begin_command_buffer(self.cb, &info) }?;
+        Ok(())
+    }
+
+    /// Sets bounds for pipelines with dynamic depth bounds.
+    pub fn set_depth_bounds(&self, device: &ash::Device, min: f32, max: f32) {
+        unsafe { device.cmd_set_depth_bounds(self.cb, min, max) };
+    }
+
+    pub fn dispatch(&self, device: &ash::Device, x: u32, y: u32, z: u32) {
+        unsafe { device.cmd_dispatch(self.cb, x, y, z) };
+    }
+
+    /// Dispatches enough workgroups of `local_size` to cover `total`
+    /// invocations, the last group in every dimension may be partial so
+    /// shaders should check bounds. Components of `local_size` must be
+    /// non-zero.
+    pub fn dispatch_for(
+        &self,
+        device: &Device,
+        total: (u32, u32, u32),
+        local_size: (u32, u32, u32),
+    ) -> Result<(), BackendError> {
+        let count = [
+            total.0.div_ceil(local_size.0),
+            total.1.div_ceil(local_size.1),
+            total.2.div_ceil(local_size.2),
+        ];
+        let max = device
+            .pdevice
+            .properties
+            .limits
+            .max_compute_work_group_count;
+        if count.iter().zip(&max).any(|(count, max)| count > max) {
+            return Err(BackendError::WorkGroupCountExceeded { count, max });
+        }
+        self.dispatch(&device.raw, count[0], count[1], count[2]);
+        Ok(())
+    }
+
+    pub fn end(&self, device: &ash::Device) -> Result<(), BackendError> {
+        unsafe { device.end_command_buffer(self.cb) }?;
+        Ok(())
+    }
+
+    /// Destroys fence of the buffer, the buffer itself is freed with its
+    /// pool.
+    pub fn free(&self) {
+        self.fence.destroy();
     }
 }
 
+/// Every command buffer of a frame has its own pool, so pools are reset
+/// one by one instead of all at once.
 #[derive(Debug)]
 struct DeviceFrame {
-    pool: vk::CommandPool,
-    pub swapchain_acquired: vk::Semaphore,
-    pub rendering_finished: vk::Semaphore,
+    main_pool: vk::CommandPool,
+    presentation_pool: vk::CommandPool,
+    pub swapchain_acquired: Semaphore,
+    pub rendering_finished: Semaphore,
     drop_list: Mutex<DropList>,
     pub main_cb: CommandBuffer,
     pub presentation_cb: CommandBuffer,
+    transfer: Option<(vk::CommandPool, CommandBuffer)>,
+    compute_pool: Option<vk::CommandPool>,
+    /// Buffers allocated from `compute_pool` and how many of them the frame
+    /// handed out, they're reused once the pool is reset.
+    compute_cbs: Mutex<(Vec<CommandBuffer>, usize)>,
+    #[cfg(feature = "video-decode")]
+    video_decode: Option<(vk::CommandPool, CommandBuffer)>,
+    queries: Option<FrameQueries>,
+    submitted: Mutex<Vec<vk::Fence>>,
 }
 
+const FRAME_QUERY_COUNT: u32 = 1024;
+
+/// Timestamp queries of a frame slot. Results are read when the slot is
+/// reused, so they're one full cycle old and available without waiting.
+#[derive(Debug)]
+struct FrameQueries {
+    pool: vk::QueryPool,
+    next: AtomicU32,
+    results: Mutex<Vec<u64>>,
+    /// Queries written by `Frame::begin_main_cb` and `Frame::end_main_cb`.
+    main_cb_span: Mutex<(Option<u32>, Option<u32>)>,
+    /// GPU ticks between the span queries, read together with results.
+    main_cb_ticks: Mutex<Option<u64>>,
+}
+
+/// Frame being recorded. `Frame` is `Send + Sync`, so `&Frame` can be passed
+/// to worker threads, but the main command buffer must be recorded from one
+/// thread at a time: every recording method writes into it. For parallel
+/// recording workers fill secondary command buffers from their own pools,
+/// then the recording thread runs them with [`Frame::execute_commands`].
 pub struct Frame<'a> {
-    device: &'a Device,
+    pub(crate) device: &'a Device,
     queue: Queue,
     frame: Arc<DeviceFrame>,
+    pub(crate) mesh_pipeline_bound: AtomicBool,
+    pub(crate) rendering: AtomicBool,
+    #[cfg(debug_assertions)]
+    pub(crate) bound_layouts: Mutex<HashMap<vk::PipelineBindPoint, vk::PipelineLayout>>,
 }
 
 impl<'a> Frame<'a> {
+    pub fn main_cb(&self) -> CommandBuffer {
+        self.frame.main_cb.clone()
+    }
+
+    pub fn presentation_cb(&self) -> CommandBuffer {
+        self.frame.presentation_cb.clone()
+    }
+
+    pub fn swapchain_acquired(&self) -> vk::Semaphore {
+        self.frame.swapchain_acquired.raw
+    }
+
+    pub fn rendering_finished(&self) -> vk::Semaphore {
+        self.frame.rendering_finished.raw
+    }
+
+    /// Writes a timestamp into the main command buffer and returns its query
+    /// index. `None` when the device has no timestamps with host query reset
+    /// or all queries of the frame are used.
+    pub fn write_timestamp(&self, stage: vk::PipelineStageFlags2) -> Option<u32> {
+        self.write_timestamp_to(self.main_cb().raw(), stage)
+    }
+
+    /// Writes a frame query timestamp into a command buffer submitted within
+    /// the frame, e.g. a secondary one.
+    pub(crate) fn write_timestamp_to(
+        &self,
+        cb: vk::CommandBuffer,
+        stage: vk::PipelineStageFlags2,
+    ) -> Option<u32> {
+        let queries = self.frame.queries.as_ref()?;
+        let query = queries.next.fetch_add(1, Ordering::Relaxed);
+        if query >= FRAME_QUERY_COUNT {
+            return None;
+        }
+        unsafe {
+            self.device
+                .raw
+                .cmd_write_timestamp2(cb, stage, queries.pool, query)
+        };
+        Some(query)
+    }
+
+    /// Executes secondary command buffers recorded by worker threads. Inside
+    /// rendering they must be recorded with inheritance of the same
+    /// attachments and `RENDER_PASS_CONTINUE` usage.
+    pub fn execute_commands(&self, secondary: &[vk::CommandBuffer]) {
+        unsafe {
+            self.device
+                .raw
+                .cmd_execute_commands(self.main_cb().raw(), secondary)
+        };
+    }
+
+    /// Resets queries of a pool owned by the caller, must be recorded outside
+    /// of rendering.
+    pub fn reset_query_pool(&self, pool: vk::QueryPool, first: u32, count: u32) {
+        debug_assert!(
+            !self.rendering.load(Ordering::Relaxed),
+            "Query pools can't be reset while rendering"
+        );
+        unsafe {
+            self.device
+                .raw
+                .cmd_reset_query_pool(self.main_cb().raw(), pool, first, count)
+        };
+    }
+
+    /// Begins the main command buffer and writes a timestamp at its start,
+    /// which together with [`Frame::end_main_cb`] measures
+    /// [`Device::last_frame_gpu_time`].
+    pub fn begin_main_cb(&self) -> Result<(), BackendError> {
+        self.main_cb()
+            .begin(&self.device.raw, CommandBufferUsage::OneTimeSubmit)?;
+        if let Some(queries) = &self.frame.queries {
+            queries.main_cb_span.lock().0 = self.write_timestamp(vk::PipelineStageFlags2::NONE);
+        }
+        Ok(())
+    }
+
+    /// Writes a timestamp after all commands of the main command buffer and
+    /// ends it.
+    pub fn end_main_cb(&self) -> Result<(), BackendError> {
+        if let Some(queries) = &self.frame.queries {
+            queries.main_cb_span.lock().1 =
+                self.write_timestamp(vk::PipelineStageFlags2::ALL_COMMANDS);
+        }
+        self.main_cb().end(&self.device.raw)
+    }
+
+    /// Timestamps written the last time this frame slot was recorded, indexed
+    /// by query. Multiply by `timestampPeriod` to get nanoseconds.
+    pub fn previous_timestamps(&self) -> Vec<u64> {
+        self.frame
+            .queries
+            .as_ref()
+            .map(|queries| queries.results.lock().clone())
+            .unwrap_or_default()
+    }
+
+    /// Queues a closure to run when the GPU has finished the frame and its
+    /// resources are released, e.g. to return something to an external pool.
+    /// Closures run in the order they were added.
+    pub fn on_frame_end<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.device
+            .with_drop_list(|drop_list| drop_list.run_on_cleanup(f));
+    }
+
+    /// Command buffer for the dedicated transfer queue, `None` when the
+    /// device has no such queue. Submit it with [`Frame::submit_transfer`].
+    pub fn transfer_cb(&self) -> Option<CommandBuffer> {
+        self.frame.transfer.as_ref().map(|(_, cb)| cb.clone())
+    }
+
+    /// Submits transfer command buffer of the frame to the transfer queue.
+    /// Does nothing without a dedicated transfer queue.
+    pub fn submit_transfer(
+        &self,
+        waits: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+        signals: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+    ) -> Result<(), BackendError> {
+        if let (Some(queue), Some(cb)) = (self.device.transfer_queue, self.transfer_cb()) {
+            self.submit_to_queue(queue, cb, waits, signals)?;
+        }
+        Ok(())
+    }
+
+    /// Command buffer for the async compute queue, `None` when the device has
+    /// no compute family without graphics or the buffer can't be allocated.
+    /// Every call returns a new buffer from the frame's compute pool, submit
+    /// it with [`Frame::submit_compute`].
+    pub fn allocate_compute_command_buffer(&self) -> Option<CommandBuffer> {
+        let pool = self.frame.compute_pool?;
+        let mut compute_cbs = self.frame.compute_cbs.lock();
+        let (cbs, used) = &mut *compute_cbs;
+        if *used == cbs.len() {
+            match CommandBuffer::new(&self.device.raw, pool) {
+                Ok(cb) => cbs.push(cb),
+                Err(err) => {
+                    error!("Failed to allocate compute command buffer: {err}");
+                    return None;
+                }
+            }
+        }
+        *used += 1;
+        Some(cbs[*used - 1].clone())
+    }
+
+    /// Submits command buffer from [`Frame::allocate_compute_command_buffer`]
+    /// to the async compute queue. Fails with [`BackendError::NoComputeQueue`]
+    /// without one.
+    pub fn submit_compute(
+        &self,
+        cb: CommandBuffer,
+        waits: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+        signals: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+    ) -> Result<(), BackendError> {
+        let queue = self
+            .device
+            .compute_queue
+            .ok_or(BackendError::NoComputeQueue)?;
+        self.submit_to_queue(queue, cb, waits, signals)
+    }
+
+    /// Command buffer for the video decode queue, `None` when the device has
+    /// no such queue.
+    #[cfg(feature = "video-decode")]
+    pub fn video_decode_cb(&self) -> Option<CommandBuffer> {
+        self.frame.video_decode.as_ref().map(|(_, cb)| cb.clone())
+    }
+
+    /// Submits command buffer to the frame queue with a single wait and
+    /// signal. The device argument is unused, the frame submits with its own.
     pub fn submit(
         &self,
-        device: &ash::Device,
+        _device: &ash::Device,
         cb: CommandBuffer,
         signal: vk::Semaphore,
         signal_stage: vk::PipelineStageFlags2,
         wait: vk::Semaphore,
         wait_stage: vk::PipelineStageFlags2,
+    ) -> Result<(), BackendError> {
+        self.submit_to_queue(
+            self.queue,
+            cb,
+            &[(wait, wait_stage)],
+            &[(signal, signal_stage)],
+        )
+    }
+
+    /// Submits command buffer to the frame queue. Every wait and signal is a
+    /// binary semaphore with the stage it's waited on or signaled at.
+    pub fn submit_pass(
+        &self,
+        cb: CommandBuffer,
+        waits: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+        signals: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+    ) -> Result<(), BackendError> {
+        self.submit_to_queue(self.queue, cb, waits, signals)
+    }
+
+    pub(crate) fn submit_to_queue(
+        &self,
+        queue: Queue,
+        cb: CommandBuffer,
+        waits: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+        signals: &[(vk::Semaphore, vk::PipelineStageFlags2)],
     ) -> Result<(), BackendError> {
         let command_buffer = [vk::CommandBufferSubmitInfo::default().command_buffer(cb.cb)];
-        let wait = [vk::SemaphoreSubmitInfo::default()
-            .semaphore(wait)
-            .stage_mask(wait_stage)];
-        let signal = [vk::SemaphoreSubmitInfo::default()
-            .semaphore(signal)
-            .stage_mask(signal_stage)];
+        let semaphore_info = |&(semaphore, stage): &(vk::Semaphore, vk::PipelineStageFlags2)| {
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(semaphore)
+                .stage_mask(stage)
+        };
+        let waits = waits.iter().map(semaphore_info).collect::<Vec<_>>();
+        let signals = signals.iter().map(semaphore_info).collect::<Vec<_>>();
         let info = vk::SubmitInfo2::default()
             .command_buffer_infos(&command_buffer)
-            .wait_semaphore_infos(&wait)
-            .signal_semaphore_infos(&signal);
-        unsafe { device.queue_submit2(self.queue.raw, &[info], cb.fence) }?;
+            .wait_semaphore_infos(&waits)
+            .signal_semaphore_infos(&signals);
+        let _lock = self.device.submit_lock.lock();
+        unsafe {
+            self.device
+                .raw
+                .queue_submit2(queue.raw, &[info], cb.fence.raw)
+        }?;
+        self.frame.submitted.lock().push(cb.fence.raw);
         Ok(())
     }
 
     pub fn end(self) {
         self.device.end_frame(self.frame);
     }
+
+    /// Ends the frame and returns fence of the main command buffer, signaled
+    /// when the GPU finishes it, so the main command buffer must have been
+    /// submitted. The guard keeps frame resources from being reset and must
+    /// be dropped before the frame slot is reused, i.e. before the device
+    /// begins the frame after next, see [`DeviceFrameGuard`].
+    pub fn end_returning_fence(self) -> (vk::Fence, DeviceFrameGuard) {
+        let guard = DeviceFrameGuard {
+            frame: self.frame.clone(),
+        };
+        self.device.end_frame(self.frame);
+        (guard.frame.main_cb.fence.raw, guard)
+    }
+}
+
+/// Keeps resources of an ended frame alive. Frame slots are reused every
+/// second frame, so [`Device::frame`] fails with
+/// [`BackendError::FrameInUse`] while the guard of the frame before the
+/// previous one is held.
+#[derive(Debug)]
+pub struct DeviceFrameGuard {
+    frame: Arc<DeviceFrame>,
 }
 
 impl DeviceFrame {
-    fn new(device: &ash::Device, queue_family_index: u32) -> Result<Self, BackendError> {
-        let pool_info = vk::CommandPoolCreateInfo::default().queue_family_index(queue_family_index);
-        let pool = unsafe { device.create_command_pool(&pool_info, None) }?;
-        let swapchain_acquired =
-            unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }?;
-        let rendering_finished =
-            unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }?;
-        let main_cb = CommandBuffer::new(device, pool)?;
-        let presentation_cb = CommandBuffer::new(device, pool)?;
+    fn new(
+        device: &ash::Device,
+        queue_family_index: u32,
+        pool_flags: vk::CommandPoolCreateFlags,
+    ) -> Result<Self, BackendError> {
+        let main_pool = create_command_pool(device, queue_family_index, pool_flags)?;
+        let presentation_pool = create_command_pool(device, queue_family_index, pool_flags)?;
+        let swapchain_acquired = Semaphore::device_owned(device)?;
+        let rendering_finished = Semaphore::device_owned(device)?;
+        let main_cb = CommandBuffer::new(device, main_pool)?;
+        let presentation_cb = CommandBuffer::new(device, presentation_pool)?;
         Ok(Self {
-            pool,
+            main_pool,
+            presentation_pool,
             swapchain_acquired,
             rendering_finished,
             main_cb,
             presentation_cb,
             drop_list: DropList::default().into(),
+            transfer: None,
+            compute_pool: None,
+            compute_cbs: Mutex::default(),
+            #[cfg(feature = "video-decode")]
+            video_decode: None,
+            queries: None,
+            submitted: Mutex::default(),
         })
     }
 
+    /// Adds timestamp query pool, requires host query reset.
+    fn with_timestamps(mut self, device: &ash::Device) -> Result<Self, BackendError> {
+        let info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(FRAME_QUERY_COUNT);
+        let pool = unsafe { device.create_query_pool(&info, None) }?;
+        unsafe { device.reset_query_pool(pool, 0, FRAME_QUERY_COUNT) };
+        self.queries = Some(FrameQueries {
+            pool,
+            next: AtomicU32::new(0),
+            results: Mutex::default(),
+            main_cb_span: Mutex::default(),
+            main_cb_ticks: Mutex::default(),
+        });
+        Ok(self)
+    }
+
+    fn with_transfer(
+        mut self,
+        device: &ash::Device,
+        queue_family_index: u32,
+        pool_flags: vk::CommandPoolCreateFlags,
+    ) -> Result<Self, BackendError> {
+        let pool = create_command_pool(device, queue_family_index, pool_flags)?;
+        let cb = CommandBuffer::new(device, pool)?;
+        self.transfer = Some((pool, cb));
+        Ok(self)
+    }
+
+    fn with_compute(
+        mut self,
+        device: &ash::Device,
+        queue_family_index: u32,
+        pool_flags: vk::CommandPoolCreateFlags,
+    ) -> Result<Self, BackendError> {
+        self.compute_pool = Some(create_command_pool(device, queue_family_index, pool_flags)?);
+        Ok(self)
+    }
+
+    #[cfg(feature = "video-decode")]
+    fn with_video_decode(
+        mut self,
+        device: &ash::Device,
+        queue_family_index: u32,
+        pool_flags: vk::CommandPoolCreateFlags,
+    ) -> Result<Self, BackendError> {
+        let pool = create_command_pool(device, queue_family_index, pool_flags)?;
+        let cb = CommandBuffer::new(device, pool)?;
+        self.video_decode = Some((pool, cb));
+        Ok(self)
+    }
+
     fn reset(
         &self,
         device: &ash::Device,
+        loaders: CleanupLoaders,
         memory_allocator: &mut GpuMemoryAllocator,
         descriptor_allocator: &mut DescriptorAllocator,
-    ) -> Result<(), BackendError> {
-        unsafe { device.reset_command_pool(self.pool, vk::CommandPoolResetFlags::empty()) }?;
-        self.drop_list
+    ) -> Result<Closures, BackendError> {
+        for pool in self.pools() {
+            unsafe { device.reset_command_pool(pool, vk::CommandPoolResetFlags::empty()) }?;
+        }
+        self.compute_cbs.lock().1 = 0;
+        if let Some(queries) = &self.queries {
+            let used = queries
+                .next
+                .swap(0, Ordering::Relaxed)
+                .min(FRAME_QUERY_COUNT);
+            let mut results = queries.results.lock();
+            results.clear();
+            results.resize(used as usize, 0);
+            if used > 0 {
+                if let Err(err) = unsafe {
+                    device.get_query_pool_results(
+                        queries.pool,
+                        0,
+                        &mut results,
+                        vk::QueryResultFlags::TYPE_64,
+                    )
+                } {
+                    warn!("Frame timestamps aren't available: {err:?}");
+                    results.clear();
+                }
+                unsafe { device.reset_query_pool(queries.pool, 0, used) };
+            }
+            let span = mem::take(&mut *queries.main_cb_span.lock());
+            *queries.main_cb_ticks.lock() = match span {
+                (Some(begin), Some(end)) => results
+                    .get(end as usize)
+                    .zip(results.get(begin as usize))
+                    .map(|(end, begin)| end.saturating_sub(*begin)),
+                _ => None,
+            };
+        }
+        Ok(self
+            .drop_list
             .lock()
-            .cleanup(device, memory_allocator, descriptor_allocator);
-        Ok(())
+            .cleanup(device, loaders, memory_allocator, descriptor_allocator))
+    }
+
+    fn pools(&self) -> Vec<vk::CommandPool> {
+        let pools = [self.main_pool, self.presentation_pool]
+            .into_iter()
+            .chain(self.transfer.as_ref().map(|(pool, _)| *pool))
+            .chain(self.compute_pool);
+        #[cfg(feature = "video-decode")]
+        let pools = pools.chain(self.video_decode.as_ref().map(|(pool, _)| *pool));
+        pools.collect()
+    }
+
+    fn fences(&self) -> Vec<(&'static str, vk::Fence)> {
+        let fences = [
+            ("main", self.main_cb.fence.raw),
+            ("presentation", self.presentation_cb.fence.raw),
+        ]
+        .into_iter()
+        .chain(
+            self.transfer
+                .as_ref()
+                .map(|(_, cb)| ("transfer", cb.fence.raw)),
+        )
+        .chain(
+            self.compute_cbs
+                .lock()
+                .0
+                .iter()
+                .map(|cb| ("compute", cb.fence.raw))
+                .collect::<Vec<_>>(),
+        );
+        #[cfg(feature = "video-decode")]
+        let fences = fences.chain(
+            self.video_decode
+                .as_ref()
+                .map(|(_, cb)| ("video decode", cb.fence.raw)),
+        );
+        fences.collect()
     }
 
     fn free(&self, device: &ash::Device) {
-        unsafe { device.destroy_command_pool(self.pool, None) };
-        self.main_cb.free(device);
-        self.presentation_cb.free(device);
-        unsafe {
-            device.destroy_semaphore(self.rendering_finished, None);
-            device.destroy_semaphore(self.swapchain_acquired, None);
+        for pool in self.pools() {
+            unsafe { device.destroy_command_pool(pool, None) };
+        }
+        self.main_cb.free();
+        self.presentation_cb.free();
+        if let Some((_, cb)) = &self.transfer {
+            cb.free();
+        }
+        for cb in &self.compute_cbs.lock().0 {
+            cb.free();
+        }
+        if let Some(queries) = &self.queries {
+            unsafe { device.destroy_query_pool(queries.pool, None) };
+        }
+        #[cfg(feature = "video-decode")]
+        if let Some((_, cb)) = &self.video_decode {
+            cb.free();
+        }
+        self.rendering_finished.destroy();
+        self.swapchain_acquired.destroy();
+    }
+}
+
+/// Formatting target of object names, spills to the heap when the name
+/// doesn't fit.
+enum NameBuffer {
+    Inline([u8; 128], usize),
+    Heap(Vec<u8>),
+}
+
+impl Default for NameBuffer {
+    fn default() -> Self {
+        Self::Inline([0; 128], 0)
+    }
+}
+
+impl NameBuffer {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Self::Inline(bytes, len) => &bytes[..*len],
+            Self::Heap(bytes) => bytes,
+        }
+    }
+}
+
+impl fmt::Write for NameBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self {
+            Self::Inline(bytes, len) if *len + s.len() <= bytes.len() => {
+                bytes[*len..*len + s.len()].copy_from_slice(s.as_bytes());
+                *len += s.len();
+            }
+            Self::Inline(bytes, len) => {
+                let mut heap = bytes[..*len].to_vec();
+                heap.extend_from_slice(s.as_bytes());
+                *self = Self::Heap(heap);
+            }
+            Self::Heap(bytes) => bytes.extend_from_slice(s.as_bytes()),
+        }
+        Ok(())
+    }
+}
+
+/// Queues to create, as queue family and number of queues in it. Roles that
+/// end up in the same family get separate queues while the family has
+/// enough of them and share one otherwise, submits are serialized by
+/// `Device::submit_lock` either way.
+#[derive(Default)]
+struct QueueRequests {
+    families: Vec<(u32, u32)>,
+}
+
+impl QueueRequests {
+    /// Main, transfer, async compute and video decode.
+    const MAX_QUEUES: usize = 4;
+
+    /// Returns queue family and index of the queue in it.
+    fn request(&mut self, pdevice: &PhysicalDevice, family: u32) -> (u32, u32) {
+        let available = pdevice.queue_families[family as usize]
+            .properties
+            .queue_count;
+        match self.families.iter_mut().find(|(index, _)| *index == family) {
+            Some((_, count)) if *count < available => {
+                *count += 1;
+                (family, *count - 1)
+            }
+            Some(_) => (family, 0),
+            None => {
+                self.families.push((family, 1));
+                (family, 0)
+            }
         }
     }
 }
 
+fn create_command_pool(
+    device: &ash::Device,
+    queue_family_index: u32,
+    flags: vk::CommandPoolCreateFlags,
+) -> Result<vk::CommandPool, BackendError> {
+    let info = vk::CommandPoolCreateInfo::default()
+        .queue_family_index(queue_family_index)
+        .flags(flags);
+    Ok(unsafe { device.create_command_pool(&info, None) }?)
+}
+
+/// Key of the sampler cache. Level of detail is stored as bits so the
+/// description stays hashable.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct SamplerDesc(vk::Filter, vk::SamplerMipmapMode, vk::SamplerAddressMode);
+pub struct SamplerDesc {
+    filter: vk::Filter,
+    mipmap_mode: vk::SamplerMipmapMode,
+    address_mode: vk::SamplerAddressMode,
+    max_anisotropy: Option<u32>,
+    compare_op: Option<vk::CompareOp>,
+    border_color: vk::BorderColor,
+    lod: [u32; 3],
+}
+
+impl SamplerDesc {
+    /// Linear samplers get 16x anisotropy, clamped to the device limit.
+    pub const fn new(
+        filter: vk::Filter,
+        mipmap_mode: vk::SamplerMipmapMode,
+        address_mode: vk::SamplerAddressMode,
+    ) -> Self {
+        let linear = filter.as_raw() == vk::Filter::LINEAR.as_raw();
+        Self {
+            filter,
+            mipmap_mode,
+            address_mode,
+            max_anisotropy: if linear { Some(16) } else { None },
+            compare_op: None,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            lod: [
+                0.0f32.to_bits(),
+                0.0f32.to_bits(),
+                vk::LOD_CLAMP_NONE.to_bits(),
+            ],
+        }
+    }
+
+    /// Maximum anisotropy, `None` disables anisotropic filtering. Requires
+    /// `samplerAnisotropy` device feature, ignored without it.
+    pub fn anisotropy(mut self, value: Option<u32>) -> Self {
+        self.max_anisotropy = value;
+        self
+    }
+
+    /// Depth comparison for shadow map samplers.
+    pub fn compare_op(mut self, value: vk::CompareOp) -> Self {
+        self.compare_op = Some(value);
+        self
+    }
+
+    /// Color returned outside of the image with `CLAMP_TO_BORDER` addressing.
+    pub fn border_color(mut self, value: vk::BorderColor) -> Self {
+        self.border_color = value;
+        self
+    }
+
+    pub fn lod(mut self, value: SamplerLod) -> Self {
+        self.lod = [
+            value.bias.to_bits(),
+            value.min.to_bits(),
+            value.max.to_bits(),
+        ];
+        self
+    }
+
+    pub fn lod_range(&self) -> SamplerLod {
+        let [bias, min, max] = self.lod.map(f32::from_bits);
+        SamplerLod { bias, min, max }
+    }
+
+    /// Bilinear filtering, mip levels aren't blended.
+    pub const fn linear_clamp() -> Self {
+        Self::new(
+            vk::Filter::LINEAR,
+            vk::SamplerMipmapMode::NEAREST,
+            vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        )
+    }
+
+    /// Bilinear filtering, mip levels aren't blended.
+    pub const fn linear_repeat() -> Self {
+        Self::new(
+            vk::Filter::LINEAR,
+            vk::SamplerMipmapMode::NEAREST,
+            vk::SamplerAddressMode::REPEAT,
+        )
+    }
+
+    pub const fn nearest_clamp() -> Self {
+        Self::new(
+            vk::Filter::NEAREST,
+            vk::SamplerMipmapMode::NEAREST,
+            vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        )
+    }
+
+    pub const fn nearest_repeat() -> Self {
+        Self::new(
+            vk::Filter::NEAREST,
+            vk::SamplerMipmapMode::NEAREST,
+            vk::SamplerAddressMode::REPEAT,
+        )
+    }
+
+    /// Linear filtering between and within mip levels. Like every linear
+    /// sampler in the table it's anisotropic.
+    pub const fn trilinear_repeat() -> Self {
+        Self::new(
+            vk::Filter::LINEAR,
+            vk::SamplerMipmapMode::LINEAR,
+            vk::SamplerAddressMode::REPEAT,
+        )
+    }
+
+    /// `max_anisotropy` is the device limit, zero when anisotropic filtering
+    /// isn't enabled.
+    pub(crate) fn info(self, max_anisotropy: f32) -> vk::SamplerCreateInfo<'static> {
+        let lod = self.lod_range();
+        let anisotropy = self
+            .max_anisotropy
+            .map_or(0.0, |value| (value as f32).min(max_anisotropy));
+        vk::SamplerCreateInfo::default()
+            .mag_filter(self.filter)
+            .min_filter(self.filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(self.address_mode)
+            .address_mode_v(self.address_mode)
+            .address_mode_w(self.address_mode)
+            .mip_lod_bias(lod.bias)
+            .min_lod(lod.min)
+            .max_lod(lod.max)
+            .anisotropy_enable(anisotropy >= 1.0)
+            .max_anisotropy(anisotropy.max(1.0))
+            .compare_enable(self.compare_op.is_some())
+            .compare_op(self.compare_op.unwrap_or(vk::CompareOp::NEVER))
+            .border_color(self.border_color)
+    }
+}
+
+pub type MemoryPressureCallback = Box<dyn Fn() + Send + Sync>;
+
+/// Receives index of the frame that begins or ends.
+pub type FrameCallback = Box<dyn Fn(u64) + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct MemoryStats {
+    pub allocation_count: usize,
+    pub allocated_bytes: u64,
+    pub remaining_allocations: u32,
+    pub heap_sizes: Vec<u64>,
+}
+
+/// Memory budget of a heap reported by the OS, includes allocations of other
+/// processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryHeapBudget {
+    pub budget_bytes: u64,
+    pub usage_bytes: u64,
+}
+
+pub struct DeviceBuilder {
+    pub(crate) instance: Arc<Instance>,
+    pdevice: PhysicalDevice,
+    frame_timeout: Duration,
+    memory_pressure_callback: Option<MemoryPressureCallback>,
+    on_begin_frame: Option<FrameCallback>,
+    on_end_frame: Option<FrameCallback>,
+    profiler_backend: BackendKind,
+    frame_pool_flags: vk::CommandPoolCreateFlags,
+    immediate_pool_flags: vk::CommandPoolCreateFlags,
+}
+
+impl DeviceBuilder {
+    pub fn new(instance: Arc<Instance>, pdevice: PhysicalDevice) -> Self {
+        Self {
+            instance,
+            pdevice,
+            frame_timeout: Duration::from_secs(5),
+            memory_pressure_callback: None,
+            on_begin_frame: None,
+            on_end_frame: None,
+            profiler_backend: BackendKind::None,
+            frame_pool_flags: vk::CommandPoolCreateFlags::empty(),
+            immediate_pool_flags: vk::CommandPoolCreateFlags::TRANSIENT,
+        }
+    }
+
+    /// How long to wait for the GPU to finish an old frame before reporting
+    /// [`BackendError::FrameTimeout`] instead of hanging forever.
+    pub fn frame_timeout(mut self, value: Duration) -> Self {
+        self.frame_timeout = value;
+        self
+    }
+
+    /// Called when device memory runs out, before the allocation is retried.
+    /// Release whatever can be evicted (streamed textures, caches) here.
+    pub fn memory_pressure_callback<F: Fn() + Send + Sync + 'static>(mut self, value: F) -> Self {
+        self.memory_pressure_callback = Some(Box::new(value));
+        self
+    }
+
+    /// Called once the frame slot is free, before any recording starts.
+    pub fn on_begin_frame<F: Fn(u64) + Send + Sync + 'static>(mut self, value: F) -> Self {
+        self.on_begin_frame = Some(Box::new(value));
+        self
+    }
+
+    /// Called when the frame is finished, before the next one can begin.
+    pub fn on_end_frame<F: Fn(u64) + Send + Sync + 'static>(mut self, value: F) -> Self {
+        self.on_end_frame = Some(Box::new(value));
+        self
+    }
+
+    /// Profiler returned by [`Device::profiler`]. Falls back to no-op when the
+    /// backend can't be used on this device.
+    pub fn profiler_backend(mut self, value: BackendKind) -> Self {
+        self.profiler_backend = value;
+        self
+    }
+
+    /// Flags of per-frame command pools. Pools are reset as a whole when the
+    /// frame begins, `RESET_COMMAND_BUFFER` is only needed when buffers are
+    /// reset individually.
+    pub fn frame_pool_flags(mut self, value: vk::CommandPoolCreateFlags) -> Self {
+        self.frame_pool_flags = value;
+        self
+    }
+
+    /// Flags of the command pool used by [`Device::immediate_submit`].
+    pub fn immediate_pool_flags(mut self, value: vk::CommandPoolCreateFlags) -> Self {
+        self.immediate_pool_flags = value;
+        self
+    }
+
+    pub fn build(self) -> Result<Arc<Device>, BackendError> {
+        Device::create(self)
+    }
+}
 
 impl Device {
     pub fn new(
         instance: Arc<Instance>,
         pdevice: PhysicalDevice,
     ) -> Result<Arc<Device>, BackendError> {
+        DeviceBuilder::new(instance, pdevice).build()
+    }
+
+    fn create(builder: DeviceBuilder) -> Result<Arc<Device>, BackendError> {
+        let DeviceBuilder {
+            instance,
+            pdevice,
+            frame_timeout,
+            memory_pressure_callback,
+            on_begin_frame,
+            on_end_frame,
+            profiler_backend,
+            frame_pool_flags,
+            immediate_pool_flags,
+        } = builder;
         let mut syncronization2 =
             vk::PhysicalDeviceSynchronization2Features::default().synchronization2(true);
+        let mut timeline_semaphore =
+            vk::PhysicalDeviceTimelineSemaphoreFeatures::default().timeline_semaphore(true);
         let mut maintenance4 = vk::PhysicalDeviceMaintenance4Features::default().maintenance4(true);
         let mut buffer_device_address =
             vk::PhysicalDeviceBufferDeviceAddressFeatures::default().buffer_device_address(true);
@@ -183,9 +1106,110 @@ impl Device {
             .runtime_descriptor_array(true)
             .descriptor_binding_sampled_image_update_after_bind(true)
             .descriptor_binding_storage_buffer_update_after_bind(true)
+            .descriptor_binding_partially_bound(true)
+            .descriptor_binding_variable_descriptor_count(true)
             .shader_sampled_image_array_non_uniform_indexing(true)
             .shader_storage_buffer_array_non_uniform_indexing(true);
 
+        let mut supported_custom_border_color =
+            vk::PhysicalDeviceCustomBorderColorFeaturesEXT::default();
+        let mut supported_pipeline_library =
+            vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT::default();
+        let mut supported_executable_properties =
+            vk::PhysicalDevicePipelineExecutablePropertiesFeaturesKHR::default();
+        let mut supported_acceleration_structure =
+            vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+        let mut supported_host_query_reset = vk::PhysicalDeviceHostQueryResetFeatures::default();
+        let mut supported_present_id = vk::PhysicalDevicePresentIdFeaturesKHR::default();
+        let mut supported_depth_clip = vk::PhysicalDeviceDepthClipEnableFeaturesEXT::default();
+        let mut supported_mesh_shader = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
+        let mut supported_pageable_memory =
+            vk::PhysicalDevicePageableDeviceLocalMemoryFeaturesEXT::default();
+        let mut supported_local_read =
+            vk::PhysicalDeviceDynamicRenderingLocalReadFeaturesKHR::default();
+        let mut supported_multiview = vk::PhysicalDeviceMultiviewFeatures::default();
+        let supported_features;
+        {
+            let mut supported = vk::PhysicalDeviceFeatures2::default()
+                .push_next(&mut supported_custom_border_color)
+                .push_next(&mut supported_pipeline_library)
+                .push_next(&mut supported_executable_properties)
+                .push_next(&mut supported_acceleration_structure)
+                .push_next(&mut supported_host_query_reset)
+                .push_next(&mut supported_present_id)
+                .push_next(&mut supported_depth_clip)
+                .push_next(&mut supported_mesh_shader)
+                .push_next(&mut supported_pageable_memory)
+                .push_next(&mut supported_local_read)
+                .push_next(&mut supported_multiview);
+            unsafe {
+                instance
+                    .raw
+                    .get_physical_device_features2(pdevice.raw, &mut supported)
+            };
+            supported_features = supported.features;
+        }
+        let features = vk::PhysicalDeviceFeatures::default()
+            .depth_clamp(supported_features.depth_clamp == vk::TRUE)
+            .alpha_to_one(supported_features.alpha_to_one == vk::TRUE)
+            .depth_bounds(supported_features.depth_bounds == vk::TRUE)
+            .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE);
+        let mut extensions = Vec::new();
+        if pdevice.supports_extension(ash::khr::swapchain::NAME) {
+            extensions.push(ash::khr::swapchain::NAME);
+        }
+        let custom_border_color = pdevice.supports_extension(ash::ext::custom_border_color::NAME)
+            && supported_custom_border_color.custom_border_colors == vk::TRUE;
+        if custom_border_color {
+            extensions.push(ash::ext::custom_border_color::NAME);
+        }
+        // Sampler creation doesn't know the format of images it's used with.
+        let custom_border_color_without_format = custom_border_color
+            && supported_custom_border_color.custom_border_color_without_format == vk::TRUE;
+        let mut custom_border_color_features =
+            vk::PhysicalDeviceCustomBorderColorFeaturesEXT::default()
+                .custom_border_colors(true)
+                .custom_border_color_without_format(custom_border_color_without_format);
+        let pipeline_library = pdevice.supports_extension(ash::khr::pipeline_library::NAME)
+            && pdevice.supports_extension(ash::ext::graphics_pipeline_library::NAME)
+            && supported_pipeline_library.graphics_pipeline_library == vk::TRUE;
+        if pipeline_library {
+            extensions.push(ash::khr::pipeline_library::NAME);
+            extensions.push(ash::ext::graphics_pipeline_library::NAME);
+        }
+        let mut pipeline_library_features =
+            vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT::default()
+                .graphics_pipeline_library(true);
+        let executable_properties = pdevice
+            .supports_extension(ash::khr::pipeline_executable_properties::NAME)
+            && supported_executable_properties.pipeline_executable_info == vk::TRUE;
+        if executable_properties {
+            extensions.push(ash::khr::pipeline_executable_properties::NAME);
+        }
+        let mut executable_properties_features =
+            vk::PhysicalDevicePipelineExecutablePropertiesFeaturesKHR::default()
+                .pipeline_executable_info(true);
+        let acceleration_structure = pdevice
+            .supports_extension(ash::khr::acceleration_structure::NAME)
+            && pdevice.supports_extension(ash::khr::deferred_host_operations::NAME)
+            && supported_acceleration_structure.acceleration_structure == vk::TRUE;
+        if acceleration_structure {
+            extensions.push(ash::khr::acceleration_structure::NAME);
+            extensions.push(ash::khr::deferred_host_operations::NAME);
+        }
+        let mut acceleration_structure_features =
+            vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default()
+                .acceleration_structure(true);
+        let mesh_shader = pdevice.supports_extension(ash::ext::mesh_shader::NAME)
+            && supported_mesh_shader.mesh_shader == vk::TRUE
+            && supported_mesh_shader.task_shader == vk::TRUE;
+        if mesh_shader {
+            extensions.push(ash::ext::mesh_shader::NAME);
+        }
+        let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default()
+            .mesh_shader(true)
+            .task_shader(true);
+
         let main_queue = pdevice
             .queue_families
             .iter()
@@ -198,36 +1222,244 @@ impl Device {
             .copied()
             .next()
             .ok_or(BackendError::NoSuitableQueue)?;
-        let queue_priorities = [1.0];
-        let queue_info = [vk::DeviceQueueCreateInfo::default()
-            .queue_family_index(main_queue.index)
-            .queue_priorities(&queue_priorities)];
+        let transfer_queue = pdevice
+            .queue_families
+            .iter()
+            .find(|queue| {
+                queue
+                    .properties
+                    .queue_flags
+                    .contains(vk::QueueFlags::TRANSFER)
+                    && !queue
+                        .properties
+                        .queue_flags
+                        .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .copied();
+        let compute_queue = pdevice
+            .queue_families
+            .iter()
+            .find(|queue| {
+                let flags = queue.properties.queue_flags;
+                flags.contains(vk::QueueFlags::COMPUTE) && !flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+            .copied();
+        #[cfg(feature = "video-decode")]
+        let video_decode_queue = pdevice.video_decode_queue_family().filter(|_| {
+            pdevice.supports_extension(ash::khr::video_queue::NAME)
+                && pdevice.supports_extension(ash::khr::video_decode_queue::NAME)
+        });
+        #[cfg(feature = "video-decode")]
+        if video_decode_queue.is_some() {
+            extensions.push(ash::khr::video_queue::NAME);
+            extensions.push(ash::khr::video_decode_queue::NAME);
+            for codec in [
+                ash::khr::video_decode_h264::NAME,
+                ash::khr::video_decode_h265::NAME,
+                ash::khr::video_decode_av1::NAME,
+            ] {
+                if pdevice.supports_extension(codec) {
+                    extensions.push(codec);
+                }
+            }
+        }
+        #[cfg(target_os = "linux")]
+        let external_semaphore = pdevice.supports_extension(ash::khr::external_semaphore_fd::NAME);
+        #[cfg(target_os = "linux")]
+        if external_semaphore {
+            extensions.push(ash::khr::external_semaphore_fd::NAME);
+        }
+        #[cfg(target_os = "windows")]
+        let external_semaphore =
+            pdevice.supports_extension(ash::khr::external_semaphore_win32::NAME);
+        #[cfg(target_os = "windows")]
+        if external_semaphore {
+            extensions.push(ash::khr::external_semaphore_win32::NAME);
+        }
+        let host_query_reset = supported_host_query_reset.host_query_reset == vk::TRUE;
+        let mut host_query_reset_features =
+            vk::PhysicalDeviceHostQueryResetFeatures::default().host_query_reset(true);
+        let present_id = pdevice.supports_extension(ash::khr::present_id::NAME)
+            && supported_present_id.present_id == vk::TRUE;
+        if present_id {
+            extensions.push(ash::khr::present_id::NAME);
+        }
+        let mut present_id_features =
+            vk::PhysicalDevicePresentIdFeaturesKHR::default().present_id(true);
+        let depth_clip = pdevice.supports_extension(ash::ext::depth_clip_enable::NAME)
+            && supported_depth_clip.depth_clip_enable == vk::TRUE;
+        if depth_clip {
+            extensions.push(ash::ext::depth_clip_enable::NAME);
+        }
+        let mut depth_clip_features =
+            vk::PhysicalDeviceDepthClipEnableFeaturesEXT::default().depth_clip_enable(true);
+        let multiview = pdevice.supports_extension(ash::khr::multiview::NAME)
+            && supported_multiview.multiview == vk::TRUE;
+        if multiview {
+            extensions.push(ash::khr::multiview::NAME);
+        }
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::default().multiview(true);
+        let pageable_memory = pdevice.supports_extension(ash::ext::memory_priority::NAME)
+            && pdevice.supports_extension(ash::ext::pageable_device_local_memory::NAME)
+            && supported_pageable_memory.pageable_device_local_memory == vk::TRUE;
+        if pageable_memory {
+            extensions.push(ash::ext::memory_priority::NAME);
+            extensions.push(ash::ext::pageable_device_local_memory::NAME);
+        }
+        let mut pageable_memory_features =
+            vk::PhysicalDevicePageableDeviceLocalMemoryFeaturesEXT::default()
+                .pageable_device_local_memory(true);
+        let local_read = pdevice.supports_extension(ash::khr::dynamic_rendering_local_read::NAME)
+            && supported_local_read.dynamic_rendering_local_read == vk::TRUE;
+        if local_read {
+            extensions.push(ash::khr::dynamic_rendering_local_read::NAME);
+        }
+        let mut local_read_features =
+            vk::PhysicalDeviceDynamicRenderingLocalReadFeaturesKHR::default()
+                .dynamic_rendering_local_read(true);
+        if pdevice.supports_extension(ash::ext::memory_budget::NAME) {
+            extensions.push(ash::ext::memory_budget::NAME);
+        }
+        // Exposed by capture layers like RenderDoc, only used without debug
+        // utils.
+        let debug_marker = instance.debug_utils().is_none()
+            && pdevice.supports_extension(ash::ext::debug_marker::NAME);
+        if debug_marker {
+            extensions.push(ash::ext::debug_marker::NAME);
+        }
+        let mut queue_requests = QueueRequests::default();
+        let main_queue = queue_requests.request(&pdevice, main_queue.index);
+        let transfer_queue =
+            transfer_queue.map(|queue| queue_requests.request(&pdevice, queue.index));
+        let compute_queue =
+            compute_queue.map(|queue| queue_requests.request(&pdevice, queue.index));
+        #[cfg(feature = "video-decode")]
+        let video_decode_queue =
+            video_decode_queue.map(|index| queue_requests.request(&pdevice, index));
+        let queue_priorities = [1.0; QueueRequests::MAX_QUEUES];
+        let queue_info = queue_requests
+            .families
+            .iter()
+            .map(|&(family, count)| {
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(family)
+                    .queue_priorities(&queue_priorities[..count as usize])
+            })
+            .collect::<Vec<_>>();
 
-        let device_create_info = vk::DeviceCreateInfo::default()
+        let extension_names = extensions
+            .iter()
+            .map(|name| name.as_ptr())
+            .collect::<Vec<_>>();
+        let mut device_create_info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_info)
+            .enabled_extension_names(&extension_names)
+            .enabled_features(&features)
             .push_next(&mut syncronization2)
+            .push_next(&mut timeline_semaphore)
             .push_next(&mut maintenance4)
             .push_next(&mut buffer_device_address)
             .push_next(&mut dynamic_rendering)
             .push_next(&mut descriptor_indexing);
+        if custom_border_color {
+            device_create_info = device_create_info.push_next(&mut custom_border_color_features);
+        }
+        if pipeline_library {
+            device_create_info = device_create_info.push_next(&mut pipeline_library_features);
+        }
+        if executable_properties {
+            device_create_info = device_create_info.push_next(&mut executable_properties_features);
+        }
+        if acceleration_structure {
+            device_create_info = device_create_info.push_next(&mut acceleration_structure_features);
+        }
+        if mesh_shader {
+            device_create_info = device_create_info.push_next(&mut mesh_shader_features);
+        }
+        if host_query_reset {
+            device_create_info = device_create_info.push_next(&mut host_query_reset_features);
+        }
+        if present_id {
+            device_create_info = device_create_info.push_next(&mut present_id_features);
+        }
+        if depth_clip {
+            device_create_info = device_create_info.push_next(&mut depth_clip_features);
+        }
+        if pageable_memory {
+            device_create_info = device_create_info.push_next(&mut pageable_memory_features);
+        }
+        if local_read {
+            device_create_info = device_create_info.push_next(&mut local_read_features);
+        }
+        if multiview {
+            device_create_info = device_create_info.push_next(&mut multiview_features);
+        }
         let device = unsafe {
-            instance
-                .raw
-                .create_device(pdevice.raw, &device_create_info, None)
+            instance.raw.create_device(
+                pdevice.raw,
+                &device_create_info,
+                instance.allocation_callbacks(),
+            )
         }?;
-        info!("Created a vulkan device");
-        let main_queue = Queue::new(
-            unsafe { device.get_device_queue(main_queue.index, 0) },
-            main_queue.index,
-        );
-        let frame1 = Mutex::new(Arc::new(DeviceFrame::new(
-            &device,
-            main_queue.queue_family_index,
-        )?));
-        let frame2 = Mutex::new(Arc::new(DeviceFrame::new(
-            &device,
-            main_queue.queue_family_index,
-        )?));
+        info!("Created a vulkan device with extensions {extensions:?}");
+        let debug_utils = instance
+            .debug_utils()
+            .map(|_| ash::ext::debug_utils::Device::new(&instance.raw, &device));
+        let acceleration_structure = acceleration_structure
+            .then(|| ash::khr::acceleration_structure::Device::new(&instance.raw, &device));
+        let mesh_shader =
+            mesh_shader.then(|| ash::ext::mesh_shader::Device::new(&instance.raw, &device));
+        let pageable_memory = pageable_memory
+            .then(|| ash::ext::pageable_device_local_memory::Device::new(&instance.raw, &device));
+        let debug_marker =
+            debug_marker.then(|| ash::ext::debug_marker::Device::new(&instance.raw, &device));
+        let local_read = local_read
+            .then(|| ash::khr::dynamic_rendering_local_read::Device::new(&instance.raw, &device));
+        #[cfg(target_os = "linux")]
+        let external_semaphore = external_semaphore
+            .then(|| ash::khr::external_semaphore_fd::Device::new(&instance.raw, &device));
+        #[cfg(target_os = "windows")]
+        let external_semaphore = external_semaphore
+            .then(|| ash::khr::external_semaphore_win32::Device::new(&instance.raw, &device));
+        let get_queue =
+            |(family, index)| Queue::new(unsafe { device.get_device_queue(family, index) }, family);
+        let main_queue = get_queue(main_queue);
+        let transfer_queue = transfer_queue.map(get_queue);
+        let compute_queue = compute_queue.map(get_queue);
+        #[cfg(feature = "video-decode")]
+        let video_decode = video_decode_queue
+            .map(|queue| VideoDecode::new(&instance.raw, &device, get_queue(queue)));
+        let timestamps = pdevice.queue_families[main_queue.queue_family_index as usize]
+            .properties
+            .timestamp_valid_bits
+            > 0;
+        let create_frame = || {
+            let mut frame =
+                DeviceFrame::new(&device, main_queue.queue_family_index, frame_pool_flags)?;
+            if host_query_reset && timestamps {
+                frame = frame.with_timestamps(&device)?;
+            }
+            if let Some(queue) = transfer_queue {
+                frame = frame.with_transfer(&device, queue.queue_family_index, frame_pool_flags)?;
+            }
+            if let Some(queue) = compute_queue {
+                frame = frame.with_compute(&device, queue.queue_family_index, frame_pool_flags)?;
+            }
+            // Video queues have their own session requirements, so they get a
+            // dedicated pool.
+            #[cfg(feature = "video-decode")]
+            let frame = match &video_decode {
+                Some(video_decode) => frame.with_video_decode(
+                    &device,
+                    video_decode.queue.queue_family_index,
+                    frame_pool_flags,
+                )?,
+                None => frame,
+            };
+            Ok::<_, BackendError>(Mutex::new(Arc::new(frame)))
+        };
+        let frame1 = create_frame()?;
+        let frame2 = create_frame()?;
         let memory_allocator = Mutex::new(GpuMemoryAllocator::new(
             gpu_alloc::Config {
                 dedicated_threshold: 32 * 1024 * 1024,
@@ -243,28 +1475,455 @@ impl Device {
             }?,
         ));
         let descriptor_allocator = Mutex::new(DescriptorAllocator::new(2));
-        let samplers = Device::create_samplers(&device)?;
+        let max_anisotropy = if features.sampler_anisotropy == vk::TRUE {
+            pdevice.properties.limits.max_sampler_anisotropy
+        } else {
+            0.0
+        };
+        let samplers = Device::create_samplers(&device, max_anisotropy)?;
+        let immediate_pool = unsafe {
+            device.create_command_pool(
+                &vk::CommandPoolCreateInfo::default()
+                    .queue_family_index(main_queue.queue_family_index)
+                    .flags(immediate_pool_flags),
+                None,
+            )
+        }?;
+        let immediate = Mutex::new((immediate_pool, CommandBuffer::new(&device, immediate_pool)?));
+        let profiler: Box<dyn Profiler> = match profiler_backend {
+            BackendKind::None => Box::new(NoopProfiler),
+            BackendKind::DebugLabels => match &debug_utils {
+                Some(debug_utils) => Box::new(DebugLabelProfiler::new(debug_utils.clone())),
+                None => {
+                    warn!("Debug utils aren't enabled, GPU passes won't be labeled");
+                    Box::new(NoopProfiler)
+                }
+            },
+            #[cfg(feature = "tracy")]
+            BackendKind::Tracy => {
+                if host_query_reset && timestamps {
+                    Box::new(crate::vulkan::TracyProfiler::new(
+                        &device,
+                        main_queue.raw,
+                        main_queue.queue_family_index,
+                        pdevice.properties.limits.timestamp_period,
+                    )?)
+                } else {
+                    warn!(
+                        "Device doesn't support timestamps with host query reset, Tracy is disabled"
+                    );
+                    Box::new(NoopProfiler)
+                }
+            }
+        };
+        #[cfg(feature = "renderdoc")]
+        let renderdoc = crate::vulkan::RenderDocCapture::load(instance.raw.handle());
         Ok(Self {
             raw: device,
+            debug_utils,
+            debug_marker,
+            acceleration_structure,
+            mesh_shader,
+            pageable_memory,
+            local_read,
+            #[cfg(any(target_os = "linux", target_os = "windows"))]
+            external_semaphore,
             pdevice,
             instance,
             main_queue,
+            transfer_queue,
+            compute_queue,
+            #[cfg(feature = "video-decode")]
+            video_decode,
+            extensions,
+            features,
+            custom_border_color_without_format,
             frames: [frame1, frame2],
             current_drop_list: DropList::default().into(),
             memory_allocator,
             descriptor_allocator,
-            samplers,
+            sampler_count: AtomicU32::new(samplers.len() as u32),
+            samplers: Mutex::new(samplers),
+            submit_lock: Mutex::new(()),
+            immediate,
+            frame_timeout,
+            memory_pressure_callback,
+            on_begin_frame,
+            on_end_frame,
+            frame_index: AtomicU64::new(0),
+            last_frame_gpu_ticks: Mutex::default(),
+            profiler: ManuallyDrop::new(profiler),
+            #[cfg(feature = "renderdoc")]
+            renderdoc,
+            allocation_count: AtomicUsize::new(0),
+            allocated_bytes: AtomicU64::new(0),
+            #[cfg(debug_assertions)]
+            pipeline_layouts: Mutex::default(),
         }
         .into())
     }
 
+    /// Records commands into a one-shot command buffer, submits it to the main
+    /// queue and waits for completion.
+    pub fn immediate_submit<F: FnOnce(&ash::Device, vk::CommandBuffer)>(
+        &self,
+        cb: F,
+    ) -> Result<(), BackendError> {
+        let immediate = self.immediate.lock();
+        let (pool, command_buffer) = &*immediate;
+        unsafe {
+            self.raw
+                .reset_command_pool(*pool, vk::CommandPoolResetFlags::empty())?;
+        }
+        command_buffer.fence.reset()?;
+        command_buffer.begin(&self.raw, CommandBufferUsage::OneTimeSubmit)?;
+        cb(&self.raw, command_buffer.cb);
+        command_buffer.end(&self.raw)?;
+        let command_buffers = [command_buffer.cb];
+        let info = vk::SubmitInfo::default().command_buffers(&command_buffers);
+        {
+            let _lock = self.submit_lock.lock();
+            unsafe {
+                self.raw
+                    .queue_submit(self.main_queue.raw, &[info], command_buffer.fence.raw)
+            }?;
+        }
+        command_buffer.fence.wait(u64::MAX)
+    }
+
+    /// Waits until work submitted to the main queue so far is finished.
+    /// Unlike `vkDeviceWaitIdle` gives up after `timeout` nanoseconds and
+    /// returns `false`.
+    pub fn wait_idle_with_timeout(&self, timeout: u64) -> Result<bool, BackendError> {
+        let fence = Fence::create_raw(&self.raw, false)?;
+        let submitted = {
+            let _lock = self.submit_lock.lock();
+            unsafe { self.raw.queue_submit(self.main_queue.raw, &[], fence) }
+        };
+        if let Err(err) = submitted {
+            unsafe { self.raw.destroy_fence(fence, None) };
+            return Err(err.into());
+        }
+        let result = Fence::wait_raw(&self.raw, fence, timeout);
+        if result.is_ok() {
+            unsafe { self.raw.destroy_fence(fence, None) };
+        } else {
+            // Still pending. The frame slot waits for its submitted fences
+            // before cleaning up its drop list, so the fence is only
+            // destroyed once it has signaled.
+            let frame = self.frames[0].lock();
+            frame.submitted.lock().push(fence);
+            frame.drop_list.lock().drop_fence(fence);
+        }
+        match result {
+            Ok(()) => Ok(true),
+            Err(BackendError::Timeout) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Allocates device memory. When the device is out of memory, releases
+    /// everything previous frames left pending destruction and retries once.
     pub fn allocate_memory(&self, request: gpu_alloc::Request) -> Result<GpuMemory, BackendError> {
+        self.allocate_memory_with_priority(request, None)
+    }
+
+    /// Like [`Device::allocate_memory`], with a residency priority from 0.0
+    /// to 1.0. Under memory pressure the driver pages out low priority memory
+    /// first. The priority needs a dedicated allocation, so it's only worth
+    /// setting for large resources. Ignored without
+    /// `VK_EXT_pageable_device_local_memory`.
+    pub fn allocate_memory_with_priority(
+        &self,
+        request: gpu_alloc::Request,
+        priority: Option<f32>,
+    ) -> Result<GpuMemory, BackendError> {
+        self.check_allocation_limit()?;
+        let priority = priority.filter(|_| self.pageable_memory.is_some());
+        let dedicated = priority.is_some();
+        let memory = match self.try_allocate_memory(request, dedicated) {
+            Err(gpu_alloc::AllocationError::OutOfDeviceMemory) => {
+                warn!("Out of device memory, trying to reclaim");
+                if let Some(callback) = &self.memory_pressure_callback {
+                    callback();
+                }
+                self.reclaim_memory()?;
+                self.try_allocate_memory(request, dedicated)
+                    .map_err(|err| match err {
+                        gpu_alloc::AllocationError::OutOfDeviceMemory => {
+                            BackendError::OutOfDeviceMemory(request, self.memory_stats())
+                        }
+                        err => self.allocation_error(err, request),
+                    })
+            }
+            result => result.map_err(|err| self.allocation_error(err, request)),
+        }?;
+        if let (Some(priority), Some(loader)) = (priority, &self.pageable_memory) {
+            unsafe {
+                (loader.fp().set_device_memory_priority_ext)(
+                    self.raw.handle(),
+                    *memory.memory(),
+                    priority.clamp(0.0, 1.0),
+                )
+            };
+        }
+        self.allocation_count.fetch_add(1, Ordering::Relaxed);
+        self.allocated_bytes
+            .fetch_add(memory.size(), Ordering::Relaxed);
+        Ok(memory)
+    }
+
+    /// Number of live allocations made through [`Device::allocate_memory`].
+    pub fn allocation_count(&self) -> usize {
+        self.allocation_count.load(Ordering::Relaxed)
+    }
+
+    /// Keeps a small share of `maxMemoryAllocationCount` in reserve, so
+    /// running out is reported before the driver fails on some internal
+    /// allocation.
+    fn check_allocation_limit(&self) -> Result<(), BackendError> {
+        let limit = self.pdevice.properties.limits.max_memory_allocation_count;
+        let remaining = self.memory_allocator.lock().remaining_allocations();
+        if remaining <= (limit / 64).max(1) {
+            error!(
+                "Only {remaining} of {limit} device memory allocations left, \
+                 suballocate from larger buffers"
+            );
+            return Err(BackendError::TooManyAllocations { limit, remaining });
+        }
+        if remaining <= limit / 10 {
+            warn!("Only {remaining} of {limit} device memory allocations left");
+        }
+        Ok(())
+    }
+
+    fn allocation_error(
+        &self,
+        err: gpu_alloc::AllocationError,
+        request: gpu_alloc::Request,
+    ) -> BackendError {
+        match err {
+            gpu_alloc::AllocationError::TooManyObjects => BackendError::TooManyAllocations {
+                limit: self.pdevice.properties.limits.max_memory_allocation_count,
+                remaining: 0,
+            },
+            err => BackendError::MemoryAllocationFailed(err, request),
+        }
+    }
+
+    fn try_allocate_memory(
+        &self,
+        request: gpu_alloc::Request,
+        dedicated: bool,
+    ) -> Result<GpuMemory, gpu_alloc::AllocationError> {
+        let device = AshMemoryDevice::wrap(&self.raw);
+        let mut allocator = self.memory_allocator.lock();
+        unsafe {
+            if dedicated {
+                allocator.alloc_with_dedicated(device, request, gpu_alloc::Dedicated::Required)
+            } else {
+                allocator.alloc(device, request)
+            }
+        }
+    }
+
+    /// Schedules memory block for destruction once the GPU is done with the current frame.
+    pub fn free_memory(&self, memory: GpuMemory) {
+        self.allocation_count.fetch_sub(1, Ordering::Relaxed);
+        self.allocated_bytes
+            .fetch_sub(memory.size(), Ordering::Relaxed);
+        self.with_drop_list(|drop_list| drop_list.drop_memory(memory));
+    }
+
+    /// Makes host writes to mapped non-coherent memory visible to the device.
+    /// `offset` is relative to the block, the range is widened to
+    /// `nonCoherentAtomSize` boundaries. Does nothing for coherent memory.
+    pub fn flush_mapped_memory(
+        &self,
+        memory: &GpuMemory,
+        offset: u64,
+        size: u64,
+    ) -> Result<(), BackendError> {
+        if let Some(range) = self.non_coherent_range(memory, offset, size) {
+            unsafe { self.raw.flush_mapped_memory_ranges(&[range]) }?;
+        }
+        Ok(())
+    }
+
+    /// Makes device writes to mapped non-coherent memory visible to the host,
+    /// call it before reading. Rounds the range like
+    /// [`Device::flush_mapped_memory`].
+    pub fn invalidate_mapped_memory(
+        &self,
+        memory: &GpuMemory,
+        offset: u64,
+        size: u64,
+    ) -> Result<(), BackendError> {
+        if let Some(range) = self.non_coherent_range(memory, offset, size) {
+            unsafe { self.raw.invalidate_mapped_memory_ranges(&[range]) }?;
+        }
+        Ok(())
+    }
+
+    /// Maps `size` bytes of the block at `offset` until
+    /// [`Device::unmap_memory`], for memory that's accessed rarely. Pair with
+    /// flush and invalidate when memory isn't coherent.
+    pub fn map_memory(
+        &self,
+        memory: &mut GpuMemory,
+        offset: u64,
+        size: u64,
+    ) -> Result<NonNull<u8>, BackendError> {
+        Ok(unsafe { memory.map(AshMemoryDevice::wrap(&self.raw), offset, size as usize) }?)
+    }
+
+    /// Pointers returned by [`Device::map_memory`] are invalid afterwards.
+    pub fn unmap_memory(&self, memory: &mut GpuMemory) {
+        unsafe { memory.unmap(AshMemoryDevice::wrap(&self.raw)) };
+    }
+
+    fn non_coherent_range(
+        &self,
+        memory: &GpuMemory,
+        offset: u64,
+        size: u64,
+    ) -> Option<vk::MappedMemoryRange<'static>> {
+        if memory
+            .props()
+            .contains(gpu_alloc::MemoryPropertyFlags::HOST_COHERENT)
+        {
+            return None;
+        }
+        let atom = self.pdevice.properties.limits.non_coherent_atom_size;
+        let start = (memory.offset() + offset) / atom * atom;
+        let end = (memory.offset() + offset + size)
+            .next_multiple_of(atom)
+            .min(memory.offset() + memory.size());
+        Some(
+            vk::MappedMemoryRange::default()
+                .memory(*memory.memory())
+                .offset(start)
+                .size(end - start),
+        )
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        let heap_sizes = self.pdevice.memory_properties.memory_heaps
+            [..self.pdevice.memory_properties.memory_heap_count as usize]
+            .iter()
+            .map(|heap| heap.size)
+            .collect();
+        MemoryStats {
+            allocation_count: self.allocation_count.load(Ordering::Relaxed),
+            allocated_bytes: self.allocated_bytes.load(Ordering::Relaxed),
+            remaining_allocations: self.memory_allocator.lock().remaining_allocations(),
+            heap_sizes,
+        }
+    }
+
+    /// Every queue created with the device, the main queue first. Meant for
+    /// tools, the device submits to these queues on its own.
+    pub fn queues(&self) -> Vec<QueueInfo> {
+        let queues = [
+            Some((self.main_queue, QueueKind::Main)),
+            self.transfer_queue
+                .map(|queue| (queue, QueueKind::Transfer)),
+            self.compute_queue.map(|queue| (queue, QueueKind::Compute)),
+            #[cfg(feature = "video-decode")]
+            self.video_decode
+                .as_ref()
+                .map(|video| (video.queue, QueueKind::Video)),
+        ];
+        queues
+            .into_iter()
+            .flatten()
+            .map(|(queue, kind)| QueueInfo {
+                raw: queue.raw,
+                family_index: queue.queue_family_index,
+                kind,
+                family_properties: self.pdevice.queue_families[queue.queue_family_index as usize]
+                    .properties,
+            })
+            .collect()
+    }
+
+    /// Current budget and usage of every memory heap, `None` without
+    /// `VK_EXT_memory_budget`. Budgets change over time, so it's queried on
+    /// every call.
+    pub fn memory_budget(&self) -> Option<Vec<MemoryHeapBudget>> {
+        if !self.is_extension_enabled(ash::ext::memory_budget::NAME) {
+            return None;
+        }
+        let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let heap_count;
+        {
+            let mut properties =
+                vk::PhysicalDeviceMemoryProperties2::default().push_next(&mut budget);
+            unsafe {
+                self.instance
+                    .raw
+                    .get_physical_device_memory_properties2(self.pdevice.raw, &mut properties)
+            };
+            heap_count = properties.memory_properties.memory_heap_count as usize;
+        }
+        Some(
+            budget.heap_budget[..heap_count]
+                .iter()
+                .zip(&budget.heap_usage[..heap_count])
+                .map(|(&budget_bytes, &usage_bytes)| MemoryHeapBudget {
+                    budget_bytes,
+                    usage_bytes,
+                })
+                .collect(),
+        )
+    }
+
+    /// Releases objects pending in the drop lists of frame slots. Objects
+    /// dropped since the last frame began are left alone: the frame being
+    /// recorded may still reference them from commands that aren't submitted
+    /// yet, so waiting for idle doesn't make them safe to destroy.
+    fn reclaim_memory(&self) -> Result<(), BackendError> {
+        unsafe { self.raw.device_wait_idle() }?;
+        for frame in &self.frames {
+            let closures = {
+                let frame = frame.lock();
+                // Fences in drop lists may also be in the submitted list.
+                self.wait_frame_fences(&frame)?;
+                frame.drop_list.lock().cleanup(
+                    &self.raw,
+                    self.cleanup_loaders(),
+                    &mut self.memory_allocator.lock(),
+                    &mut self.descriptor_allocator.lock(),
+                )
+            };
+            closures.run();
+        }
         unsafe {
             self.memory_allocator
                 .lock()
-                .alloc(AshMemoryDevice::wrap(&self.raw), request)
+                .cleanup(AshMemoryDevice::wrap(&self.raw))
+        };
+        Ok(())
+    }
+
+    /// Waits for the device to go idle, then frees `sets` and every set
+    /// pending in drop lists of previous frames at once, releasing pools that became empty. Much
+    /// faster than dropping thousands of sets one by one when a level is
+    /// unloaded. Pools can't be reset while sets allocated from them are
+    /// alive, so everything to reclaim must be passed in, and none of it may
+    /// be used afterwards.
+    pub fn reset_descriptor_pools(
+        &self,
+        sets: impl IntoIterator<Item = DescriptorSet>,
+    ) -> Result<(), BackendError> {
+        self.reclaim_memory()?;
+        let mut allocator = self.descriptor_allocator.lock();
+        let device = AshDescriptorDevice::wrap(&self.raw);
+        unsafe {
+            allocator.free(device, sets);
+            allocator.cleanup(device);
         }
-        .map_err(|err| BackendError::MemoryAllocationFailed(err, request))
+        Ok(())
     }
 
     pub fn allocate_descriptors(
@@ -291,61 +1950,248 @@ impl Device {
         Ok(descriptors)
     }
 
+    pub fn allocate_descriptor_set(
+        &self,
+        layout: vk::DescriptorSetLayout,
+        layout_count: DescriptorTotalCount,
+        bindless: bool,
+    ) -> Result<DescriptorSet, BackendError> {
+        let mut sets = self.allocate_descriptors(layout, layout_count, 1, bindless)?;
+        Ok(sets.remove(0))
+    }
+
+    /// Allocates sets whose variable count binding has `variable_count`
+    /// descriptors. `layout_count` must include the variable binding with
+    /// that count.
+    pub fn allocate_descriptors_variable_count(
+        &self,
+        layout: vk::DescriptorSetLayout,
+        layout_count: DescriptorTotalCount,
+        count: u32,
+        variable_count: u32,
+        bindless: bool,
+    ) -> Result<Vec<DescriptorSet>, BackendError> {
+        let flags = if bindless {
+            DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND
+        } else {
+            DescriptorSetLayoutCreateFlags::empty()
+        };
+        let device = VariableCountDescriptorDevice {
+            raw: &self.raw,
+            variable_count,
+        };
+        let descriptors = unsafe {
+            self.descriptor_allocator
+                .lock()
+                .allocate(&device, &layout, flags, &layout_count, count)
+        }?;
+        Ok(descriptors)
+    }
+
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.extensions.contains(&name)
+    }
+
+    pub fn set_object_name<T: vk::Handle>(&self, handle: T, name: &str) {
+        if self.debug_utils.is_some() {
+            let Ok(name) = CString::new(name) else {
+                warn!("Object name {name:?} contains nul byte");
+                return;
+            };
+            self.set_object_name_c(handle, &name);
+        }
+    }
+
+    /// Like [`Device::set_object_name`], but the name is only formatted with
+    /// debug utils enabled, on the stack unless it's longer than 128 bytes.
+    pub fn set_object_name_fmt<T: vk::Handle>(&self, handle: T, name: impl Display) {
+        if self.debug_utils.is_none() {
+            return;
+        }
+        let mut buffer = NameBuffer::default();
+        if write!(buffer, "{name}\0").is_err() {
+            return;
+        }
+        match CStr::from_bytes_with_nul(buffer.bytes()) {
+            Ok(name) => self.set_object_name_c(handle, name),
+            Err(_) => warn!("Object name {name} contains nul byte"),
+        }
+    }
+
+    fn set_object_name_c<T: vk::Handle>(&self, handle: T, name: &CStr) {
+        if let Some(debug_utils) = &self.debug_utils {
+            let info = vk::DebugUtilsObjectNameInfoEXT::default()
+                .object_handle(handle)
+                .object_name(name);
+            if let Err(err) = unsafe { debug_utils.set_debug_utils_object_name(&info) } {
+                warn!("Failed to set object name {name:?}: {err:?}");
+            }
+        }
+    }
+
+    fn cleanup_loaders(&self) -> CleanupLoaders<'_> {
+        CleanupLoaders {
+            acceleration_structure: self.acceleration_structure.as_ref(),
+            #[cfg(feature = "video-decode")]
+            video_queue: self
+                .video_decode
+                .as_ref()
+                .map(|video_decode| &video_decode.video_queue),
+        }
+    }
+
     pub fn with_drop_list<CB: FnOnce(&mut DropList)>(&self, cb: CB) {
         cb(&mut self.current_drop_list.lock());
     }
 
     fn begin_frame(&self) -> Result<Arc<DeviceFrame>, BackendError> {
-        let mut frame = self.frames[0].lock();
-        {
-            let frame =
-                Arc::get_mut(&mut frame).expect("Frame is used by something, can't start new");
-            unsafe {
-                self.raw.wait_for_fences(
-                    &[frame.main_cb.fence, frame.presentation_cb.fence],
-                    true,
-                    u64::MAX,
+        let (frame, closures) = {
+            let mut slot = self.frames[0].lock();
+            let closures = {
+                let frame = Arc::get_mut(&mut slot).ok_or(BackendError::FrameInUse)?;
+                self.wait_frame_fences(frame)?;
+                let closures = frame.reset(
+                    &self.raw,
+                    self.cleanup_loaders(),
+                    &mut self.memory_allocator.lock(),
+                    &mut self.descriptor_allocator.lock(),
                 )?;
+                if let Some(queries) = &frame.queries {
+                    *self.last_frame_gpu_ticks.lock() = *queries.main_cb_ticks.lock();
+                }
+                let mut frame_drop_list = frame.drop_list.lock();
+                let mut current_drop_list = self.current_drop_list.lock();
+                mem::swap(&mut frame_drop_list, &mut current_drop_list);
+                closures
+            };
+            (slot.clone(), closures)
+        };
+        // Called without the slot locked, callbacks may use the device, e.g.
+        // allocate memory, which can lock the frame slots again.
+        closures.run();
+        if let Some(callback) = &self.on_begin_frame {
+            callback(self.frame_index());
+        }
+        Ok(frame)
+    }
+
+    /// Waits for command buffers submitted in the frame and resets their
+    /// fences. Buffers that were never submitted have nothing to wait for,
+    /// their fences stay unsignaled.
+    fn wait_frame_fences(&self, frame: &DeviceFrame) -> Result<(), BackendError> {
+        let mut submitted = frame.submitted.lock();
+        if submitted.is_empty() {
+            return Ok(());
+        }
+        let timeout = self.frame_timeout.as_nanos().try_into().unwrap_or(u64::MAX);
+        match unsafe { self.raw.wait_for_fences(&submitted, true, timeout) } {
+            Ok(()) => {
+                unsafe { self.raw.reset_fences(&submitted) }?;
+                submitted.clear();
+                Ok(())
             }
-            frame.reset(
-                &self.raw,
-                &mut self.memory_allocator.lock(),
-                &mut self.descriptor_allocator.lock(),
-            )?;
-            let mut frame_drop_list = frame.drop_list.lock();
-            let mut current_drop_list = self.current_drop_list.lock();
-            mem::swap(&mut frame_drop_list, &mut current_drop_list);
+            Err(vk::Result::TIMEOUT) => {
+                let names = frame.fences();
+                let pending = submitted
+                    .iter()
+                    .filter(|fence| !unsafe { self.raw.get_fence_status(**fence) }.unwrap_or(false))
+                    .map(|fence| {
+                        names
+                            .iter()
+                            .find(|(_, named)| named == fence)
+                            .map_or("external", |(name, _)| name)
+                    })
+                    .collect::<Vec<_>>();
+                error!(
+                    "Frame fences didn't signal in {:?}: {:?}",
+                    self.frame_timeout, pending
+                );
+                Err(BackendError::FrameTimeout(pending))
+            }
+            Err(err) => Err(err.into()),
         }
-        Ok(frame.clone())
     }
 
     fn end_frame(&self, frame: Arc<DeviceFrame>) {
         drop(frame);
-        let mut frame = self.frames[0].lock();
-        let mut frame0 =
-            Arc::get_mut(&mut frame).expect("Can't finish frame - it still hel by something");
-        {
-            let mut frame1 = self.frames[1].lock();
-            let mut frame1 = Arc::get_mut(&mut frame1).unwrap();
-            mem::swap(&mut frame0, &mut frame1);
+        let index = self.frame_index.fetch_add(1, Ordering::Relaxed);
+        if let Some(callback) = &self.on_end_frame {
+            callback(index);
         }
+        // Frames are swapped by pointer, a finished frame may still be held
+        // by a `DeviceFrameGuard`.
+        let mut frame0 = self.frames[0].lock();
+        let mut frame1 = self.frames[1].lock();
+        mem::swap(&mut *frame0, &mut *frame1);
+    }
+
+    pub fn instance(&self) -> &Arc<Instance> {
+        &self.instance
+    }
+
+    pub fn profiler(&self) -> &dyn Profiler {
+        self.profiler.as_ref()
+    }
+
+    /// GPU time of the main command buffer of the last finished frame,
+    /// measured when it's recorded between [`Frame::begin_main_cb`] and
+    /// [`Frame::end_main_cb`]. `None` without timestamps and host query reset.
+    pub fn last_frame_gpu_time(&self) -> Option<Duration> {
+        let ticks = (*self.last_frame_gpu_ticks.lock())?;
+        let period = self.pdevice.properties.limits.timestamp_period as f64;
+        Some(Duration::from_nanos((ticks as f64 * period) as u64))
+    }
+
+    /// Whether frames have timestamp queries, see [`Frame::write_timestamp`].
+    pub(crate) fn has_frame_queries(&self) -> bool {
+        self.frames[0].lock().queries.is_some()
+    }
+
+    /// Index of the current frame, incremented by every finished frame.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index.load(Ordering::Relaxed)
     }
 
     pub fn frame<'a>(&'a self) -> Result<Frame<'a>, BackendError> {
         let frame = self.begin_frame()?;
-        Ok(Frame {
+        let frame = Frame {
             device: self,
             queue: self.main_queue,
             frame,
-        })
+            mesh_pipeline_bound: AtomicBool::new(false),
+            rendering: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            bound_layouts: Mutex::default(),
+        };
+        self.profiler.begin_frame(&frame);
+        Ok(frame)
     }
 
-    pub fn get_sampler(&self, desc: SamplerDesc) -> Option<vk::Sampler> {
-        self.samplers.get(&desc).copied()
+    /// Returns sampler shared by every user of the description, created on
+    /// the first request. Presets of filter, mipmap and address modes are
+    /// created with the device. Cached samplers live as long as the device.
+    pub fn get_or_create_sampler(&self, desc: SamplerDesc) -> Result<vk::Sampler, BackendError> {
+        let mut samplers = self.samplers.lock();
+        if let Some(sampler) = samplers.get(&desc) {
+            return Ok(*sampler);
+        }
+        let sampler = self.create_sampler(desc)?;
+        samplers.insert(desc, sampler);
+        Ok(sampler)
+    }
+
+    /// Anisotropy limit passed to [`SamplerDesc::info`].
+    pub(crate) fn max_sampler_anisotropy(&self) -> f32 {
+        if self.features.sampler_anisotropy == vk::TRUE {
+            self.pdevice.properties.limits.max_sampler_anisotropy
+        } else {
+            0.0
+        }
     }
 
     fn create_samplers(
         device: &ash::Device,
+        max_anisotropy: f32,
     ) -> Result<HashMap<SamplerDesc, vk::Sampler>, BackendError> {
         let texel_filters = [vk::Filter::NEAREST, vk::Filter::LINEAR];
         let mipmap_modes = [
@@ -360,19 +2206,10 @@ impl Device {
         for filter in texel_filters {
             for mipmap_mode in mipmap_modes {
                 for address_mode in address_modes {
-                    let anisotropy = filter == vk::Filter::LINEAR;
-                    let info = vk::SamplerCreateInfo::default()
-                        .mag_filter(filter)
-                        .min_filter(filter)
-                        .mipmap_mode(mipmap_mode)
-                        .address_mode_u(address_mode)
-                        .address_mode_v(address_mode)
-                        .address_mode_w(address_mode)
-                        .max_lod(vk::LOD_CLAMP_NONE)
-                        .max_anisotropy(16.0)
-                        .anisotropy_enable(anisotropy);
+                    let desc = SamplerDesc::new(filter, mipmap_mode, address_mode);
+                    let info = desc.info(max_anisotropy);
                     let sampler = unsafe { device.create_sampler(&info, None) }?;
-                    samplers.insert(SamplerDesc(filter, mipmap_mode, address_mode), sampler);
+                    samplers.insert(desc, sampler);
                 }
             }
         }
@@ -383,24 +2220,76 @@ impl Device {
 impl Drop for Device {
     fn drop(&mut self) {
         unsafe { self.raw.device_wait_idle() }.unwrap();
+        // Closures may drop more objects into the lists, repeat until none
+        // are left.
+        loop {
+            let closures = {
+                let mut memory_allocator = self.memory_allocator.lock();
+                let mut descriptor_allocator = self.descriptor_allocator.lock();
+                let mut closures = self.current_drop_list.lock().cleanup(
+                    &self.raw,
+                    self.cleanup_loaders(),
+                    &mut memory_allocator,
+                    &mut descriptor_allocator,
+                );
+                for frame in &self.frames {
+                    let frame = frame.lock();
+                    closures.append(
+                        frame
+                            .reset(
+                                &self.raw,
+                                self.cleanup_loaders(),
+                                &mut memory_allocator,
+                                &mut descriptor_allocator,
+                            )
+                            .unwrap(),
+                    );
+                }
+                closures
+            };
+            if closures.is_empty() {
+                break;
+            }
+            closures.run();
+        }
         let mut memory_allocator = self.memory_allocator.lock();
         let mut descriptor_allocator = self.descriptor_allocator.lock();
-        self.current_drop_list.lock().cleanup(
-            &self.raw,
-            &mut memory_allocator,
-            &mut descriptor_allocator,
-        );
         for frame in &self.frames {
-            let frame = frame.lock();
-            frame
-                .reset(&self.raw, &mut memory_allocator, &mut descriptor_allocator)
-                .unwrap();
-            frame.free(&self.raw);
+            frame.lock().free(&self.raw);
+        }
+        for (_, sampler) in self.samplers.get_mut().drain() {
+            unsafe { self.raw.destroy_sampler(sampler, None) };
         }
+        let (immediate_pool, immediate_cb) = self.immediate.get_mut();
+        immediate_cb.free();
+        unsafe { self.raw.destroy_command_pool(*immediate_pool, None) };
         unsafe {
             memory_allocator.cleanup(AshMemoryDevice::wrap(&self.raw));
             descriptor_allocator.cleanup(AshDescriptorDevice::wrap(&self.raw));
         }
-        unsafe { self.raw.destroy_device(None) };
+        unsafe { ManuallyDrop::drop(&mut self.profiler) };
+        unsafe {
+            self.raw
+                .destroy_device(self.instance.allocation_callbacks())
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::NameBuffer;
+
+    #[test]
+    fn long_names_spill_to_heap() {
+        let mut buffer = NameBuffer::default();
+        write!(buffer, "ShadowMap[{}]", 3).unwrap();
+        assert!(matches!(buffer, NameBuffer::Inline(..)));
+        assert_eq!(buffer.bytes(), b"ShadowMap[3]");
+        let long = "x".repeat(200);
+        write!(buffer, "{long}").unwrap();
+        assert!(matches!(buffer, NameBuffer::Heap(_)));
+        assert_eq!(buffer.bytes().len(), 212);
     }
 }
@@ -0,0 +1,506 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    mem,
+    sync::{Arc, atomic::Ordering},
+};
+
+use ash::vk;
+use egui::{
+    ClippedPrimitive, ImageData, TextureFilter, TextureId, TextureOptions, TextureWrapMode,
+    TexturesDelta,
+    epaint::{Primitive, Vertex},
+};
+use gpu_descriptor::DescriptorTotalCount;
+use log::warn;
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError, DescriptorSet,
+    vulkan::{
+        BufferDesc, BufferSuballocator, Device, Frame, GraphicsPipelineBuilder, Image, ImageDesc,
+        ImageState, SamplerDesc, VertexLayout, as_bytes, spirv_words,
+    },
+};
+
+const SHADER: &[u8] = include_bytes!("shaders/egui.spv");
+
+/// Vertices and indices of a frame, bytes.
+const GEOMETRY_SIZE: u64 = 4 << 20;
+
+struct Texture {
+    image: Option<Image>,
+    view: vk::ImageView,
+    set: Option<DescriptorSet>,
+    device: Arc<Device>,
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        let owns_view = self.image.is_some();
+        let view = self.view;
+        let set = self.set.take();
+        self.device.with_drop_list(|drop_list| {
+            if owns_view {
+                drop_list.drop_image_view(view);
+            }
+            if let Some(set) = set {
+                drop_list.drop_descriptor_set(set);
+            }
+        });
+    }
+}
+
+/// Renders egui output with premultiplied alpha blending. Both sRGB and UNORM
+/// targets get gamma correct colors. Every frame calls
+/// [`EguiRenderer::update_textures`], [`EguiRenderer::render`] and
+/// [`EguiRenderer::free_textures`] in this order.
+pub struct EguiRenderer {
+    shader: vk::ShaderModule,
+    set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    pipelines: Mutex<HashMap<vk::Format, vk::Pipeline>>,
+    textures: Mutex<HashMap<TextureId, Texture>>,
+    next_user_texture: Mutex<u64>,
+    geometry: BufferSuballocator,
+    device: Arc<Device>,
+}
+
+impl Debug for EguiRenderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EguiRenderer")
+            .field("layout", &self.layout)
+            .field("pipelines", &self.pipelines.lock())
+            .finish()
+    }
+}
+
+fn sampler_desc(options: TextureOptions) -> SamplerDesc {
+    let filter = match options.magnification {
+        TextureFilter::Nearest => vk::Filter::NEAREST,
+        TextureFilter::Linear => vk::Filter::LINEAR,
+    };
+    let address_mode = match options.wrap_mode {
+        TextureWrapMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        TextureWrapMode::Repeat | TextureWrapMode::MirroredRepeat => vk::SamplerAddressMode::REPEAT,
+    };
+    SamplerDesc::new(filter, vk::SamplerMipmapMode::NEAREST, address_mode)
+}
+
+/// Converts clip rect in points into scissor, `None` if nothing is visible.
+fn scissor_rect(
+    clip_rect: egui::Rect,
+    pixels_per_point: f32,
+    extent: vk::Extent2D,
+) -> Option<vk::Rect2D> {
+    let min_x = (clip_rect.min.x * pixels_per_point).round().max(0.0) as u32;
+    let min_y = (clip_rect.min.y * pixels_per_point).round().max(0.0) as u32;
+    let max_x = ((clip_rect.max.x * pixels_per_point).round() as u32).min(extent.width);
+    let max_y = ((clip_rect.max.y * pixels_per_point).round() as u32).min(extent.height);
+    if min_x >= max_x || min_y >= max_y {
+        return None;
+    }
+    Some(vk::Rect2D {
+        offset: vk::Offset2D {
+            x: min_x as i32,
+            y: min_y as i32,
+        },
+        extent: vk::Extent2D {
+            width: max_x - min_x,
+            height: max_y - min_y,
+        },
+    })
+}
+
+fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+impl EguiRenderer {
+    pub fn new(device: &Arc<Device>) -> Result<Self, BackendError> {
+        let shader = device.create_shader_module(&spirv_words(SHADER))?;
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
+        let info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+        let set_layout = unsafe { device.raw.create_descriptor_set_layout(&info, None) }?;
+        let set_layouts = [set_layout];
+        let push_constants = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .size(8)];
+        let info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constants);
+        let layout = unsafe { device.raw.create_pipeline_layout(&info, None) }?;
+        Ok(Self {
+            shader,
+            set_layout,
+            layout,
+            pipelines: Mutex::default(),
+            textures: Mutex::default(),
+            next_user_texture: Mutex::new(0),
+            geometry: BufferSuballocator::new(
+                device,
+                GEOMETRY_SIZE,
+                vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER,
+            )?,
+            device: device.clone(),
+        })
+    }
+
+    /// Makes image view owned by the caller available to `egui::Image`. The
+    /// view must be in `SHADER_READ_ONLY_OPTIMAL` layout when drawn and stay
+    /// alive until [`Self::free_user_texture`].
+    pub fn register_user_texture(
+        &self,
+        view: vk::ImageView,
+        options: TextureOptions,
+    ) -> Result<TextureId, BackendError> {
+        let set = self.allocate_set(view, options)?;
+        let mut next = self.next_user_texture.lock();
+        let id = TextureId::User(*next);
+        *next += 1;
+        self.textures.lock().insert(
+            id,
+            Texture {
+                image: None,
+                view,
+                set: Some(set),
+                device: self.device.clone(),
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn free_user_texture(&self, id: TextureId) {
+        self.textures.lock().remove(&id);
+    }
+
+    /// Records texture uploads of `textures.set` into the main command buffer
+    /// of the frame, outside of rendering and before [`Self::render`].
+    pub fn update_textures(
+        &self,
+        frame: &Frame,
+        textures: &TexturesDelta,
+    ) -> Result<(), BackendError> {
+        debug_assert!(
+            !frame.rendering.load(Ordering::Relaxed),
+            "egui textures can't be updated while rendering"
+        );
+        for (id, deltas) in &textures.set {
+            for delta in deltas {
+                self.update_texture(frame, *id, delta)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases textures of `textures.free`, call after the last
+    /// [`Self::render`] using them. They're destroyed once the GPU is done
+    /// with frames that may use them.
+    pub fn free_textures(&self, textures: &TexturesDelta) {
+        let mut all_textures = self.textures.lock();
+        for id in &textures.free {
+            all_textures.remove(id);
+        }
+    }
+
+    /// Records draw commands into `cb`, which must be inside dynamic rendering
+    /// into a `format` color attachment of `extent` size. Vertices and indices
+    /// are written into buffers of the current frame, which hold 4 MiB.
+    pub fn render(
+        &self,
+        cb: vk::CommandBuffer,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        pixels_per_point: f32,
+        primitives: &[ClippedPrimitive],
+    ) -> Result<(), BackendError> {
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(());
+        }
+        let meshes = primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                Primitive::Mesh(mesh) if !mesh.indices.is_empty() => {
+                    Some((primitive.clip_rect, mesh))
+                }
+                Primitive::Mesh(_) => None,
+                Primitive::Callback(_) => {
+                    warn!("egui paint callbacks aren't supported");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if meshes.is_empty() {
+            return Ok(());
+        }
+        // Indices go first, so their offset keeps the allocation alignment.
+        let mut data = Vec::new();
+        for (_, mesh) in &meshes {
+            data.extend_from_slice(as_bytes(&mesh.indices));
+        }
+        let indices_size = data.len() as u64;
+        for (_, mesh) in &meshes {
+            data.extend_from_slice(as_bytes(&mesh.vertices));
+        }
+        let geometry = self.geometry.alloc_bytes(&data)?;
+
+        let device = &self.device.raw;
+        let pipeline = self.pipeline(format)?;
+        let viewport = vk::Viewport::default()
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .max_depth(1.0);
+        let screen = [
+            extent.width as f32 / pixels_per_point,
+            extent.height as f32 / pixels_per_point,
+        ];
+        unsafe {
+            device.cmd_bind_pipeline(cb, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_set_viewport(cb, 0, &[viewport]);
+            device.cmd_push_constants(
+                cb,
+                self.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                as_bytes(&screen),
+            );
+            device.cmd_bind_vertex_buffers(
+                cb,
+                0,
+                &[geometry.buffer],
+                &[geometry.offset + indices_size],
+            );
+            device.cmd_bind_index_buffer(
+                cb,
+                geometry.buffer,
+                geometry.offset,
+                vk::IndexType::UINT32,
+            );
+        }
+
+        let textures = self.textures.lock();
+        let mut base_vertex = 0;
+        let mut first_index = 0;
+        for (clip_rect, mesh) in meshes {
+            let index_count = mesh.indices.len() as u32;
+            let vertex_count = mesh.vertices.len() as i32;
+            let texture = textures.get(&mesh.texture_id);
+            let scissor = scissor_rect(clip_rect, pixels_per_point, extent);
+            if let (Some(texture), Some(scissor)) = (texture, scissor) {
+                unsafe {
+                    device.cmd_set_scissor(cb, 0, &[scissor]);
+                    device.cmd_bind_descriptor_sets(
+                        cb,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.layout,
+                        0,
+                        &[*texture.set.as_ref().unwrap().raw()],
+                        &[],
+                    );
+                    device.cmd_draw_indexed(cb, index_count, 1, first_index, base_vertex, 0);
+                }
+            }
+            base_vertex += vertex_count;
+            first_index += index_count;
+        }
+        Ok(())
+    }
+
+    fn pipeline(&self, format: vk::Format) -> Result<vk::Pipeline, BackendError> {
+        let mut pipelines = self.pipelines.lock();
+        if let Some(pipeline) = pipelines.get(&format) {
+            return Ok(*pipeline);
+        }
+        let stride = mem::size_of::<Vertex>() as u32;
+        let vertex_layout = VertexLayout::default()
+            .binding(0, stride, vk::VertexInputRate::VERTEX)
+            .attribute(
+                0,
+                0,
+                vk::Format::R32G32_SFLOAT,
+                mem::offset_of!(Vertex, pos) as u32,
+            )
+            .attribute(
+                1,
+                0,
+                vk::Format::R32G32_SFLOAT,
+                mem::offset_of!(Vertex, uv) as u32,
+            )
+            .attribute(
+                2,
+                0,
+                vk::Format::R8G8B8A8_UNORM,
+                mem::offset_of!(Vertex, color) as u32,
+            );
+        let fragment = if is_srgb(format) {
+            c"fs_linear"
+        } else {
+            c"fs_gamma"
+        };
+        let pipeline = GraphicsPipelineBuilder::new(self.layout)
+            .vertex_shader(self.shader, c"vs_main")
+            .fragment_shader(self.shader, fragment)
+            .vertex_layout(vertex_layout)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .color_formats(&[format])
+            .blend(true)
+            .build(&self.device, None)?;
+        pipelines.insert(format, pipeline);
+        Ok(pipeline)
+    }
+
+    fn allocate_set(
+        &self,
+        view: vk::ImageView,
+        options: TextureOptions,
+    ) -> Result<DescriptorSet, BackendError> {
+        let set = self.device.allocate_descriptor_set(
+            self.set_layout,
+            DescriptorTotalCount {
+                sampled_image: 1,
+                sampler: 1,
+                ..Default::default()
+            },
+            false,
+        )?;
+        let sampler = self.device.get_or_create_sampler(sampler_desc(options))?;
+        let image_info = [vk::DescriptorImageInfo::default()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let sampler_info = [vk::DescriptorImageInfo::default().sampler(sampler)];
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set.raw())
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_info),
+            vk::WriteDescriptorSet::default()
+                .dst_set(*set.raw())
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&sampler_info),
+        ];
+        unsafe { self.device.raw.update_descriptor_sets(&writes, &[]) };
+        Ok(set)
+    }
+
+    fn update_texture(
+        &self,
+        frame: &Frame,
+        id: TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) -> Result<(), BackendError> {
+        let ImageData::Color(image) = &delta.image;
+        let [width, height] = image.size;
+        let data = as_bytes(&image.pixels);
+        let mut staging = self.device.create_buffer(
+            BufferDesc::new(data.len() as u64, vk::BufferUsageFlags::TRANSFER_SRC)
+                .memory(gpu_alloc::UsageFlags::HOST_ACCESS | gpu_alloc::UsageFlags::UPLOAD),
+        )?;
+        staging.write(0, data)?;
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_offset(vk::Offset3D {
+                x: x as i32,
+                y: y as i32,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: width as u32,
+                height: height as u32,
+                depth: 1,
+            });
+        let copy = |image: &Image, from: ImageState| {
+            let range = image.subresource_range();
+            frame.transition_image_layout(image.raw, range, from, ImageState::TRANSFER_DST);
+            unsafe {
+                self.device.raw.cmd_copy_buffer_to_image(
+                    frame.main_cb().raw(),
+                    staging.raw,
+                    image.raw,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                )
+            };
+            frame.transition_image_layout(
+                image.raw,
+                range,
+                ImageState::TRANSFER_DST,
+                ImageState::SHADER_READ,
+            );
+        };
+        // Staging buffer is released through the drop list when it goes out
+        // of scope, after the frame is done with the copy.
+        if delta.pos.is_some() {
+            let textures = self.textures.lock();
+            let Some(image) = textures.get(&id).and_then(|texture| texture.image.as_ref()) else {
+                warn!("Partial update of unknown egui texture {id:?}");
+                return Ok(());
+            };
+            // Frames still in flight may sample this image, the barriers
+            // order the copy after them.
+            copy(image, ImageState::SHADER_READ);
+            return Ok(());
+        }
+
+        let image = self.device.create_image(ImageDesc::texture_2d(
+            vk::Format::R8G8B8A8_SRGB,
+            width as u32,
+            height as u32,
+        ))?;
+        copy(&image, ImageState::UNDEFINED);
+        let view = image.create_view()?;
+        let mut texture = Texture {
+            image: Some(image),
+            view,
+            set: None,
+            device: self.device.clone(),
+        };
+        texture.set = Some(self.allocate_set(view, delta.options)?);
+        // Replaced texture is released through the drop list, so frames in
+        // flight can finish with it.
+        self.textures.lock().insert(id, texture);
+        Ok(())
+    }
+}
+
+impl Drop for EguiRenderer {
+    fn drop(&mut self) {
+        self.textures.get_mut().clear();
+        let shader = self.shader;
+        let pipelines = self
+            .pipelines
+            .get_mut()
+            .drain()
+            .map(|(_, pipeline)| pipeline);
+        self.device.with_drop_list(|drop_list| {
+            pipelines.for_each(|pipeline| drop_list.drop_pipeline(pipeline));
+            drop_list.drop_shader_module(shader);
+        });
+        unsafe {
+            self.device.raw.destroy_pipeline_layout(self.layout, None);
+            self.device
+                .raw
+                .destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use ash::vk;
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError, GpuMemory,
+    vulkan::{Device, ImageState, cmd_transition_image},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageDesc {
+    pub ty: vk::ImageType,
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+    pub usage: vk::ImageUsageFlags,
+    pub flags: vk::ImageCreateFlags,
+    pub tiling: vk::ImageTiling,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+    /// Residency priority from 0.0 to 1.0, see
+    /// [`Device::allocate_memory_with_priority`].
+    pub priority: Option<f32>,
+}
+
+impl ImageDesc {
+    pub fn new(ty: vk::ImageType, format: vk::Format, extent: vk::Extent3D) -> Self {
+        Self {
+            ty,
+            format,
+            extent,
+            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            flags: vk::ImageCreateFlags::empty(),
+            tiling: vk::ImageTiling::OPTIMAL,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            priority: None,
+        }
+    }
+
+    pub fn texture_2d(format: vk::Format, width: u32, height: u32) -> Self {
+        Self::new(
+            vk::ImageType::TYPE_2D,
+            format,
+            vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+        )
+    }
+
+    pub fn usage(mut self, value: vk::ImageUsageFlags) -> Self {
+        self.usage = value;
+        self
+    }
+
+    pub fn flags(mut self, value: vk::ImageCreateFlags) -> Self {
+        self.flags = value;
+        self
+    }
+
+    pub fn tiling(mut self, value: vk::ImageTiling) -> Self {
+        self.tiling = value;
+        self
+    }
+
+    pub fn mip_levels(mut self, value: u32) -> Self {
+        self.mip_levels = value;
+        self
+    }
+
+    pub fn array_layers(mut self, value: u32) -> Self {
+        self.array_layers = value;
+        self
+    }
+
+    pub fn samples(mut self, value: vk::SampleCountFlags) -> Self {
+        self.samples = value;
+        self
+    }
+
+    pub fn priority(mut self, value: f32) -> Self {
+        self.priority = Some(value);
+        self
+    }
+
+    pub fn mip_extent(&self, level: u32) -> vk::Extent3D {
+        vk::Extent3D {
+            width: (self.extent.width >> level).max(1),
+            height: (self.extent.height >> level).max(1),
+            depth: (self.extent.depth >> level).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageViewDesc {
+    pub ty: vk::ImageViewType,
+    pub format: vk::Format,
+    pub aspect: vk::ImageAspectFlags,
+    pub base_mip_level: u32,
+    pub mip_levels: u32,
+    pub base_array_layer: u32,
+    pub array_layers: u32,
+}
+
+impl ImageViewDesc {
+    pub fn ty(mut self, value: vk::ImageViewType) -> Self {
+        self.ty = value;
+        self
+    }
+
+    pub fn format(mut self, value: vk::Format) -> Self {
+        self.format = value;
+        self
+    }
+
+    pub fn aspect(mut self, value: vk::ImageAspectFlags) -> Self {
+        self.aspect = value;
+        self
+    }
+
+    pub fn mip_levels(mut self, base: u32, count: u32) -> Self {
+        self.base_mip_level = base;
+        self.mip_levels = count;
+        self
+    }
+
+    pub fn array_layers(mut self, base: u32, count: u32) -> Self {
+        self.base_array_layer = base;
+        self.array_layers = count;
+        self
+    }
+
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(self.aspect)
+            .base_mip_level(self.base_mip_level)
+            .level_count(self.mip_levels)
+            .base_array_layer(self.base_array_layer)
+            .layer_count(self.array_layers)
+    }
+}
+
+pub struct Image {
+    pub raw: vk::Image,
+    pub desc: ImageDesc,
+    memory: Option<GpuMemory>,
+    views: Mutex<HashMap<ImageViewDesc, vk::ImageView>>,
+    device: Arc<Device>,
+}
+
+impl Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image")
+            .field("raw", &self.raw)
+            .field("desc", &self.desc)
+            .finish()
+    }
+}
+
+impl Image {
+    /// Creates view covering the whole image. Caller owns the view and should
+    /// release it through the drop list.
+    pub fn create_view(&self) -> Result<vk::ImageView, BackendError> {
+        self.create_view_with_desc(&self.view_desc())
+    }
+
+    /// Returns view owned by the image, views with the same description are
+    /// created once and live until the image is dropped.
+    pub fn view(&self, desc: ImageViewDesc) -> Result<vk::ImageView, BackendError> {
+        let mut views = self.views.lock();
+        if let Some(view) = views.get(&desc) {
+            return Ok(*view);
+        }
+        let view = self.create_view_with_desc(&desc)?;
+        views.insert(desc, view);
+        Ok(view)
+    }
+
+    fn create_view_with_desc(&self, desc: &ImageViewDesc) -> Result<vk::ImageView, BackendError> {
+        self.device
+            .create_image_view_with_swizzle(self.raw, desc, vk::ComponentMapping::default())
+    }
+
+    /// Description of the view covering the whole image.
+    pub fn view_desc(&self) -> ImageViewDesc {
+        let cube = self
+            .desc
+            .flags
+            .contains(vk::ImageCreateFlags::CUBE_COMPATIBLE);
+        let ty = match self.desc.ty {
+            vk::ImageType::TYPE_1D if self.desc.array_layers > 1 => {
+                vk::ImageViewType::TYPE_1D_ARRAY
+            }
+            vk::ImageType::TYPE_1D => vk::ImageViewType::TYPE_1D,
+            vk::ImageType::TYPE_3D => vk::ImageViewType::TYPE_3D,
+            _ if cube && self.desc.array_layers > 6 => vk::ImageViewType::CUBE_ARRAY,
+            _ if cube => vk::ImageViewType::CUBE,
+            _ if self.desc.array_layers > 1 => vk::ImageViewType::TYPE_2D_ARRAY,
+            _ => vk::ImageViewType::TYPE_2D,
+        };
+        ImageViewDesc {
+            ty,
+            format: self.desc.format,
+            aspect: self.aspect(),
+            base_mip_level: 0,
+            mip_levels: self.desc.mip_levels,
+            base_array_layer: 0,
+            array_layers: self.desc.array_layers,
+        }
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.desc.format
+    }
+
+    pub fn extent(&self) -> vk::Extent3D {
+        self.desc.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.desc.mip_levels
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.desc.array_layers
+    }
+
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.desc.samples
+    }
+
+    pub fn aspect(&self) -> vk::ImageAspectFlags {
+        format_aspect(self.desc.format)
+    }
+
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(self.aspect())
+            .base_mip_level(0)
+            .level_count(self.desc.mip_levels)
+            .base_array_layer(0)
+            .layer_count(self.desc.array_layers)
+    }
+}
+
+pub fn format_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+impl Device {
+    /// Creates view of `image` with remapped components, e.g. to read single
+    /// channel formats as grayscale. Caller owns the view and should release
+    /// it through the drop list.
+    pub fn create_image_view_with_swizzle(
+        &self,
+        image: vk::Image,
+        desc: &ImageViewDesc,
+        swizzle: vk::ComponentMapping,
+    ) -> Result<vk::ImageView, BackendError> {
+        let info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(desc.ty)
+            .format(desc.format)
+            .components(swizzle)
+            .subresource_range(desc.subresource_range());
+        Ok(unsafe { self.raw.create_image_view(&info, None) }?)
+    }
+
+    /// Creates `TYPE_2D_ARRAY` view of `range`, even if it covers a single
+    /// layer, so shaders declared with array samplers can always use it.
+    /// Caller owns the view and should release it through the drop list.
+    pub fn create_image_view_array(
+        &self,
+        image: vk::Image,
+        format: vk::Format,
+        range: vk::ImageSubresourceRange,
+    ) -> Result<vk::ImageView, BackendError> {
+        let desc = ImageViewDesc {
+            ty: vk::ImageViewType::TYPE_2D_ARRAY,
+            format,
+            aspect: range.aspect_mask,
+            base_mip_level: range.base_mip_level,
+            mip_levels: range.level_count,
+            base_array_layer: range.base_array_layer,
+            array_layers: range.layer_count,
+        };
+        self.create_image_view_with_swizzle(image, &desc, vk::ComponentMapping::default())
+    }
+
+    pub fn create_image(self: &Arc<Self>, desc: ImageDesc) -> Result<Image, BackendError> {
+        let max_dim = desc
+            .extent
+            .width
+            .max(desc.extent.height)
+            .max(desc.extent.depth);
+        let max_mips = max_dim.checked_ilog2().map_or(0, |log| log + 1);
+        if desc.mip_levels == 0 || desc.mip_levels > max_mips {
+            return Err(BackendError::InvalidArgument(format!(
+                "{} mip levels requested, image of {:?} has up to {max_mips}",
+                desc.mip_levels, desc.extent
+            )));
+        }
+        if desc.array_layers == 0 {
+            return Err(BackendError::InvalidArgument(
+                "Image must have at least one array layer".into(),
+            ));
+        }
+        let info = vk::ImageCreateInfo::default()
+            .image_type(desc.ty)
+            .format(desc.format)
+            .extent(desc.extent)
+            .usage(desc.usage)
+            .flags(desc.flags)
+            .tiling(desc.tiling)
+            .mip_levels(desc.mip_levels)
+            .array_layers(desc.array_layers)
+            .samples(desc.samples)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let raw = unsafe { self.raw.create_image(&info, None) }?;
+        let requirements = unsafe { self.raw.get_image_memory_requirements(raw) };
+        let request = gpu_alloc::Request {
+            size: requirements.size,
+            align_mask: requirements.alignment - 1,
+            usage: gpu_alloc::UsageFlags::FAST_DEVICE_ACCESS,
+            memory_types: requirements.memory_type_bits,
+        };
+        let memory = match self.allocate_memory_with_priority(request, desc.priority) {
+            Ok(memory) => memory,
+            Err(err) => {
+                unsafe { self.raw.destroy_image(raw, None) };
+                return Err(err);
+            }
+        };
+        let bind_result = unsafe {
+            self.raw
+                .bind_image_memory(raw, *memory.memory(), memory.offset())
+        };
+        let image = Image {
+            raw,
+            desc,
+            memory: Some(memory),
+            views: Mutex::default(),
+            device: self.clone(),
+        };
+        bind_result?;
+        Ok(image)
+    }
+
+    /// Checks that device can create and sample image with such description.
+    pub fn image_format_properties(
+        &self,
+        desc: &ImageDesc,
+    ) -> Result<vk::ImageFormatProperties, BackendError> {
+        let properties = unsafe {
+            self.instance
+                .raw
+                .get_physical_device_image_format_properties(
+                    self.pdevice.raw,
+                    desc.format,
+                    desc.ty,
+                    desc.tiling,
+                    desc.usage,
+                    desc.flags,
+                )
+        }
+        .map_err(|err| match err {
+            vk::Result::ERROR_FORMAT_NOT_SUPPORTED => BackendError::UnsupportedFormat(desc.format),
+            err => err.into(),
+        })?;
+        if desc.extent.width > properties.max_extent.width
+            || desc.extent.height > properties.max_extent.height
+            || desc.extent.depth > properties.max_extent.depth
+            || desc.mip_levels > properties.max_mip_levels
+            || desc.array_layers > properties.max_array_layers
+            || !properties.sample_counts.contains(desc.samples)
+        {
+            return Err(BackendError::UnsupportedImage(*desc));
+        }
+        Ok(properties)
+    }
+
+    pub fn format_features(&self, format: vk::Format) -> vk::FormatFeatureFlags {
+        unsafe {
+            self.instance
+                .raw
+                .get_physical_device_format_properties(self.pdevice.raw, format)
+        }
+        .optimal_tiling_features
+    }
+
+    /// First of `candidates` usable as a sampled color attachment with optimal
+    /// tiling, and as a storage image if `storage` is set. Doesn't depend on
+    /// surface formats, meant for offscreen targets.
+    pub fn preferred_render_target_format(
+        &self,
+        candidates: &[vk::Format],
+        storage: bool,
+    ) -> Option<vk::Format> {
+        let mut required =
+            vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+        if storage {
+            required |= vk::FormatFeatureFlags::STORAGE_IMAGE;
+        }
+        candidates
+            .iter()
+            .copied()
+            .find(|format| self.format_features(*format).contains(required))
+    }
+
+    /// Uploads data through a staging buffer in a single submit. The whole
+    /// image is left in `SHADER_READ_ONLY_OPTIMAL` layout.
+    pub fn upload_image(
+        self: &Arc<Self>,
+        image: &Image,
+        data: &[u8],
+        regions: &[vk::BufferImageCopy],
+    ) -> Result<(), BackendError> {
+        let range = image.subresource_range();
+        self.upload_with_staging(data, |device, cb, staging| unsafe {
+            cmd_transition_image(
+                device,
+                cb,
+                image.raw,
+                range,
+                ImageState::UNDEFINED,
+                ImageState::TRANSFER_DST,
+            );
+            device.cmd_copy_buffer_to_image(
+                cb,
+                staging,
+                image.raw,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                regions,
+            );
+            cmd_transition_image(
+                device,
+                cb,
+                image.raw,
+                range,
+                ImageState::TRANSFER_DST,
+                ImageState::SHADER_READ,
+            );
+        })
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        self.device.with_drop_list(|drop_list| {
+            for (_, view) in self.views.get_mut().drain() {
+                drop_list.drop_image_view(view);
+            }
+            drop_list.drop_image(self.raw);
+        });
+        if let Some(memory) = self.memory.take() {
+            self.device.free_memory(memory);
+        }
+    }
+}
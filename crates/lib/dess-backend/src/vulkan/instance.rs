@@ -1,7 +1,10 @@
 use std::{
-    ffi::{CString, c_void},
+    ffi::{CStr, c_void},
     fmt::Debug,
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
 };
 
 use ash::vk;
@@ -9,12 +12,32 @@ use log::{error, info, trace, warn};
 
 use crate::BackendError;
 
+const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
 pub struct Instance {
     pub(crate) entry: ash::Entry,
     pub raw: ash::Instance,
     debug: Option<(ash::ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
+    // Boxed, the messenger keeps a pointer to it.
+    debug_messages: Box<DebugMessageCounts>,
+    pub(crate) headless_surface: bool,
+    allocation_callbacks: Option<AllocationCallbacks>,
+}
+
+#[derive(Debug, Default)]
+struct DebugMessageCounts {
+    errors: AtomicU32,
+    warnings: AtomicU32,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct AllocationCallbacks(vk::AllocationCallbacks<'static>);
+
+// Safety: `InstanceBuilder::allocation_callbacks` requires callbacks to be
+// callable from any thread.
+unsafe impl Send for AllocationCallbacks {}
+unsafe impl Sync for AllocationCallbacks {}
+
 impl Debug for Instance {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Instance")
@@ -25,13 +48,59 @@ impl Debug for Instance {
 
 #[derive(Debug, Default)]
 pub struct InstanceBuilder<'a> {
-    debug: bool,
+    validation: bool,
+    debug_utils: bool,
+    synchronization_validation: bool,
+    require_debug: bool,
+    headless_surface: bool,
     title: Option<&'a str>,
+    debug_message_types: Option<vk::DebugUtilsMessageTypeFlagsEXT>,
+    allocation_callbacks: Option<vk::AllocationCallbacks<'static>>,
 }
 
 impl<'a> InstanceBuilder<'a> {
-    pub fn debug(mut self, value: bool) -> Self {
-        self.debug = value;
+    /// Shorthand for enabling both validation and debug utils.
+    pub fn debug(self, value: bool) -> Self {
+        self.validation(value).debug_utils(value)
+    }
+
+    /// Enables `VK_LAYER_KHRONOS_validation`.
+    pub fn validation(mut self, value: bool) -> Self {
+        self.validation = value;
+        self
+    }
+
+    /// Enables debug messenger, object names and labels.
+    pub fn debug_utils(mut self, value: bool) -> Self {
+        self.debug_utils = value;
+        self
+    }
+
+    /// Enables synchronization validation, which reports missing and
+    /// redundant barriers. Has effect only with validation.
+    pub fn synchronization_validation(mut self, value: bool) -> Self {
+        self.synchronization_validation = value;
+        self
+    }
+
+    /// Fails instance creation when validation layer or debug utils are
+    /// requested but not installed. By default they are skipped with a
+    /// warning.
+    pub fn require_debug(mut self, value: bool) -> Self {
+        self.require_debug = value;
+        self
+    }
+
+    /// Enables `VK_EXT_headless_surface` for [`Instance::create_headless_surface`].
+    pub fn headless_surface(mut self, value: bool) -> Self {
+        self.headless_surface = value;
+        self
+    }
+
+    /// Message types reported by the debug messenger, all but device address
+    /// binding ones by default.
+    pub fn debug_message_types(mut self, value: vk::DebugUtilsMessageTypeFlagsEXT) -> Self {
+        self.debug_message_types = Some(value);
         self
     }
 
@@ -40,48 +109,112 @@ impl<'a> InstanceBuilder<'a> {
         self
     }
 
-    fn extensions(&self) -> Vec<*const i8> {
-        let mut names = vec![vk::KHR_GET_PHYSICAL_DEVICE_PROPERTIES2_NAME.as_ptr()];
-        if self.debug {
-            names.push(vk::EXT_DEBUG_UTILS_NAME.as_ptr());
-        }
-        names
+    /// Host allocation callbacks for the instance, devices, surfaces and
+    /// swapchains created from it.
+    ///
+    /// # Safety
+    ///
+    /// Callbacks and user data must stay valid until the instance and every
+    /// object created from it are destroyed, and must be callable from any
+    /// thread.
+    pub unsafe fn allocation_callbacks(
+        mut self,
+        callbacks: vk::AllocationCallbacks<'static>,
+    ) -> Self {
+        self.allocation_callbacks = Some(callbacks);
+        self
     }
 
-    fn layers(&self) -> Vec<CString> {
-        let mut names = Vec::new();
-        if self.debug {
-            names.push(CString::new("VK_LAYER_KHRONOS_validation").unwrap());
+    /// Drops validation and debug utils that aren't installed, unless they
+    /// are required.
+    fn available_debug(&self, entry: &ash::Entry) -> Result<(bool, bool), BackendError> {
+        let mut validation = self.validation;
+        if validation {
+            let layers = unsafe { entry.enumerate_instance_layer_properties() }?;
+            let present = layers
+                .iter()
+                .any(|layer| layer.layer_name_as_c_str() == Ok(VALIDATION_LAYER));
+            if !present && !self.require_debug {
+                warn!("{VALIDATION_LAYER:?} isn't installed, validation is disabled");
+                validation = false;
+            }
+        }
+        let mut debug_utils = self.debug_utils;
+        if debug_utils {
+            let mut extensions = unsafe { entry.enumerate_instance_extension_properties(None) }?;
+            // Validation layer provides debug utils too.
+            if validation {
+                extensions.extend(
+                    unsafe {
+                        entry.enumerate_instance_extension_properties(Some(VALIDATION_LAYER))
+                    }
+                    .unwrap_or_default(),
+                );
+            }
+            let present = extensions.iter().any(|extension| {
+                extension.extension_name_as_c_str() == Ok(vk::EXT_DEBUG_UTILS_NAME)
+            });
+            if !present && !self.require_debug {
+                warn!(
+                    "{:?} isn't available, debug utils are disabled",
+                    vk::EXT_DEBUG_UTILS_NAME
+                );
+                debug_utils = false;
+            }
         }
-        names
+        Ok((validation, debug_utils))
     }
 
     pub fn build(self) -> Result<Arc<Instance>, BackendError> {
         let entry = unsafe { ash::Entry::load()? };
-        let extension_names = self.extensions();
-        let layer_names = self.layers().iter().map(|x| x.as_ptr()).collect::<Vec<_>>();
+        let (validation, debug_utils) = self.available_debug(&entry)?;
+        let mut extension_names = vec![vk::KHR_GET_PHYSICAL_DEVICE_PROPERTIES2_NAME.as_ptr()];
+        if debug_utils {
+            extension_names.push(vk::EXT_DEBUG_UTILS_NAME.as_ptr());
+        }
+        if self.headless_surface {
+            extension_names.push(ash::khr::surface::NAME.as_ptr());
+            extension_names.push(ash::ext::headless_surface::NAME.as_ptr());
+        }
+        let mut layer_names = Vec::new();
+        if validation {
+            layer_names.push(VALIDATION_LAYER.as_ptr());
+        }
+        let synchronization_validation = validation && self.synchronization_validation;
+        if synchronization_validation {
+            // Provided by the validation layer.
+            extension_names.push(ash::ext::validation_features::NAME.as_ptr());
+        }
+        let enabled_features = [vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION];
+        let mut validation_features =
+            vk::ValidationFeaturesEXT::default().enabled_validation_features(&enabled_features);
         let info = vk::ApplicationInfo::default().api_version(vk::make_api_version(0, 1, 3, 0));
-        let desc = vk::InstanceCreateInfo::default()
+        let mut desc = vk::InstanceCreateInfo::default()
             .application_info(&info)
             .enabled_layer_names(&layer_names)
             .enabled_extension_names(&extension_names);
-        let instance = unsafe { entry.create_instance(&desc, None) }?;
+        if synchronization_validation {
+            desc = desc.push_next(&mut validation_features);
+        }
+        let instance = unsafe { entry.create_instance(&desc, self.allocation_callbacks.as_ref()) }?;
         info!("Created a Vulkan instance");
 
-        let debug = if self.debug {
+        let debug_messages = Box::<DebugMessageCounts>::default();
+        let debug = if debug_utils {
             let utils = ash::ext::debug_utils::Instance::new(&entry, &instance);
             let info = vk::DebugUtilsMessengerCreateInfoEXT::default()
-                .message_type(
+                .message_type(self.debug_message_types.unwrap_or(
                     vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
                         | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
                         | vk::DebugUtilsMessageTypeFlagsEXT::GENERAL,
-                )
+                ))
                 .message_severity(
                     vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
                         | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
                         | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
                 )
-                .pfn_user_callback(Some(vulkan_debug_callback));
+                .pfn_user_callback(Some(vulkan_debug_callback))
+                .user_data(&*debug_messages as *const DebugMessageCounts as *mut c_void);
             let messanger = unsafe { utils.create_debug_utils_messenger(&info, None) }?;
             Some((utils, messanger))
         } else {
@@ -92,12 +225,21 @@ impl<'a> InstanceBuilder<'a> {
             entry,
             raw: instance,
             debug,
+            debug_messages,
+            headless_surface: self.headless_surface,
+            allocation_callbacks: self.allocation_callbacks.map(AllocationCallbacks),
         }
         .into())
     }
 }
 
 impl Instance {
+    /// Entry the instance was created with, together with `raw` it's enough
+    /// to load extension functions the crate doesn't wrap.
+    pub fn entry(&self) -> &ash::Entry {
+        &self.entry
+    }
+
     pub fn debug_utils(&self) -> Option<&ash::ext::debug_utils::Instance> {
         if let Some((debug, _)) = &self.debug {
             Some(debug)
@@ -106,18 +248,48 @@ impl Instance {
         }
     }
 
+    /// Number of errors reported by the debug messenger so far.
+    pub fn debug_errors(&self) -> u32 {
+        self.debug_messages.errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of warnings reported by the debug messenger so far.
+    pub fn debug_warnings(&self) -> u32 {
+        self.debug_messages.warnings.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn allocation_callbacks(&self) -> Option<&vk::AllocationCallbacks<'static>> {
+        self.allocation_callbacks
+            .as_ref()
+            .map(|callbacks| &callbacks.0)
+    }
+
     pub fn version(&self) -> u32 {
         vk::make_api_version(0, 1, 3, 0)
     }
 }
 
+/// Queries that don't need an instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BackendEntry;
+
+impl BackendEntry {
+    /// Highest instance version supported by the loader, 1.0 for loaders
+    /// that predate `vkEnumerateInstanceVersion`.
+    pub fn vulkan_version() -> Result<u32, BackendError> {
+        let entry = unsafe { ash::Entry::load()? };
+        let version = unsafe { entry.try_enumerate_instance_version() }?;
+        Ok(version.unwrap_or(vk::API_VERSION_1_0))
+    }
+}
+
 impl Drop for Instance {
     fn drop(&mut self) {
         if let Some((debug, messenger)) = self.debug.take() {
             unsafe { debug.destroy_debug_utils_messenger(messenger, None) };
         }
         unsafe {
-            self.raw.destroy_instance(None);
+            self.raw.destroy_instance(self.allocation_callbacks());
         }
     }
 }
@@ -126,8 +298,9 @@ unsafe extern "system" fn vulkan_debug_callback(
     severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     ty: vk::DebugUtilsMessageTypeFlagsEXT,
     data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _: *mut c_void,
+    user_data: *mut c_void,
 ) -> u32 {
+    let counts = unsafe { &*(user_data as *const DebugMessageCounts) };
     let message = unsafe { (*data).message_as_c_str().unwrap().to_str().unwrap() };
     let ty = match ty {
         vk::DebugUtilsMessageTypeFlagsEXT::DEVICE_ADDRESS_BINDING => "Device address binding",
@@ -137,8 +310,14 @@ unsafe extern "system" fn vulkan_debug_callback(
     };
 
     match severity {
-        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => error!("ERROR: {0}: {1}", ty, message),
-        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => warn!("WARN {0}: {1}", ty, message),
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => {
+            counts.errors.fetch_add(1, Ordering::Relaxed);
+            error!("ERROR: {0}: {1}", ty, message)
+        }
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => {
+            counts.warnings.fetch_add(1, Ordering::Relaxed);
+            warn!("WARN {0}: {1}", ty, message)
+        }
         vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => trace!("{0}: {1}", ty, message),
         _ => info!("{0}: {1}", ty, message),
     };
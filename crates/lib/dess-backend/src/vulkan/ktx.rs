@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, Image, ImageDesc, format_aspect},
+};
+
+impl Device {
+    /// Creates image from KTX2 container and uploads all levels, layers and
+    /// faces in one submit. Image is left in `SHADER_READ_ONLY_OPTIMAL`
+    /// layout. Supercompressed payloads aren't supported.
+    pub fn create_image_from_ktx2(self: &Arc<Self>, data: &[u8]) -> Result<Image, BackendError> {
+        let reader = ktx2::Reader::new(data)?;
+        let header = reader.header();
+        if header.supercompression_scheme.is_some() {
+            return Err(BackendError::UnsupportedTexture(
+                "supercompressed KTX2 payload",
+            ));
+        }
+        let format = header
+            .format
+            .map(|format| vk::Format::from_raw(format.value() as i32))
+            .ok_or(BackendError::UnsupportedTexture(
+                "KTX2 texture without Vulkan format",
+            ))?;
+        let ty = if header.pixel_depth > 0 {
+            vk::ImageType::TYPE_3D
+        } else if header.pixel_height > 0 {
+            vk::ImageType::TYPE_2D
+        } else {
+            vk::ImageType::TYPE_1D
+        };
+        let extent = vk::Extent3D {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            depth: header.pixel_depth.max(1),
+        };
+        let flags = if header.face_count == 6 {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            vk::ImageCreateFlags::empty()
+        };
+        let desc = ImageDesc::new(ty, format, extent)
+            .flags(flags)
+            .mip_levels(header.level_count.max(1))
+            .array_layers(header.layer_count.max(1) * header.face_count);
+        let _ = self.image_format_properties(&desc)?;
+
+        // Levels store layers and faces tightly packed in the same order Vulkan
+        // expects array layers, so one region per level is enough.
+        let aspect = format_aspect(format);
+        let mut staging = Vec::new();
+        let mut regions = Vec::with_capacity(desc.mip_levels as usize);
+        for (level, data) in reader.levels().enumerate() {
+            let offset = staging.len().next_multiple_of(16);
+            staging.resize(offset, 0);
+            staging.extend_from_slice(data.data);
+            regions.push(
+                vk::BufferImageCopy::default()
+                    .buffer_offset(offset as u64)
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(aspect)
+                            .mip_level(level as u32)
+                            .base_array_layer(0)
+                            .layer_count(desc.array_layers),
+                    )
+                    .image_extent(desc.mip_extent(level as u32)),
+            );
+        }
+        if staging.is_empty() {
+            return Err(BackendError::UnsupportedTexture(
+                "KTX2 texture without data",
+            ));
+        }
+
+        let image = self.create_image(desc)?;
+        self.upload_image(&image, &staging, &regions)?;
+        Ok(image)
+    }
+}
@@ -0,0 +1,349 @@
+use std::{mem, slice, sync::Arc};
+
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{BufferSlice, Device, Frame},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct VertexLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    pub fn binding(mut self, binding: u32, stride: u32, input_rate: vk::VertexInputRate) -> Self {
+        self.bindings.push(vk::VertexInputBindingDescription {
+            binding,
+            stride,
+            input_rate,
+        });
+        self
+    }
+
+    pub fn attribute(
+        mut self,
+        location: u32,
+        binding: u32,
+        format: vk::Format,
+        offset: u32,
+    ) -> Self {
+        self.attributes.push(vk::VertexInputAttributeDescription {
+            location,
+            binding,
+            format,
+            offset,
+        });
+        self
+    }
+
+    /// Checks that every attribute fits into its binding stride and the
+    /// layout stays within device limits.
+    pub fn validate(&self, device: &Device) -> Result<(), BackendError> {
+        let limits = &device.pdevice.properties.limits;
+        if self.attributes.len() > limits.max_vertex_input_attributes as usize {
+            return Err(BackendError::InvalidVertexLayout(format!(
+                "{} attributes exceed device limit of {}",
+                self.attributes.len(),
+                limits.max_vertex_input_attributes
+            )));
+        }
+        if self.bindings.len() > limits.max_vertex_input_bindings as usize {
+            return Err(BackendError::InvalidVertexLayout(format!(
+                "{} bindings exceed device limit of {}",
+                self.bindings.len(),
+                limits.max_vertex_input_bindings
+            )));
+        }
+        for (index, attribute) in self.attributes.iter().enumerate() {
+            if attribute.location >= limits.max_vertex_input_attributes {
+                return Err(BackendError::InvalidVertexLayout(format!(
+                    "location {} exceeds device limit of {}",
+                    attribute.location, limits.max_vertex_input_attributes
+                )));
+            }
+            if self.attributes[..index]
+                .iter()
+                .any(|other| other.location == attribute.location)
+            {
+                return Err(BackendError::InvalidVertexLayout(format!(
+                    "location {} is used twice",
+                    attribute.location
+                )));
+            }
+            let Some(binding) = self
+                .bindings
+                .iter()
+                .find(|binding| binding.binding == attribute.binding)
+            else {
+                return Err(BackendError::InvalidVertexLayout(format!(
+                    "location {} uses missing binding {}",
+                    attribute.location, attribute.binding
+                )));
+            };
+            let Some(size) = vertex_format_size(attribute.format) else {
+                return Err(BackendError::InvalidVertexLayout(format!(
+                    "location {} has unsupported format {:?}",
+                    attribute.location, attribute.format
+                )));
+            };
+            if attribute.offset + size > binding.stride {
+                return Err(BackendError::InvalidVertexLayout(format!(
+                    "location {} at offset {} with {} bytes doesn't fit stride {}",
+                    attribute.location, attribute.offset, size, binding.stride
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn vertex_format_size(format: vk::Format) -> Option<u32> {
+    Some(match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT | vk::Format::R8_SINT => {
+            1
+        }
+        vk::Format::R8G8_UNORM
+        | vk::Format::R8G8_SNORM
+        | vk::Format::R8G8_UINT
+        | vk::Format::R8G8_SINT
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_SNORM
+        | vk::Format::R16_UINT
+        | vk::Format::R16_SINT
+        | vk::Format::R16_SFLOAT => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R8G8B8A8_SINT
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::R16G16_UNORM
+        | vk::Format::R16G16_SNORM
+        | vk::Format::R16G16_UINT
+        | vk::Format::R16G16_SINT
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::R32_SINT
+        | vk::Format::R32_SFLOAT => 4,
+        vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SNORM
+        | vk::Format::R16G16B16A16_UINT
+        | vk::Format::R16G16B16A16_SINT
+        | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R32G32_UINT
+        | vk::Format::R32G32_SINT
+        | vk::Format::R32G32_SFLOAT => 8,
+        vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32_SFLOAT => {
+            12
+        }
+        vk::Format::R32G32B32A32_UINT
+        | vk::Format::R32G32B32A32_SINT
+        | vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => return None,
+    })
+}
+
+/// Vertex that can be uploaded as raw bytes.
+///
+/// # Safety
+///
+/// Implementor must be `#[repr(C)]` without padding.
+pub unsafe trait Vertex: Copy {
+    fn layout() -> VertexLayout;
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PositionVertex {
+    pub position: [f32; 3],
+}
+
+unsafe impl Vertex for PositionVertex {
+    fn layout() -> VertexLayout {
+        VertexLayout::default()
+            .binding(0, 12, vk::VertexInputRate::VERTEX)
+            .attribute(0, 0, vk::Format::R32G32B32_SFLOAT, 0)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PositionUvVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+unsafe impl Vertex for PositionUvVertex {
+    fn layout() -> VertexLayout {
+        VertexLayout::default()
+            .binding(0, 20, vk::VertexInputRate::VERTEX)
+            .attribute(0, 0, vk::Format::R32G32B32_SFLOAT, 0)
+            .attribute(1, 0, vk::Format::R32G32_SFLOAT, 12)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PositionNormalUvVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+unsafe impl Vertex for PositionNormalUvVertex {
+    fn layout() -> VertexLayout {
+        VertexLayout::default()
+            .binding(0, 32, vk::VertexInputRate::VERTEX)
+            .attribute(0, 0, vk::Format::R32G32B32_SFLOAT, 0)
+            .attribute(1, 0, vk::Format::R32G32B32_SFLOAT, 12)
+            .attribute(2, 0, vk::Format::R32G32_SFLOAT, 24)
+    }
+}
+
+/// Vertex buffers bound for a draw, the draw time counterpart of
+/// [`VertexLayout`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VertexBuffers {
+    bindings: Vec<VertexBufferBinding>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexBufferBinding {
+    pub binding: u32,
+    pub buffer: vk::Buffer,
+    pub offset: u64,
+    pub stride: u32,
+}
+
+impl VertexBuffers {
+    pub fn builder() -> Self {
+        Self::default()
+    }
+
+    /// Replaces buffer previously added for the same binding.
+    pub fn add_buffer(
+        mut self,
+        binding: u32,
+        buffer: vk::Buffer,
+        offset: u64,
+        stride: u32,
+    ) -> Self {
+        self.bindings.retain(|b| b.binding != binding);
+        let value = VertexBufferBinding {
+            binding,
+            buffer,
+            offset,
+            stride,
+        };
+        let index = self.bindings.partition_point(|b| b.binding < binding);
+        self.bindings.insert(index, value);
+        self
+    }
+
+    pub fn bindings(&self) -> &[VertexBufferBinding] {
+        &self.bindings
+    }
+
+    /// Checks that every binding of the pipeline layout has a buffer with
+    /// the same stride.
+    pub fn matches(&self, layout: &VertexLayout) -> bool {
+        layout.bindings.iter().all(|desc| {
+            self.bindings
+                .iter()
+                .any(|b| b.binding == desc.binding && b.stride == desc.stride)
+        })
+    }
+}
+
+impl Frame<'_> {
+    /// Binds all buffers, one call per run of consecutive bindings.
+    pub fn bind_vertex_buffers(&self, buffers: &VertexBuffers) {
+        let cb = self.main_cb().raw();
+        for run in buffers.bindings.chunk_by(|a, b| a.binding + 1 == b.binding) {
+            let raw = run.iter().map(|b| b.buffer).collect::<Vec<_>>();
+            let offsets = run.iter().map(|b| b.offset).collect::<Vec<_>>();
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_bind_vertex_buffers(cb, run[0].binding, &raw, &offsets)
+            };
+        }
+    }
+}
+
+pub(crate) fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub vertices: BufferSlice,
+    pub indices: Option<BufferSlice>,
+    pub layout: VertexLayout,
+    pub vertex_count: u32,
+    pub index_count: u32,
+}
+
+impl Mesh {
+    pub fn bind(&self, cb: vk::CommandBuffer) {
+        let device = &self.vertices.buffer.device.raw;
+        unsafe {
+            device.cmd_bind_vertex_buffers(
+                cb,
+                0,
+                &[self.vertices.buffer.raw],
+                &[self.vertices.offset],
+            )
+        };
+        if let Some(indices) = &self.indices {
+            unsafe {
+                device.cmd_bind_index_buffer(
+                    cb,
+                    indices.buffer.raw,
+                    indices.offset,
+                    vk::IndexType::UINT32,
+                )
+            };
+        }
+    }
+
+    pub fn draw(&self, cb: vk::CommandBuffer) {
+        let device = &self.vertices.buffer.device.raw;
+        if self.indices.is_some() {
+            unsafe { device.cmd_draw_indexed(cb, self.index_count, 1, 0, 0, 0) };
+        } else {
+            unsafe { device.cmd_draw(cb, self.vertex_count, 1, 0, 0) };
+        }
+    }
+}
+
+impl Device {
+    /// Uploads vertices and optional 32 bit indices into device local buffers.
+    pub fn create_mesh<V: Vertex>(
+        self: &Arc<Self>,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> Result<Mesh, BackendError> {
+        let layout = V::layout();
+        layout.validate(self)?;
+        let vertex_buffer =
+            self.create_buffer_with_data(vk::BufferUsageFlags::VERTEX_BUFFER, as_bytes(vertices))?;
+        let index_buffer = if indices.is_empty() {
+            None
+        } else {
+            let buffer = self
+                .create_buffer_with_data(vk::BufferUsageFlags::INDEX_BUFFER, as_bytes(indices))?;
+            Some(BufferSlice::whole(Arc::new(buffer)))
+        };
+        Ok(Mesh {
+            vertices: BufferSlice::whole(Arc::new(vertex_buffer)),
+            indices: index_buffer,
+            layout,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.len() as u32,
+        })
+    }
+}
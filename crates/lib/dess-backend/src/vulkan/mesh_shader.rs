@@ -0,0 +1,73 @@
+use std::sync::atomic::Ordering;
+
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, Frame},
+};
+
+impl Device {
+    pub(crate) fn mesh_shader_loader(
+        &self,
+    ) -> Result<&ash::ext::mesh_shader::Device, BackendError> {
+        self.mesh_shader
+            .as_ref()
+            .ok_or(BackendError::ExtensionNotEnabled(
+                ash::ext::mesh_shader::NAME,
+            ))
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Binds graphics pipeline with task and mesh shaders to the main command
+    /// buffer of the frame. Pipelines used by mesh draws must be bound here.
+    pub fn bind_mesh_pipeline(&self, pipeline: vk::Pipeline) {
+        unsafe {
+            self.device.raw.cmd_bind_pipeline(
+                self.main_cb().raw(),
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            )
+        };
+        self.mesh_pipeline_bound.store(true, Ordering::Relaxed);
+    }
+
+    pub fn draw_mesh_tasks_ext(&self, x: u32, y: u32, z: u32) -> Result<(), BackendError> {
+        let loader = self.mesh_loader()?;
+        unsafe { loader.cmd_draw_mesh_tasks(self.main_cb().raw(), x, y, z) };
+        Ok(())
+    }
+
+    pub fn draw_mesh_tasks_indirect_ext(
+        &self,
+        buffer: vk::Buffer,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) -> Result<(), BackendError> {
+        let loader = self.mesh_loader()?;
+        unsafe {
+            loader.cmd_draw_mesh_tasks_indirect(
+                self.main_cb().raw(),
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            )
+        };
+        Ok(())
+    }
+
+    fn mesh_loader(&self) -> Result<&ash::ext::mesh_shader::Device, BackendError> {
+        debug_assert!(
+            self.device.mesh_shader.is_some(),
+            "VK_EXT_mesh_shader isn't enabled"
+        );
+        debug_assert!(
+            self.mesh_pipeline_bound.load(Ordering::Relaxed),
+            "Mesh draw without a pipeline bound by Frame::bind_mesh_pipeline"
+        );
+        self.device.mesh_shader_loader()
+    }
+}
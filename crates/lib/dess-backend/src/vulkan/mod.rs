@@ -1,9 +1,61 @@
+mod acceleration_structure;
+mod barrier;
+mod blit;
+mod buffer;
+mod descriptor;
 mod device;
+#[cfg(feature = "egui")]
+mod egui_renderer;
+mod image;
 mod instance;
+#[cfg(feature = "ktx2")]
+mod ktx;
+mod mesh;
+mod mesh_shader;
 mod physical_device;
+mod pipeline;
+mod pixels;
+mod profiler;
+mod render_graph;
+mod render_pass;
+mod render_target;
+#[cfg(feature = "renderdoc")]
+mod renderdoc;
+mod sampler;
+mod suballocator;
 mod surface;
+mod swapchain;
+mod sync;
+mod transfer;
+mod upload;
+#[cfg(feature = "video-decode")]
+mod video;
 
+pub use acceleration_structure::*;
+pub use barrier::*;
+pub use blit::*;
+pub use buffer::*;
+pub use descriptor::*;
 pub use device::*;
+#[cfg(feature = "egui")]
+pub use egui_renderer::*;
+pub use image::*;
 pub use instance::*;
+pub use mesh::*;
 pub use physical_device::*;
+pub use pipeline::*;
+pub use pixels::*;
+pub use profiler::*;
+pub use render_graph::*;
+pub use render_pass::*;
+pub use render_target::*;
+#[cfg(feature = "renderdoc")]
+pub use renderdoc::*;
+pub use sampler::*;
+pub use suballocator::*;
 pub use surface::*;
+pub use swapchain::*;
+pub use sync::*;
+pub use upload::*;
+#[cfg(feature = "video-decode")]
+pub use video::*;
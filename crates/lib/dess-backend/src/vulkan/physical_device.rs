@@ -1,6 +1,11 @@
+use std::{ffi::CStr, fmt};
+
 use ash::vk;
 
-use crate::{BackendError, vulkan::Instance};
+use crate::{
+    BackendError,
+    vulkan::{Device, Instance},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct QueueFamily {
@@ -13,7 +18,73 @@ pub struct PhysicalDevice {
     pub raw: vk::PhysicalDevice,
     pub queue_families: Vec<QueueFamily>,
     pub properties: vk::PhysicalDeviceProperties,
+    /// Identifies the device across instances and processes, stable between
+    /// runs as long as the hardware and driver don't change.
+    pub device_uuid: [u8; vk::UUID_SIZE],
     pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub extensions: Vec<vk::ExtensionProperties>,
+}
+
+impl PhysicalDevice {
+    pub fn supports_extension(&self, name: &CStr) -> bool {
+        self.extensions
+            .iter()
+            .any(|extension| extension.extension_name_as_c_str() == Ok(name))
+    }
+
+    /// Integrated GPUs share memory with the CPU, so host visible buffers
+    /// avoid a staging copy.
+    pub fn is_integrated(&self) -> bool {
+        self.properties.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU
+    }
+
+    pub fn is_discrete(&self) -> bool {
+        self.properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
+    }
+
+    /// Maximum number of samplers that may exist at once on the device.
+    pub fn max_sampler_allocation_count(&self) -> u32 {
+        self.properties.limits.max_sampler_allocation_count
+    }
+
+    /// Maximum width, height and layer count of framebuffer attachments.
+    pub fn max_framebuffer_size(&self) -> (u32, u32, u32) {
+        let limits = &self.properties.limits;
+        (
+            limits.max_framebuffer_width,
+            limits.max_framebuffer_height,
+            limits.max_framebuffer_layers,
+        )
+    }
+
+    /// Checks that an attachment of such size fits into framebuffer limits.
+    pub fn validate_image_size(
+        &self,
+        width: u32,
+        height: u32,
+        layers: u32,
+    ) -> Result<(), BackendError> {
+        let max = self.max_framebuffer_size();
+        if width > max.0 || height > max.1 || layers > max.2 {
+            return Err(BackendError::ImageSizeExceedsLimit {
+                size: (width, height, layers),
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn video_decode_queue_family(&self) -> Option<u32> {
+        self.queue_families
+            .iter()
+            .find(|queue| {
+                queue
+                    .properties
+                    .queue_flags
+                    .contains(vk::QueueFlags::VIDEO_DECODE_KHR)
+            })
+            .map(|queue| queue.index)
+    }
 }
 
 impl Instance {
@@ -21,7 +92,14 @@ impl Instance {
         let pdevices = unsafe { self.raw.enumerate_physical_devices() }?
             .into_iter()
             .map(|pdevice| {
-                let properties = unsafe { self.raw.get_physical_device_properties(pdevice) };
+                let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+                let mut properties2 =
+                    vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
+                unsafe {
+                    self.raw
+                        .get_physical_device_properties2(pdevice, &mut properties2)
+                };
+                let properties = properties2.properties;
                 let memory_properties =
                     unsafe { self.raw.get_physical_device_memory_properties(pdevice) };
                 let queue_families = unsafe {
@@ -35,14 +113,290 @@ impl Instance {
                     properties,
                 })
                 .collect();
-                PhysicalDevice {
+                let extensions =
+                    unsafe { self.raw.enumerate_device_extension_properties(pdevice) }?;
+                Ok(PhysicalDevice {
                     raw: pdevice,
                     queue_families,
                     properties,
+                    device_uuid: id_properties.device_uuid,
                     memory_properties,
-                }
+                    extensions,
+                })
             })
-            .collect();
+            .collect::<Result<Vec<_>, BackendError>>()?;
         Ok(pdevices)
     }
+
+    /// Finds device by its [`device_uuid`](PhysicalDevice::device_uuid) or,
+    /// failing that, by pipeline cache UUID.
+    pub fn physical_device_by_uuid(
+        &self,
+        uuid: [u8; vk::UUID_SIZE],
+    ) -> Result<Option<PhysicalDevice>, BackendError> {
+        let mut pdevices = self.get_physical_devices()?;
+        let index = pdevices
+            .iter()
+            .position(|pdevice| pdevice.device_uuid == uuid)
+            .or_else(|| {
+                pdevices
+                    .iter()
+                    .position(|pdevice| pdevice.properties.pipeline_cache_uuid == uuid)
+            });
+        Ok(index.map(|index| pdevices.swap_remove(index)))
+    }
+
+    /// Device at `index` in enumeration order. The order is usually stable,
+    /// but may change with driver updates, prefer
+    /// [`Instance::physical_device_by_uuid`] when pinning a device.
+    pub fn physical_device_by_index(
+        &self,
+        index: usize,
+    ) -> Result<Option<PhysicalDevice>, BackendError> {
+        let mut pdevices = self.get_physical_devices()?;
+        Ok((index < pdevices.len()).then(|| pdevices.swap_remove(index)))
+    }
+
+    /// Format features with 64 bit flags, e.g. storage access without format.
+    /// `None` when the device has neither Vulkan 1.3 nor
+    /// `VK_KHR_format_feature_flags2`.
+    pub fn get_physical_device_format_properties3(
+        &self,
+        pdevice: &PhysicalDevice,
+        format: vk::Format,
+    ) -> Option<vk::FormatProperties3<'static>> {
+        if pdevice.properties.api_version < vk::API_VERSION_1_3
+            && !pdevice.supports_extension(ash::khr::format_feature_flags2::NAME)
+        {
+            return None;
+        }
+        let mut properties3 = vk::FormatProperties3::default();
+        let mut properties = vk::FormatProperties2::default().push_next(&mut properties3);
+        unsafe {
+            self.raw
+                .get_physical_device_format_properties2(pdevice.raw, format, &mut properties)
+        };
+        Some(properties3)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DriverInfo {
+    pub id: vk::DriverId,
+    pub name: String,
+    pub info: String,
+    pub version: String,
+    pub conformance_version: vk::ConformanceVersion,
+    pub device_uuid: [u8; vk::UUID_SIZE],
+    pub driver_uuid: [u8; vk::UUID_SIZE],
+}
+
+impl DriverInfo {
+    pub fn id_name(&self) -> &'static str {
+        match self.id {
+            vk::DriverId::AMD_PROPRIETARY => "AMD proprietary",
+            vk::DriverId::AMD_OPEN_SOURCE => "AMD open source",
+            vk::DriverId::MESA_RADV => "Mesa RADV",
+            vk::DriverId::NVIDIA_PROPRIETARY => "NVIDIA proprietary",
+            vk::DriverId::INTEL_PROPRIETARY_WINDOWS => "Intel proprietary (Windows)",
+            vk::DriverId::INTEL_OPEN_SOURCE_MESA => "Mesa Intel",
+            vk::DriverId::IMAGINATION_PROPRIETARY => "Imagination proprietary",
+            vk::DriverId::QUALCOMM_PROPRIETARY => "Qualcomm proprietary",
+            vk::DriverId::ARM_PROPRIETARY => "ARM proprietary",
+            vk::DriverId::GOOGLE_SWIFTSHADER => "SwiftShader",
+            vk::DriverId::GGP_PROPRIETARY => "GGP proprietary",
+            vk::DriverId::BROADCOM_PROPRIETARY => "Broadcom proprietary",
+            vk::DriverId::MESA_LLVMPIPE => "Mesa llvmpipe",
+            vk::DriverId::MOLTENVK => "MoltenVK",
+            vk::DriverId::COREAVI_PROPRIETARY => "CoreAVI proprietary",
+            vk::DriverId::JUICE_PROPRIETARY => "Juice proprietary",
+            vk::DriverId::VERISILICON_PROPRIETARY => "VeriSilicon proprietary",
+            vk::DriverId::MESA_TURNIP => "Mesa Turnip",
+            vk::DriverId::MESA_V3DV => "Mesa V3DV",
+            vk::DriverId::MESA_PANVK => "Mesa PanVK",
+            vk::DriverId::SAMSUNG_PROPRIETARY => "Samsung proprietary",
+            vk::DriverId::MESA_VENUS => "Mesa Venus",
+            vk::DriverId::MESA_DOZEN => "Mesa Dozen",
+            vk::DriverId::MESA_NVK => "Mesa NVK",
+            vk::DriverId::IMAGINATION_OPEN_SOURCE_MESA => "Mesa Imagination",
+            vk::DriverId::MESA_AGXV => "Mesa AGXV",
+            _ => "Unknown",
+        }
+    }
+}
+
+impl PhysicalDevice {
+    pub fn driver_info(&self, instance: &Instance) -> DriverInfo {
+        let mut driver = vk::PhysicalDeviceDriverProperties::default();
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::default()
+            .push_next(&mut driver)
+            .push_next(&mut id_properties);
+        unsafe {
+            instance
+                .raw
+                .get_physical_device_properties2(self.raw, &mut properties)
+        };
+        DriverInfo {
+            id: driver.driver_id,
+            name: driver
+                .driver_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            info: driver
+                .driver_info_as_c_str()
+                .map(|info| info.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            version: decode_driver_version(driver.driver_id, self.properties.driver_version),
+            conformance_version: driver.conformance_version,
+            device_uuid: id_properties.device_uuid,
+            driver_uuid: id_properties.driver_uuid,
+        }
+    }
+}
+
+// Driver version encoding is vendor specific, only a few vendors deviate from
+// the Vulkan version packing.
+fn decode_driver_version(id: vk::DriverId, version: u32) -> String {
+    match id {
+        vk::DriverId::NVIDIA_PROPRIETARY => format!(
+            "{}.{}.{}.{}",
+            (version >> 22) & 0x3ff,
+            (version >> 14) & 0xff,
+            (version >> 6) & 0xff,
+            version & 0x3f
+        ),
+        vk::DriverId::INTEL_PROPRIETARY_WINDOWS => {
+            format!("{}.{}", version >> 14, version & 0x3fff)
+        }
+        _ => format!(
+            "{}.{}.{}",
+            vk::api_version_major(version),
+            vk::api_version_minor(version),
+            vk::api_version_patch(version)
+        ),
+    }
+}
+
+/// Summary of the device for logs and crash reports.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PhysicalDeviceInfo {
+    pub name: String,
+    pub device_type: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub api_version: String,
+    pub driver_name: String,
+    pub driver_info: String,
+    pub driver_version: String,
+    pub device_uuid: String,
+    /// Sizes of device local memory heaps in bytes.
+    pub device_local_heaps: Vec<u64>,
+    /// Sizes of other memory heaps in bytes.
+    pub host_heaps: Vec<u64>,
+    pub max_image_dimension_2d: u32,
+    pub max_push_constants_size: u32,
+    pub max_compute_work_group_invocations: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub timestamp_period: f32,
+}
+
+impl fmt::Display for PhysicalDeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MIB: u64 = 1024 * 1024;
+        let heaps = |heaps: &[u64]| {
+            heaps
+                .iter()
+                .map(|size| format!("{} MiB", size / MIB))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(
+            f,
+            "{} ({}, {:04x}:{:04x})",
+            self.name, self.device_type, self.vendor_id, self.device_id
+        )?;
+        writeln!(f, "Vulkan {}", self.api_version)?;
+        writeln!(
+            f,
+            "Driver {} {} ({})",
+            self.driver_name, self.driver_version, self.driver_info
+        )?;
+        writeln!(f, "UUID {}", self.device_uuid)?;
+        writeln!(f, "Device local heaps: {}", heaps(&self.device_local_heaps))?;
+        writeln!(f, "Host heaps: {}", heaps(&self.host_heaps))?;
+        writeln!(f, "Max 2D image dimension: {}", self.max_image_dimension_2d)?;
+        writeln!(
+            f,
+            "Max push constants size: {}",
+            self.max_push_constants_size
+        )?;
+        writeln!(
+            f,
+            "Max compute work group invocations: {}",
+            self.max_compute_work_group_invocations
+        )?;
+        writeln!(
+            f,
+            "Max bound descriptor sets: {}",
+            self.max_bound_descriptor_sets
+        )?;
+        write!(f, "Timestamp period: {} ns", self.timestamp_period)
+    }
+}
+
+impl Device {
+    /// Only reads cached and static device properties, doesn't touch the GPU.
+    pub fn physical_device_info(&self) -> PhysicalDeviceInfo {
+        let pdevice = &self.pdevice;
+        let properties = &pdevice.properties;
+        let driver = pdevice.driver_info(&self.instance);
+        let memory = &pdevice.memory_properties;
+        let (device_local_heaps, host_heaps) = memory.memory_heaps
+            [..memory.memory_heap_count as usize]
+            .iter()
+            .partition::<Vec<&vk::MemoryHeap>, _>(|heap| {
+                heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+            });
+        let device_type = match properties.device_type {
+            vk::PhysicalDeviceType::INTEGRATED_GPU => "integrated GPU",
+            vk::PhysicalDeviceType::DISCRETE_GPU => "discrete GPU",
+            vk::PhysicalDeviceType::VIRTUAL_GPU => "virtual GPU",
+            vk::PhysicalDeviceType::CPU => "CPU",
+            _ => "other",
+        };
+        PhysicalDeviceInfo {
+            name: properties
+                .device_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            device_type: device_type.to_owned(),
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            api_version: format!(
+                "{}.{}.{}",
+                vk::api_version_major(properties.api_version),
+                vk::api_version_minor(properties.api_version),
+                vk::api_version_patch(properties.api_version)
+            ),
+            driver_name: driver.name,
+            driver_info: driver.info,
+            driver_version: driver.version,
+            device_uuid: pdevice
+                .device_uuid
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+            device_local_heaps: device_local_heaps.iter().map(|heap| heap.size).collect(),
+            host_heaps: host_heaps.iter().map(|heap| heap.size).collect(),
+            max_image_dimension_2d: properties.limits.max_image_dimension2_d,
+            max_push_constants_size: properties.limits.max_push_constants_size,
+            max_compute_work_group_invocations: properties
+                .limits
+                .max_compute_work_group_invocations,
+            max_bound_descriptor_sets: properties.limits.max_bound_descriptor_sets,
+            timestamp_period: properties.limits.timestamp_period,
+        }
+    }
 }
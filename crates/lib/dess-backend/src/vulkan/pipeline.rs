@@ -0,0 +1,1068 @@
+use std::{collections::HashMap, ffi::CStr, fmt::Debug, fs, io, path::Path, ptr, sync::Arc};
+
+use ash::vk;
+use log::warn;
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, Frame, InputAttachmentIndices, VertexLayout, as_bytes},
+};
+
+pub struct PipelineCache {
+    pub raw: vk::PipelineCache,
+    device: Arc<Device>,
+}
+
+impl Debug for PipelineCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipelineCache")
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+impl PipelineCache {
+    pub fn data(&self) -> Result<Vec<u8>, BackendError> {
+        Ok(unsafe { self.device.raw.get_pipeline_cache_data(self.raw) }?)
+    }
+
+    /// Writes cache data to `path`. The data goes to a temporary file first,
+    /// which then replaces the old one, so a crash never leaves a partially
+    /// written cache.
+    pub fn save(&self, path: &Path) -> Result<(), BackendError> {
+        let data = self.data()?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        unsafe { self.device.raw.destroy_pipeline_cache(self.raw, None) };
+    }
+}
+
+impl Device {
+    pub fn create_pipeline_cache(
+        self: &Arc<Self>,
+        initial_data: &[u8],
+    ) -> Result<PipelineCache, BackendError> {
+        let info = vk::PipelineCacheCreateInfo::default().initial_data(initial_data);
+        let raw = unsafe { self.raw.create_pipeline_cache(&info, None) }?;
+        Ok(PipelineCache {
+            raw,
+            device: self.clone(),
+        })
+    }
+
+    /// Creates pipeline cache from data saved with [`PipelineCache::save`].
+    /// Missing file or data written by another device or driver results in an
+    /// empty cache.
+    pub fn load_pipeline_cache(
+        self: &Arc<Self>,
+        path: &Path,
+    ) -> Result<PipelineCache, BackendError> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        if data.is_empty() || self.is_pipeline_cache_compatible(&data) {
+            return self.create_pipeline_cache(&data);
+        }
+        warn!("Pipeline cache {path:?} is corrupted or outdated, ignoring it");
+        self.create_pipeline_cache(&[])
+    }
+
+    /// Checks `VkPipelineCacheHeaderVersionOne` against the device.
+    fn is_pipeline_cache_compatible(&self, data: &[u8]) -> bool {
+        const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+        if data.len() < HEADER_SIZE {
+            return false;
+        }
+        let word =
+            |index: usize| u32::from_ne_bytes(data[index * 4..index * 4 + 4].try_into().unwrap());
+        let properties = &self.pdevice.properties;
+        word(0) as usize >= HEADER_SIZE
+            && word(1) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && word(2) == properties.vendor_id
+            && word(3) == properties.device_id
+            && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid
+    }
+
+    /// Creates all pipelines in a single driver call, letting the driver
+    /// compile them in parallel.
+    pub fn create_compute_pipelines_batch(
+        &self,
+        infos: &[vk::ComputePipelineCreateInfo],
+        cache: Option<&PipelineCache>,
+    ) -> Result<Vec<vk::Pipeline>, BackendError> {
+        let cache = cache.map_or(vk::PipelineCache::null(), |cache| cache.raw);
+        unsafe { self.raw.create_compute_pipelines(cache, infos, None) }
+            .map_err(|(pipelines, err)| self.destroy_partial_batch(pipelines, err))
+    }
+
+    /// Creates all pipelines in a single driver call, letting the driver
+    /// compile them in parallel.
+    pub fn create_graphics_pipelines_batch(
+        &self,
+        infos: &[vk::GraphicsPipelineCreateInfo],
+        cache: Option<&PipelineCache>,
+    ) -> Result<Vec<vk::Pipeline>, BackendError> {
+        let cache = cache.map_or(vk::PipelineCache::null(), |cache| cache.raw);
+        unsafe { self.raw.create_graphics_pipelines(cache, infos, None) }
+            .map_err(|(pipelines, err)| self.destroy_partial_batch(pipelines, err))
+    }
+
+    fn destroy_partial_batch(&self, pipelines: Vec<vk::Pipeline>, err: vk::Result) -> BackendError {
+        pipelines
+            .into_iter()
+            .filter(|pipeline| *pipeline != vk::Pipeline::null())
+            .for_each(|pipeline| unsafe { self.raw.destroy_pipeline(pipeline, None) });
+        err.into()
+    }
+}
+
+/// Common subset of [`GraphicsPipelineBuilder`] options for
+/// [`Device::create_graphics_pipeline_from_spirv`].
+#[derive(Debug, Clone)]
+pub struct GraphicsPipelineConfig {
+    pub vertex_layout: VertexLayout,
+    pub color_formats: Vec<vk::Format>,
+    /// `UNDEFINED` renders without depth attachment.
+    pub depth_format: vk::Format,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub cull_mode: vk::CullModeFlags,
+    /// Premultiplied alpha blending for all color attachments.
+    pub blend: bool,
+}
+
+impl Default for GraphicsPipelineConfig {
+    fn default() -> Self {
+        Self {
+            vertex_layout: VertexLayout::default(),
+            color_formats: Vec::new(),
+            depth_format: vk::Format::UNDEFINED,
+            depth_test: false,
+            depth_write: false,
+            cull_mode: vk::CullModeFlags::BACK,
+            blend: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphicsPipelineBuilder<'a> {
+    layout: vk::PipelineLayout,
+    vertex_shader: Option<(vk::ShaderModule, &'a CStr)>,
+    fragment_shader: Option<(vk::ShaderModule, &'a CStr)>,
+    vertex_layout: VertexLayout,
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    samples: vk::SampleCountFlags,
+    alpha_to_coverage: bool,
+    alpha_to_one: bool,
+    color_formats: Vec<vk::Format>,
+    blend: bool,
+    color_write_masks: Vec<vk::ColorComponentFlags>,
+    depth_format: vk::Format,
+    depth_test: bool,
+    depth_write: bool,
+    depth_compare_op: vk::CompareOp,
+    depth_clamp: bool,
+    depth_clip: Option<bool>,
+    depth_bounds: Option<(f32, f32)>,
+    dynamic_depth_bounds: bool,
+    input_attachment_indices: Option<InputAttachmentIndices>,
+    library: Option<vk::GraphicsPipelineLibraryFlagsEXT>,
+    capture_statistics: bool,
+}
+
+impl<'a> GraphicsPipelineBuilder<'a> {
+    pub fn new(layout: vk::PipelineLayout) -> Self {
+        Self {
+            layout,
+            vertex_shader: None,
+            fragment_shader: None,
+            vertex_layout: VertexLayout::default(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            samples: vk::SampleCountFlags::TYPE_1,
+            alpha_to_coverage: false,
+            alpha_to_one: false,
+            color_formats: Vec::new(),
+            blend: false,
+            color_write_masks: Vec::new(),
+            depth_format: vk::Format::UNDEFINED,
+            depth_test: false,
+            depth_write: false,
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            depth_clamp: false,
+            depth_clip: None,
+            depth_bounds: None,
+            dynamic_depth_bounds: false,
+            input_attachment_indices: None,
+            library: None,
+            capture_statistics: false,
+        }
+    }
+
+    pub fn vertex_shader(mut self, module: vk::ShaderModule, entry: &'a CStr) -> Self {
+        self.vertex_shader = Some((module, entry));
+        self
+    }
+
+    pub fn fragment_shader(mut self, module: vk::ShaderModule, entry: &'a CStr) -> Self {
+        self.fragment_shader = Some((module, entry));
+        self
+    }
+
+    pub fn vertex_layout(mut self, value: VertexLayout) -> Self {
+        self.vertex_layout = value;
+        self
+    }
+
+    pub fn topology(mut self, value: vk::PrimitiveTopology) -> Self {
+        self.topology = value;
+        self
+    }
+
+    pub fn polygon_mode(mut self, value: vk::PolygonMode) -> Self {
+        self.polygon_mode = value;
+        self
+    }
+
+    pub fn cull_mode(mut self, value: vk::CullModeFlags) -> Self {
+        self.cull_mode = value;
+        self
+    }
+
+    pub fn front_face(mut self, value: vk::FrontFace) -> Self {
+        self.front_face = value;
+        self
+    }
+
+    pub fn samples(mut self, value: vk::SampleCountFlags) -> Self {
+        self.samples = value;
+        self
+    }
+
+    /// Derives sample coverage from the fragment alpha, useful for cutout
+    /// geometry rendered with MSAA.
+    pub fn alpha_to_coverage(mut self, value: bool) -> Self {
+        self.alpha_to_coverage = value;
+        self
+    }
+
+    /// Replaces fragment alpha with one after coverage is computed. Requires
+    /// `alphaToOne` device feature.
+    pub fn alpha_to_one(mut self, value: bool) -> Self {
+        self.alpha_to_one = value;
+        self
+    }
+
+    pub fn color_formats(mut self, value: &[vk::Format]) -> Self {
+        self.color_formats = value.to_vec();
+        self
+    }
+
+    /// Premultiplied alpha blending for all color attachments.
+    pub fn blend(mut self, value: bool) -> Self {
+        self.blend = value;
+        self
+    }
+
+    /// Channels written to the color attachment at `index`, all of them by
+    /// default.
+    pub fn color_write_mask(mut self, index: usize, mask: vk::ColorComponentFlags) -> Self {
+        if self.color_write_masks.len() <= index {
+            self.color_write_masks
+                .resize(index + 1, vk::ColorComponentFlags::RGBA);
+        }
+        self.color_write_masks[index] = mask;
+        self
+    }
+
+    pub fn depth_format(mut self, value: vk::Format) -> Self {
+        self.depth_format = value;
+        self
+    }
+
+    pub fn depth_test(mut self, test: bool, write: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth_test = test;
+        self.depth_write = write;
+        self.depth_compare_op = compare_op;
+        self
+    }
+
+    /// Clamps fragment depth instead of clipping primitives against near and
+    /// far planes. Requires `depthClamp` device feature.
+    pub fn depth_clamp(mut self, value: bool) -> Self {
+        self.depth_clamp = value;
+        self
+    }
+
+    /// Enables or disables depth clipping independently of depth clamp.
+    /// Requires `VK_EXT_depth_clip_enable`, without it clipping is disabled
+    /// only together with depth clamp.
+    pub fn depth_clip(mut self, value: bool) -> Self {
+        self.depth_clip = Some(value);
+        self
+    }
+
+    /// Discards fragments whose stored depth lies outside of `min..=max`.
+    /// Requires `depthBounds` device feature.
+    pub fn depth_bounds(mut self, min: f32, max: f32) -> Self {
+        self.depth_bounds = Some((min, max));
+        self
+    }
+
+    /// Enables depth bounds test with bounds set by `set_depth_bounds` of the
+    /// command buffer. Requires `depthBounds` device feature.
+    pub fn dynamic_depth_bounds(mut self) -> Self {
+        self.dynamic_depth_bounds = true;
+        self
+    }
+
+    /// Input attachment indices of attachments read by the fragment shader
+    /// during rendering. Requires `VK_KHR_dynamic_rendering_local_read`.
+    pub fn input_attachment_indices(mut self, value: InputAttachmentIndices) -> Self {
+        self.input_attachment_indices = Some(value);
+        self
+    }
+
+    /// Builds only the given parts of the pipeline as a library, to be linked
+    /// later with [`Device::link_pipeline_libraries`].
+    pub fn as_library(mut self, parts: vk::GraphicsPipelineLibraryFlagsEXT) -> Self {
+        self.library = Some(parts);
+        self
+    }
+
+    /// Keeps shader statistics and internal representations, see
+    /// [`Device::pipeline_executable_properties`].
+    pub fn capture_statistics(mut self, value: bool) -> Self {
+        self.capture_statistics = value;
+        self
+    }
+
+    pub fn build(
+        &self,
+        device: &Device,
+        cache: Option<&PipelineCache>,
+    ) -> Result<vk::Pipeline, BackendError> {
+        if self.library.is_some()
+            && !device.is_extension_enabled(ash::ext::graphics_pipeline_library::NAME)
+        {
+            return Err(BackendError::ExtensionNotEnabled(
+                ash::ext::graphics_pipeline_library::NAME,
+            ));
+        }
+        if self.depth_clamp && device.features.depth_clamp != vk::TRUE {
+            return Err(BackendError::FeatureNotEnabled("depthClamp"));
+        }
+        if self.alpha_to_one && device.features.alpha_to_one != vk::TRUE {
+            return Err(BackendError::FeatureNotEnabled("alphaToOne"));
+        }
+        let depth_bounds = self.depth_bounds.is_some() || self.dynamic_depth_bounds;
+        if depth_bounds && device.features.depth_bounds != vk::TRUE {
+            return Err(BackendError::FeatureNotEnabled("depthBounds"));
+        }
+        if self.depth_clip.is_some()
+            && !device.is_extension_enabled(ash::ext::depth_clip_enable::NAME)
+        {
+            return Err(BackendError::ExtensionNotEnabled(
+                ash::ext::depth_clip_enable::NAME,
+            ));
+        }
+        if self.input_attachment_indices.is_some() {
+            device.local_read_loader()?;
+        }
+        self.vertex_layout.validate(device)?;
+        let parts = self.library.unwrap_or(
+            vk::GraphicsPipelineLibraryFlagsEXT::VERTEX_INPUT_INTERFACE
+                | vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS
+                | vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER
+                | vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_OUTPUT_INTERFACE,
+        );
+        let mut stages = Vec::new();
+        if let Some((module, entry)) = self.vertex_shader
+            && parts.contains(vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS)
+        {
+            stages.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::VERTEX)
+                    .module(module)
+                    .name(entry),
+            );
+        }
+        if let Some((module, entry)) = self.fragment_shader
+            && parts.contains(vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER)
+        {
+            stages.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::FRAGMENT)
+                    .module(module)
+                    .name(entry),
+            );
+        }
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&self.vertex_layout.bindings)
+            .vertex_attribute_descriptions(&self.vertex_layout.attributes);
+        let input_assembly =
+            vk::PipelineInputAssemblyStateCreateInfo::default().topology(self.topology);
+        let viewport = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let mut depth_clip = vk::PipelineRasterizationDepthClipStateCreateInfoEXT::default()
+            .depth_clip_enable(self.depth_clip.unwrap_or(true));
+        let mut rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(self.polygon_mode)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_clamp_enable(self.depth_clamp)
+            .line_width(1.0);
+        if self.depth_clip.is_some() {
+            rasterization = rasterization.push_next(&mut depth_clip);
+        }
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(self.samples)
+            .alpha_to_coverage_enable(self.alpha_to_coverage)
+            .alpha_to_one_enable(self.alpha_to_one);
+        let (min_depth_bounds, max_depth_bounds) = self.depth_bounds.unwrap_or((0.0, 1.0));
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(self.depth_test)
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(self.depth_compare_op)
+            .depth_bounds_test_enable(depth_bounds)
+            .min_depth_bounds(min_depth_bounds)
+            .max_depth_bounds(max_depth_bounds);
+        let attachment = vk::PipelineColorBlendAttachmentState::default()
+            .blend_enable(self.blend)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(vk::ColorComponentFlags::RGBA);
+        let attachments = (0..self.color_formats.len())
+            .map(|index| {
+                let mask = self
+                    .color_write_masks
+                    .get(index)
+                    .copied()
+                    .unwrap_or(vk::ColorComponentFlags::RGBA);
+                attachment.color_write_mask(mask)
+            })
+            .collect::<Vec<_>>();
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&attachments);
+        let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        if self.dynamic_depth_bounds {
+            dynamic_states.push(vk::DynamicState::DEPTH_BOUNDS);
+        }
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+        let mut rendering = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&self.color_formats)
+            .depth_attachment_format(self.depth_format);
+        let mut library = vk::GraphicsPipelineLibraryCreateInfoEXT::default().flags(parts);
+        let mut input_indices = self
+            .input_attachment_indices
+            .as_ref()
+            .map(InputAttachmentIndices::info);
+
+        let mut info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic_state)
+            .layout(self.layout)
+            .push_next(&mut rendering);
+        let mut flags = vk::PipelineCreateFlags::empty();
+        if self.capture_statistics {
+            flags |= vk::PipelineCreateFlags::CAPTURE_STATISTICS_KHR
+                | vk::PipelineCreateFlags::CAPTURE_INTERNAL_REPRESENTATIONS_KHR;
+        }
+        if self.library.is_some() {
+            flags |= vk::PipelineCreateFlags::LIBRARY_KHR
+                | vk::PipelineCreateFlags::RETAIN_LINK_TIME_OPTIMIZATION_INFO_EXT;
+            info = info.push_next(&mut library);
+        }
+        if let Some(input_indices) = &mut input_indices {
+            info = info.push_next(input_indices);
+        }
+        info = info.flags(flags);
+        Ok(device.create_graphics_pipelines_batch(&[info], cache)?[0])
+    }
+}
+
+/// Values of specialization constants for one pipeline variant.
+pub trait Specialize {
+    fn specialization_info(&self) -> vk::SpecializationInfo<'_>;
+}
+
+impl Device {
+    pub fn create_shader_module(&self, code: &[u32]) -> Result<vk::ShaderModule, BackendError> {
+        const SPIRV_MAGIC: u32 = 0x0723_0203;
+        if code.first() != Some(&SPIRV_MAGIC) {
+            return Err(BackendError::InvalidArgument(
+                "Shader code isn't SPIR-V".into(),
+            ));
+        }
+        let info = vk::ShaderModuleCreateInfo::default().code(code);
+        Ok(unsafe { self.raw.create_shader_module(&info, None) }?)
+    }
+
+    /// Creates a compute pipeline for every variant of the shader in a single
+    /// driver call. Pipelines are returned in the order of `variants`.
+    pub fn create_compute_pipeline_variants<T: Specialize>(
+        &self,
+        spirv: &[u32],
+        entry: &CStr,
+        layout: vk::PipelineLayout,
+        variants: &[T],
+        cache: Option<&PipelineCache>,
+    ) -> Result<Vec<vk::Pipeline>, BackendError> {
+        let module = self.create_shader_module(spirv)?;
+        let specializations = variants
+            .iter()
+            .map(Specialize::specialization_info)
+            .collect::<Vec<_>>();
+        let infos = specializations
+            .iter()
+            .map(|specialization| {
+                let stage = vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::COMPUTE)
+                    .module(module)
+                    .name(entry)
+                    .specialization_info(specialization);
+                vk::ComputePipelineCreateInfo::default()
+                    .stage(stage)
+                    .layout(layout)
+            })
+            .collect::<Vec<_>>();
+        let pipelines = self.create_compute_pipelines_batch(&infos, cache);
+        // Pipelines don't reference the module after creation.
+        unsafe { self.raw.destroy_shader_module(module, None) };
+        pipelines
+    }
+
+    /// Creates shader modules, builds pipeline with `main` entry points and
+    /// destroys the modules. Without fragment shader only depth is written.
+    pub fn create_graphics_pipeline_from_spirv(
+        &self,
+        vert_spirv: &[u32],
+        frag_spirv: Option<&[u32]>,
+        layout: vk::PipelineLayout,
+        config: GraphicsPipelineConfig,
+        cache: Option<&PipelineCache>,
+    ) -> Result<vk::Pipeline, BackendError> {
+        let vertex = self.create_shader_module(vert_spirv)?;
+        let fragment = match frag_spirv.map(|spirv| self.create_shader_module(spirv)) {
+            Some(Ok(module)) => Some(module),
+            Some(Err(err)) => {
+                unsafe { self.raw.destroy_shader_module(vertex, None) };
+                return Err(err);
+            }
+            None => None,
+        };
+        let mut builder = GraphicsPipelineBuilder::new(layout)
+            .vertex_shader(vertex, c"main")
+            .vertex_layout(config.vertex_layout)
+            .color_formats(&config.color_formats)
+            .depth_format(config.depth_format)
+            .depth_test(
+                config.depth_test,
+                config.depth_write,
+                vk::CompareOp::LESS_OR_EQUAL,
+            )
+            .cull_mode(config.cull_mode)
+            .blend(config.blend);
+        if let Some(fragment) = fragment {
+            builder = builder.fragment_shader(fragment, c"main");
+        }
+        let pipeline = builder.build(self, cache);
+        unsafe {
+            self.raw.destroy_shader_module(vertex, None);
+            if let Some(fragment) = fragment {
+                self.raw.destroy_shader_module(fragment, None);
+            }
+        }
+        pipeline
+    }
+
+    /// Links pipeline libraries built with [`GraphicsPipelineBuilder::as_library`]
+    /// into a complete pipeline.
+    pub fn link_pipeline_libraries(
+        &self,
+        libs: &[vk::Pipeline],
+        layout: vk::PipelineLayout,
+        cache: Option<&PipelineCache>,
+    ) -> Result<vk::Pipeline, BackendError> {
+        if !self.is_extension_enabled(ash::ext::graphics_pipeline_library::NAME) {
+            return Err(BackendError::ExtensionNotEnabled(
+                ash::ext::graphics_pipeline_library::NAME,
+            ));
+        }
+        let mut library = vk::PipelineLibraryCreateInfoKHR::default().libraries(libs);
+        let info = vk::GraphicsPipelineCreateInfo::default()
+            .flags(vk::PipelineCreateFlags::LINK_TIME_OPTIMIZATION_EXT)
+            .layout(layout)
+            .push_next(&mut library);
+        Ok(self.create_graphics_pipelines_batch(&[info], cache)?[0])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineExecutableRepresentation {
+    pub name: String,
+    pub description: String,
+    pub is_text: bool,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineExecutableInfo {
+    pub name: String,
+    pub description: String,
+    pub stages: vk::ShaderStageFlags,
+    pub subgroup_size: u32,
+    pub statistics: Vec<vk::PipelineExecutableStatisticKHR<'static>>,
+    pub internal_representations: Vec<PipelineExecutableRepresentation>,
+}
+
+impl Device {
+    /// Queries compiler statistics of every executable of the pipeline. The
+    /// pipeline must be built with statistics capture enabled.
+    pub fn pipeline_executable_properties(
+        &self,
+        pipeline: vk::Pipeline,
+    ) -> Result<Vec<PipelineExecutableInfo>, BackendError> {
+        if !self.is_extension_enabled(ash::khr::pipeline_executable_properties::NAME) {
+            return Err(BackendError::ExtensionNotEnabled(
+                ash::khr::pipeline_executable_properties::NAME,
+            ));
+        }
+        let loader =
+            ash::khr::pipeline_executable_properties::Device::new(&self.instance.raw, &self.raw);
+        let pipeline_info = vk::PipelineInfoKHR::default().pipeline(pipeline);
+        let executables = unsafe { loader.get_pipeline_executable_properties(&pipeline_info) }?;
+        executables
+            .iter()
+            .enumerate()
+            .map(|(index, executable)| {
+                let info = vk::PipelineExecutableInfoKHR::default()
+                    .pipeline(pipeline)
+                    .executable_index(index as u32);
+                let statistics = unsafe { loader.get_pipeline_executable_statistics(&info) }?
+                    .into_iter()
+                    .map(|statistic| vk::PipelineExecutableStatisticKHR {
+                        p_next: ptr::null_mut(),
+                        _marker: Default::default(),
+                        ..statistic
+                    })
+                    .collect();
+                Ok(PipelineExecutableInfo {
+                    name: c_chars_to_string(&executable.name),
+                    description: c_chars_to_string(&executable.description),
+                    stages: executable.stages,
+                    subgroup_size: executable.subgroup_size,
+                    statistics,
+                    internal_representations: Self::internal_representations(&loader, &info)?,
+                })
+            })
+            .collect()
+    }
+
+    fn internal_representations(
+        loader: &ash::khr::pipeline_executable_properties::Device,
+        info: &vk::PipelineExecutableInfoKHR,
+    ) -> Result<Vec<PipelineExecutableRepresentation>, BackendError> {
+        // First pass only reports data sizes.
+        let mut representations =
+            unsafe { loader.get_pipeline_executable_internal_representations(info) }?;
+        let mut data = representations
+            .iter()
+            .map(|representation| vec![0u8; representation.data_size])
+            .collect::<Vec<_>>();
+        for (representation, data) in representations.iter_mut().zip(&mut data) {
+            representation.p_data = data.as_mut_ptr().cast();
+        }
+        let mut count = representations.len() as u32;
+        unsafe {
+            (loader
+                .fp()
+                .get_pipeline_executable_internal_representations_khr)(
+                loader.device(),
+                info,
+                &mut count,
+                representations.as_mut_ptr(),
+            )
+        }
+        .result()?;
+        Ok(representations
+            .iter()
+            .zip(data)
+            .map(|(representation, mut data)| {
+                data.truncate(representation.data_size);
+                PipelineExecutableRepresentation {
+                    name: c_chars_to_string(&representation.name),
+                    description: c_chars_to_string(&representation.description),
+                    is_text: representation.is_text == vk::TRUE,
+                    data,
+                }
+            })
+            .collect())
+    }
+}
+
+fn c_chars_to_string(chars: &[std::ffi::c_char]) -> String {
+    let bytes = chars
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+pub(crate) type PipelineLayoutKey = (
+    Vec<vk::DescriptorSetLayout>,
+    Vec<(vk::ShaderStageFlags, u32, u32)>,
+);
+
+#[derive(Debug, Default)]
+struct PipelineLayoutEntries {
+    layouts: HashMap<PipelineLayoutKey, (vk::PipelineLayout, u64)>,
+    clock: u64,
+}
+
+/// Pipeline layouts shared by equal set layouts and push constants. Holds at
+/// most `max_entries` layouts, the least recently used one is destroyed
+/// through the drop list when the cache is full, so it must not be used for
+/// recording after the next `get_or_create`.
+pub struct PipelineLayoutCache {
+    entries: Mutex<PipelineLayoutEntries>,
+    max_entries: usize,
+    device: Arc<Device>,
+}
+
+impl Debug for PipelineLayoutCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipelineLayoutCache")
+            .field("len", &self.entries.lock().layouts.len())
+            .field("max_entries", &self.max_entries)
+            .finish()
+    }
+}
+
+impl PipelineLayoutCache {
+    pub fn new(device: &Arc<Device>, max_entries: usize) -> Self {
+        Self {
+            entries: Mutex::default(),
+            max_entries: max_entries.max(1),
+            device: device.clone(),
+        }
+    }
+
+    pub fn get_or_create(
+        &self,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<vk::PipelineLayout, BackendError> {
+        let key = (
+            set_layouts.to_vec(),
+            push_constant_ranges
+                .iter()
+                .map(|range| (range.stage_flags, range.offset, range.size))
+                .collect(),
+        );
+        let mut entries = self.entries.lock();
+        entries.clock += 1;
+        let clock = entries.clock;
+        if let Some((layout, last_used)) = entries.layouts.get_mut(&key) {
+            *last_used = clock;
+            return Ok(*layout);
+        }
+        if entries.layouts.len() >= self.max_entries {
+            let oldest = entries
+                .layouts
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some((layout, _)) = oldest.and_then(|key| entries.layouts.remove(&key)) {
+                self.device.destroy_pipeline_layout(layout);
+            }
+        }
+        let layout = self
+            .device
+            .create_pipeline_layout(set_layouts, push_constant_ranges)?;
+        entries.layouts.insert(key, (layout, clock));
+        Ok(layout)
+    }
+}
+
+impl Drop for PipelineLayoutCache {
+    fn drop(&mut self) {
+        self.entries
+            .get_mut()
+            .layouts
+            .drain()
+            .for_each(|(_, (layout, _))| self.device.destroy_pipeline_layout(layout));
+    }
+}
+
+impl Device {
+    /// Creates pipeline layout, debug builds remember its sets and push
+    /// constants to validate binds recorded with it. Release it with
+    /// [`Device::destroy_pipeline_layout`].
+    pub fn create_pipeline_layout(
+        &self,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<vk::PipelineLayout, BackendError> {
+        let info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+        let layout = unsafe { self.raw.create_pipeline_layout(&info, None) }?;
+        #[cfg(debug_assertions)]
+        self.pipeline_layouts.lock().insert(
+            layout,
+            (
+                set_layouts.to_vec(),
+                push_constant_ranges
+                    .iter()
+                    .map(|range| (range.stage_flags, range.offset, range.size))
+                    .collect(),
+            ),
+        );
+        Ok(layout)
+    }
+
+    /// Destroys layout through the drop list.
+    pub fn destroy_pipeline_layout(&self, layout: vk::PipelineLayout) {
+        #[cfg(debug_assertions)]
+        self.pipeline_layouts.lock().remove(&layout);
+        self.with_drop_list(|drop_list| drop_list.drop_pipeline_layout(layout));
+    }
+
+    /// Checks that sets `first_set..first_set + set_count` exist in `layout`
+    /// and that the layout is compatible with the one bound for the bind
+    /// point. Layouts not created with [`Device::create_pipeline_layout`]
+    /// aren't checked.
+    #[cfg(debug_assertions)]
+    fn validate_layout(
+        &self,
+        layout: vk::PipelineLayout,
+        bound: Option<vk::PipelineLayout>,
+        set_count: usize,
+    ) -> Result<(), BackendError> {
+        let layouts = self.pipeline_layouts.lock();
+        let Some((sets, push_constants)) = layouts.get(&layout) else {
+            return Ok(());
+        };
+        if set_count > sets.len() {
+            return Err(BackendError::IncompatiblePipelineLayout(format!(
+                "{layout:?} has {} sets, {set_count} are bound",
+                sets.len()
+            )));
+        }
+        if let Some((bound_sets, bound_push_constants)) = bound
+            .filter(|bound| *bound != layout)
+            .and_then(|bound| layouts.get(&bound))
+            && (bound_push_constants != push_constants
+                || bound_sets.len() < set_count
+                || bound_sets[..set_count] != sets[..set_count])
+        {
+            return Err(BackendError::IncompatiblePipelineLayout(format!(
+                "{layout:?} isn't compatible with bound {:?} for {set_count} sets",
+                bound.unwrap()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Parameters of an indexed draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawIndexed {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+impl DrawIndexed {
+    /// Single instance drawing `index_count` indices from the start.
+    pub fn new(index_count: u32) -> Self {
+        Self {
+            index_count,
+            instance_count: 1,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+        }
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Binds pipeline to the main command buffer, `layout` is the layout it
+    /// was created with and is used to check following binds in debug builds.
+    pub fn bind_pipeline(
+        &self,
+        bind_point: vk::PipelineBindPoint,
+        pipeline: vk::Pipeline,
+        layout: vk::PipelineLayout,
+    ) {
+        #[cfg(debug_assertions)]
+        self.bound_layouts.lock().insert(bind_point, layout);
+        #[cfg(not(debug_assertions))]
+        let _ = layout;
+        unsafe {
+            self.device
+                .raw
+                .cmd_bind_pipeline(self.main_cb().raw(), bind_point, pipeline)
+        };
+    }
+
+    /// Binds descriptor sets to the main command buffer. Debug builds check
+    /// first that `layout` has the sets and is compatible with the layout of
+    /// the pipeline bound by [`Frame::bind_pipeline`].
+    pub fn bind_descriptor_sets(
+        &self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) -> Result<(), BackendError> {
+        #[cfg(debug_assertions)]
+        {
+            let bound = self.bound_layouts.lock().get(&bind_point).copied();
+            self.device
+                .validate_layout(layout, bound, first_set as usize + sets.len())?;
+        }
+        unsafe {
+            self.device.raw.cmd_bind_descriptor_sets(
+                self.main_cb().raw(),
+                bind_point,
+                layout,
+                first_set,
+                sets,
+                dynamic_offsets,
+            )
+        };
+        Ok(())
+    }
+
+    /// Updates push constants in the main command buffer. Debug builds check
+    /// first that every byte is covered by ranges of `layout` for all of
+    /// `stages` and that `stages` include every range overlapping the data.
+    pub fn push_constants(
+        &self,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        data: &[u8],
+    ) -> Result<(), BackendError> {
+        #[cfg(debug_assertions)]
+        self.validate_push_constants(layout, stages, offset, data.len() as u32)?;
+        unsafe {
+            self.device
+                .raw
+                .cmd_push_constants(self.main_cb().raw(), layout, stages, offset, data)
+        };
+        Ok(())
+    }
+
+    pub fn draw_indexed(&self, draw: DrawIndexed) {
+        unsafe {
+            self.device.raw.cmd_draw_indexed(
+                self.main_cb().raw(),
+                draw.index_count,
+                draw.instance_count,
+                draw.first_index,
+                draw.vertex_offset,
+                draw.first_instance,
+            )
+        };
+    }
+
+    /// Pushes `push_data` at offset 0 and draws, for per-draw constants.
+    pub fn draw_indexed_with_push<T: Copy>(
+        &self,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        push_data: &T,
+        draw: DrawIndexed,
+    ) -> Result<(), BackendError> {
+        self.push_constants(layout, stages, 0, as_bytes(std::slice::from_ref(push_data)))?;
+        self.draw_indexed(draw);
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    fn validate_push_constants(
+        &self,
+        layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        size: u32,
+    ) -> Result<(), BackendError> {
+        if let Some(bind_point) = stage_bind_point(stages) {
+            let bound = self.bound_layouts.lock().get(&bind_point).copied();
+            self.device.validate_layout(layout, bound, 0)?;
+        }
+        let layouts = self.device.pipeline_layouts.lock();
+        let Some((_, ranges)) = layouts.get(&layout) else {
+            return Ok(());
+        };
+        for byte in offset..offset + size {
+            let overlapping = ranges
+                .iter()
+                .filter(|(_, offset, size)| (*offset..offset + size).contains(&byte));
+            let mut covered = vk::ShaderStageFlags::empty();
+            for (range_stages, _, _) in overlapping {
+                if !stages.contains(*range_stages) {
+                    return Err(BackendError::IncompatiblePipelineLayout(format!(
+                        "push constant byte {byte} is also used by {range_stages:?}, pushed for {stages:?}"
+                    )));
+                }
+                covered |= *range_stages;
+            }
+            if !covered.contains(stages) {
+                return Err(BackendError::IncompatiblePipelineLayout(format!(
+                    "push constant byte {byte} isn't in {layout:?} for {stages:?}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bind point whose pipelines have all of `stages`.
+#[cfg(debug_assertions)]
+fn stage_bind_point(stages: vk::ShaderStageFlags) -> Option<vk::PipelineBindPoint> {
+    if stages == vk::ShaderStageFlags::COMPUTE {
+        Some(vk::PipelineBindPoint::COMPUTE)
+    } else if !stages.intersects(vk::ShaderStageFlags::COMPUTE) {
+        Some(vk::PipelineBindPoint::GRAPHICS)
+    } else {
+        None
+    }
+}
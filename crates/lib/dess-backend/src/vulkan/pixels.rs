@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, Image, ImageDesc, ImageState, cmd_transition_image},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    R8Unorm,
+    R8Srgb,
+    Rg8Unorm,
+    Rg8Srgb,
+    Rgb8Unorm,
+    Rgb8Srgb,
+    Rgba8Unorm,
+    Rgba8Srgb,
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::R8Unorm | PixelFormat::R8Srgb => 1,
+            PixelFormat::Rg8Unorm | PixelFormat::Rg8Srgb => 2,
+            PixelFormat::Rgb8Unorm | PixelFormat::Rgb8Srgb => 3,
+            PixelFormat::Rgba8Unorm | PixelFormat::Rgba8Srgb => 4,
+            PixelFormat::Rgba16Float => 8,
+            PixelFormat::Rgba32Float => 16,
+        }
+    }
+
+    fn native(self) -> vk::Format {
+        match self {
+            PixelFormat::R8Unorm => vk::Format::R8_UNORM,
+            PixelFormat::R8Srgb => vk::Format::R8_SRGB,
+            PixelFormat::Rg8Unorm => vk::Format::R8G8_UNORM,
+            PixelFormat::Rg8Srgb => vk::Format::R8G8_SRGB,
+            PixelFormat::Rgb8Unorm => vk::Format::R8G8B8_UNORM,
+            PixelFormat::Rgb8Srgb => vk::Format::R8G8B8_SRGB,
+            PixelFormat::Rgba8Unorm => vk::Format::R8G8B8A8_UNORM,
+            PixelFormat::Rgba8Srgb => vk::Format::R8G8B8A8_SRGB,
+            PixelFormat::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+            PixelFormat::Rgba32Float => vk::Format::R32G32B32A32_SFLOAT,
+        }
+    }
+
+    /// Format the data can be expanded to when the native one isn't supported.
+    fn expanded(self) -> Option<vk::Format> {
+        match self {
+            PixelFormat::Rgb8Unorm => Some(vk::Format::R8G8B8A8_UNORM),
+            PixelFormat::Rgb8Srgb => Some(vk::Format::R8G8B8A8_SRGB),
+            _ => None,
+        }
+    }
+}
+
+fn expand_rgb8(data: &[u8]) -> Vec<u8> {
+    data.chunks_exact(3)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+        .collect()
+}
+
+impl Device {
+    /// Creates sampled 2D image from tightly packed pixels. Picks native
+    /// format if the device supports it and expands RGB8 data to RGBA8
+    /// otherwise. Mips are generated by blitting on the GPU.
+    pub fn create_image_from_pixels(
+        self: &Arc<Self>,
+        extent: vk::Extent2D,
+        format: PixelFormat,
+        data: &[u8],
+        generate_mips: bool,
+    ) -> Result<Image, BackendError> {
+        if extent.width == 0 || extent.height == 0 || data.is_empty() {
+            return Err(BackendError::EmptyImage);
+        }
+        let stride = format.bytes_per_pixel();
+        if !data.len().is_multiple_of(stride) {
+            return Err(BackendError::PixelDataStride {
+                len: data.len(),
+                stride,
+            });
+        }
+        let expected = extent.width as usize * extent.height as usize * stride;
+        if data.len() != expected {
+            return Err(BackendError::PixelDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        let mut required =
+            vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST;
+        if generate_mips {
+            required |= vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        }
+        let supports = |vk_format| self.format_features(vk_format).contains(required);
+        let (vk_format, data) = if supports(format.native()) {
+            (format.native(), data.to_vec())
+        } else if let Some(expanded) = format.expanded().filter(|&format| supports(format)) {
+            (expanded, expand_rgb8(data))
+        } else {
+            return Err(BackendError::UnsupportedFormat(format.native()));
+        };
+
+        let mip_levels = if generate_mips {
+            32 - extent.width.max(extent.height).leading_zeros()
+        } else {
+            1
+        };
+        let mut usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+        if generate_mips {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let desc = ImageDesc::texture_2d(vk_format, extent.width, extent.height)
+            .usage(usage)
+            .mip_levels(mip_levels);
+        let image = self.create_image(desc)?;
+        let aspect = image.aspect();
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(aspect)
+                    .mip_level(0)
+                    .layer_count(1),
+            )
+            .image_extent(desc.extent);
+        let level_range = |level| {
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect)
+                .base_mip_level(level)
+                .level_count(1)
+                .layer_count(1)
+        };
+
+        self.upload_with_staging(&data, |device, cb, staging| unsafe {
+            cmd_transition_image(
+                device,
+                cb,
+                image.raw,
+                image.subresource_range(),
+                ImageState::UNDEFINED,
+                ImageState::TRANSFER_DST,
+            );
+            device.cmd_copy_buffer_to_image(
+                cb,
+                staging,
+                image.raw,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+            for level in 1..mip_levels {
+                cmd_transition_image(
+                    device,
+                    cb,
+                    image.raw,
+                    level_range(level - 1),
+                    ImageState::TRANSFER_DST,
+                    ImageState::TRANSFER_SRC,
+                );
+                let src = desc.mip_extent(level - 1);
+                let dst = desc.mip_extent(level);
+                let blit = vk::ImageBlit::default()
+                    .src_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(aspect)
+                            .mip_level(level - 1)
+                            .layer_count(1),
+                    )
+                    .src_offsets([
+                        vk::Offset3D::default(),
+                        vk::Offset3D {
+                            x: src.width as i32,
+                            y: src.height as i32,
+                            z: 1,
+                        },
+                    ])
+                    .dst_subresource(
+                        vk::ImageSubresourceLayers::default()
+                            .aspect_mask(aspect)
+                            .mip_level(level)
+                            .layer_count(1),
+                    )
+                    .dst_offsets([
+                        vk::Offset3D::default(),
+                        vk::Offset3D {
+                            x: dst.width as i32,
+                            y: dst.height as i32,
+                            z: 1,
+                        },
+                    ]);
+                device.cmd_blit_image(
+                    cb,
+                    image.raw,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image.raw,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR,
+                );
+                cmd_transition_image(
+                    device,
+                    cb,
+                    image.raw,
+                    level_range(level - 1),
+                    ImageState::TRANSFER_SRC,
+                    ImageState::SHADER_READ,
+                );
+            }
+            cmd_transition_image(
+                device,
+                cb,
+                image.raw,
+                level_range(mip_levels - 1),
+                ImageState::TRANSFER_DST,
+                ImageState::SHADER_READ,
+            );
+        })?;
+        Ok(image)
+    }
+}
@@ -0,0 +1,403 @@
+use std::ffi::CString;
+
+use ash::vk;
+use log::warn;
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, Frame},
+};
+
+/// Marks GPU work recorded into command buffers of a frame. Passes may
+/// nest, every `begin_gpu_pass` needs a matching `end_gpu_pass` in the same
+/// command buffer.
+pub trait Profiler: Send + Sync {
+    /// Called by [`Device::frame`] for every new frame of the device's own
+    /// profiler, before anything is recorded.
+    fn begin_frame(&self, _frame: &Frame) {}
+    fn begin_gpu_pass(&self, frame: &Frame, name: &str, cb: vk::CommandBuffer);
+    fn end_gpu_pass(&self, frame: &Frame, cb: vk::CommandBuffer);
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    #[default]
+    None,
+    /// Debug utils labels, visible in RenderDoc and Nsight captures. Requires
+    /// instance created with debug utils.
+    DebugLabels,
+    /// GPU zones in Tracy, measured with timestamp queries.
+    #[cfg(feature = "tracy")]
+    Tracy,
+}
+
+#[derive(Debug, Default)]
+pub struct NoopProfiler;
+
+impl Profiler for NoopProfiler {
+    fn begin_gpu_pass(&self, _frame: &Frame, _name: &str, _cb: vk::CommandBuffer) {}
+
+    fn end_gpu_pass(&self, _frame: &Frame, _cb: vk::CommandBuffer) {}
+}
+
+pub struct DebugLabelProfiler {
+    loader: ash::ext::debug_utils::Device,
+}
+
+impl DebugLabelProfiler {
+    pub(crate) fn new(loader: ash::ext::debug_utils::Device) -> Self {
+        Self { loader }
+    }
+}
+
+impl Profiler for DebugLabelProfiler {
+    fn begin_gpu_pass(&self, _frame: &Frame, name: &str, cb: vk::CommandBuffer) {
+        let name = CString::new(name).unwrap_or_else(|_| {
+            warn!("Pass name {name:?} contains nul byte");
+            CString::default()
+        });
+        let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+        unsafe { self.loader.cmd_begin_debug_utils_label(cb, &label) };
+    }
+
+    fn end_gpu_pass(&self, _frame: &Frame, cb: vk::CommandBuffer) {
+        unsafe { self.loader.cmd_end_debug_utils_label(cb) };
+    }
+}
+
+impl Device {
+    /// Whether `Frame::debug_marker_*` commands reach capture tools, through
+    /// debug utils or the older `VK_EXT_debug_marker`.
+    pub fn supports_debug_marker(&self) -> bool {
+        self.debug_utils.is_some() || self.debug_marker.is_some()
+    }
+}
+
+// Debug marker is deprecated in favor of debug utils, it's only the fallback.
+#[allow(deprecated)]
+impl<'a> Frame<'a> {
+    /// Opens a labeled region of the main command buffer, closed by
+    /// [`Frame::debug_marker_end`]. Does nothing without debug marker support.
+    pub fn debug_marker_begin(&self, name: &str, color: [f32; 4]) {
+        let cb = self.main_cb().raw();
+        let name = marker_name(name);
+        if let Some(debug_utils) = &self.device.debug_utils {
+            let label = vk::DebugUtilsLabelEXT::default()
+                .label_name(&name)
+                .color(color);
+            unsafe { debug_utils.cmd_begin_debug_utils_label(cb, &label) };
+        } else if let Some(debug_marker) = &self.device.debug_marker {
+            let marker = vk::DebugMarkerMarkerInfoEXT::default()
+                .marker_name(&name)
+                .color(color);
+            unsafe { debug_marker.cmd_debug_marker_begin(cb, &marker) };
+        }
+    }
+
+    pub fn debug_marker_end(&self) {
+        let cb = self.main_cb().raw();
+        if let Some(debug_utils) = &self.device.debug_utils {
+            unsafe { debug_utils.cmd_end_debug_utils_label(cb) };
+        } else if let Some(debug_marker) = &self.device.debug_marker {
+            unsafe { debug_marker.cmd_debug_marker_end(cb) };
+        }
+    }
+
+    /// Inserts a single label into the main command buffer.
+    pub fn debug_marker_insert(&self, name: &str, color: [f32; 4]) {
+        let cb = self.main_cb().raw();
+        let name = marker_name(name);
+        if let Some(debug_utils) = &self.device.debug_utils {
+            let label = vk::DebugUtilsLabelEXT::default()
+                .label_name(&name)
+                .color(color);
+            unsafe { debug_utils.cmd_insert_debug_utils_label(cb, &label) };
+        } else if let Some(debug_marker) = &self.device.debug_marker {
+            let marker = vk::DebugMarkerMarkerInfoEXT::default()
+                .marker_name(&name)
+                .color(color);
+            unsafe { debug_marker.cmd_debug_marker_insert(cb, &marker) };
+        }
+    }
+}
+
+fn marker_name(name: &str) -> CString {
+    CString::new(name).unwrap_or_else(|_| {
+        warn!("Marker name {name:?} contains nul byte");
+        CString::default()
+    })
+}
+
+#[cfg(feature = "tracy")]
+pub use tracy::TracyProfiler;
+
+#[cfg(feature = "tracy")]
+mod tracy {
+    use std::collections::HashMap;
+
+    use ash::vk;
+    use parking_lot::Mutex;
+
+    use crate::{
+        BackendError,
+        vulkan::{Frame, Profiler},
+    };
+
+    #[derive(Debug, Clone, Copy)]
+    struct Event {
+        /// `None` when the frame ran out of queries for the span end.
+        query: Option<u32>,
+        span: u64,
+        end: bool,
+    }
+
+    #[derive(Default)]
+    struct State {
+        next_span: u64,
+        /// Queries of each frame slot in the order they were written, Tracy
+        /// wants timestamps of nested zones in this order. They're uploaded
+        /// when the slot is reused and its timestamps are available.
+        events: [Vec<Event>; 2],
+        spans: HashMap<u64, tracy_client::GpuSpan>,
+        /// Spans open in each command buffer.
+        open: HashMap<vk::CommandBuffer, Vec<Option<u64>>>,
+    }
+
+    /// GPU zones measured with timestamps of frame queries, see
+    /// [`Frame::write_timestamp`].
+    pub struct TracyProfiler {
+        context: tracy_client::GpuContext,
+        state: Mutex<State>,
+    }
+
+    impl TracyProfiler {
+        /// Calibrates GPU clock with a blocking submit to `queue`, so it must
+        /// not be used by anything else at the moment.
+        pub(crate) fn new(
+            device: &ash::Device,
+            queue: vk::Queue,
+            queue_family_index: u32,
+            timestamp_period: f32,
+        ) -> Result<Self, BackendError> {
+            let timestamp = Self::calibrate(device, queue, queue_family_index)?;
+            let context = tracy_client::Client::start()
+                .new_gpu_context(
+                    Some("dess"),
+                    tracy_client::GpuContextType::Vulkan,
+                    timestamp,
+                    timestamp_period,
+                )
+                .map_err(|err| BackendError::ProfilerError(err.to_string()))?;
+            Ok(Self {
+                context,
+                state: Mutex::default(),
+            })
+        }
+
+        fn calibrate(
+            device: &ash::Device,
+            queue: vk::Queue,
+            queue_family_index: u32,
+        ) -> Result<i64, BackendError> {
+            let info = vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(1);
+            let pool = unsafe { device.create_query_pool(&info, None) }?;
+            let pool_info =
+                vk::CommandPoolCreateInfo::default().queue_family_index(queue_family_index);
+            let command_pool = match unsafe { device.create_command_pool(&pool_info, None) } {
+                Ok(command_pool) => command_pool,
+                Err(err) => {
+                    unsafe { device.destroy_query_pool(pool, None) };
+                    return Err(err.into());
+                }
+            };
+            let result = (|| {
+                let info = vk::CommandBufferAllocateInfo::default()
+                    .command_buffer_count(1)
+                    .command_pool(command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY);
+                let cb = unsafe { device.allocate_command_buffers(&info) }?[0];
+                let begin = vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                unsafe {
+                    device.begin_command_buffer(cb, &begin)?;
+                    device.cmd_reset_query_pool(cb, pool, 0, 1);
+                    device.cmd_write_timestamp2(cb, vk::PipelineStageFlags2::ALL_COMMANDS, pool, 0);
+                    device.end_command_buffer(cb)?;
+                }
+                let command_buffers = [vk::CommandBufferSubmitInfo::default().command_buffer(cb)];
+                let submit = vk::SubmitInfo2::default().command_buffer_infos(&command_buffers);
+                unsafe {
+                    device.queue_submit2(queue, &[submit], vk::Fence::null())?;
+                    device.queue_wait_idle(queue)?;
+                }
+                let mut timestamp = [0u64];
+                unsafe {
+                    device.get_query_pool_results(
+                        pool,
+                        0,
+                        &mut timestamp,
+                        vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                    )
+                }?;
+                Ok::<_, BackendError>(timestamp[0] as i64)
+            })();
+            unsafe {
+                device.destroy_command_pool(command_pool, None);
+                device.destroy_query_pool(pool, None);
+            }
+            result
+        }
+    }
+
+    impl Profiler for TracyProfiler {
+        /// Uploads timestamps written the last time the frame slot was used.
+        fn begin_frame(&self, frame: &Frame) {
+            let mut state = self.state.lock();
+            let state = &mut *state;
+            let slot = (frame.device.frame_index() % 2) as usize;
+            let timestamps = frame.previous_timestamps();
+            // Missing results repeat the previous timestamp, so uploads stay
+            // monotonic and every span is closed.
+            let mut last = 0;
+            for event in state.events[slot].drain(..) {
+                if let Some(timestamp) =
+                    event.query.and_then(|query| timestamps.get(query as usize))
+                {
+                    last = *timestamp as i64;
+                }
+                if event.end {
+                    if let Some(span) = state.spans.remove(&event.span) {
+                        span.upload_timestamp_end(last);
+                    }
+                } else if let Some(span) = state.spans.get(&event.span) {
+                    span.upload_timestamp_start(last);
+                }
+            }
+        }
+
+        fn begin_gpu_pass(&self, frame: &Frame, name: &str, cb: vk::CommandBuffer) {
+            let mut state = self.state.lock();
+            let slot = (frame.device.frame_index() % 2) as usize;
+            // Spans are skipped once the frame is out of queries.
+            let span = frame
+                .write_timestamp_to(cb, vk::PipelineStageFlags2::TOP_OF_PIPE)
+                .and_then(|query| Some((self.context.span_alloc(name, "", "", 0).ok()?, query)))
+                .map(|(span, query)| {
+                    let id = state.next_span;
+                    state.next_span += 1;
+                    state.events[slot].push(Event {
+                        query: Some(query),
+                        span: id,
+                        end: false,
+                    });
+                    state.spans.insert(id, span);
+                    id
+                });
+            state.open.entry(cb).or_default().push(span);
+        }
+
+        fn end_gpu_pass(&self, frame: &Frame, cb: vk::CommandBuffer) {
+            let mut state = self.state.lock();
+            let Some(Some(id)) = state.open.get_mut(&cb).and_then(|open| open.pop()) else {
+                return;
+            };
+            if state.open.get(&cb).is_some_and(|open| open.is_empty()) {
+                state.open.remove(&cb);
+            }
+            let slot = (frame.device.frame_index() % 2) as usize;
+            let query = frame.write_timestamp_to(cb, vk::PipelineStageFlags2::BOTTOM_OF_PIPE);
+            if let Some(span) = state.spans.get_mut(&id) {
+                span.end_zone();
+            }
+            state.events[slot].push(Event {
+                query,
+                span: id,
+                end: true,
+            });
+        }
+    }
+}
+
+/// Measures GPU time between two points of the main command buffer,
+/// usually the whole frame, with timestamps of frame queries.
+#[derive(Debug)]
+pub struct FrameTimer {
+    /// Begin and end queries written into each frame slot.
+    spans: Mutex<[(Option<u32>, Option<u32>); 2]>,
+    elapsed: Mutex<Option<u64>>,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Bits of timestamps the main queue writes, the rest are undefined.
+    valid_mask: u64,
+}
+
+impl Device {
+    /// Fails without timestamps with host query reset, which frame queries
+    /// require.
+    pub fn create_frame_timer(&self) -> Result<FrameTimer, BackendError> {
+        if !self.has_frame_queries() {
+            return Err(BackendError::FeatureNotEnabled("timestamp queries"));
+        }
+        let valid_bits = self.pdevice.queue_families[self.main_queue.queue_family_index as usize]
+            .properties
+            .timestamp_valid_bits;
+        Ok(FrameTimer {
+            spans: Mutex::default(),
+            elapsed: Mutex::default(),
+            period: self.pdevice.properties.limits.timestamp_period,
+            valid_mask: u64::MAX >> (64 - valid_bits),
+        })
+    }
+}
+
+impl FrameTimer {
+    /// Writes the begin timestamp and reads the span written the last time
+    /// the frame slot was used, which is available without waiting.
+    pub fn begin(&self, frame: &Frame) {
+        let slot = (frame.device.frame_index() % 2) as usize;
+        let mut spans = self.spans.lock();
+        let timestamps = frame.previous_timestamps();
+        *self.elapsed.lock() = match spans[slot] {
+            (Some(begin), Some(end)) => timestamps
+                .get(end as usize)
+                .zip(timestamps.get(begin as usize))
+                .map(|(end, begin)| {
+                    // Counters wrap at the valid bits.
+                    let ticks = (end & self.valid_mask).wrapping_sub(begin & self.valid_mask)
+                        & self.valid_mask;
+                    (ticks as f64 * self.period as f64) as u64
+                }),
+            _ => None,
+        };
+        spans[slot] = (
+            frame.write_timestamp(vk::PipelineStageFlags2::TOP_OF_PIPE),
+            None,
+        );
+    }
+
+    pub fn end(&self, frame: &Frame) {
+        let slot = (frame.device.frame_index() % 2) as usize;
+        self.spans.lock()[slot].1 = frame.write_timestamp(vk::PipelineStageFlags2::BOTTOM_OF_PIPE);
+    }
+
+    /// Time between `begin` and `end` of the frame before the previous one,
+    /// updated by every `begin`. `None` until two frames are measured or when
+    /// a frame ran out of queries.
+    pub fn elapsed_ns(&self) -> Option<u64> {
+        *self.elapsed.lock()
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Wraps commands recorded by `f` into a profiler pass in the main command
+    /// buffer.
+    pub fn with_profiler<F: FnOnce(&Frame)>(&self, profiler: &dyn Profiler, name: &str, f: F) {
+        let cb = self.main_cb().raw();
+        profiler.begin_gpu_pass(self, name, cb);
+        f(self);
+        profiler.end_gpu_pass(self, cb);
+    }
+}
@@ -0,0 +1,757 @@
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{
+        BufferState, Device, Frame, Image, ImageDesc, ImageState, RenderTargetPool, buffer_barrier,
+        format_aspect, full_subresource_range, image_barrier,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GraphImage(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GraphBuffer(usize);
+
+#[derive(Debug, Clone, Copy)]
+enum ImageSource {
+    Imported {
+        raw: vk::Image,
+        view: vk::ImageView,
+        initial: ImageState,
+    },
+    Transient(ImageDesc),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GraphImageInfo {
+    source: ImageSource,
+    aspect: vk::ImageAspectFlags,
+    final_state: Option<ImageState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GraphBufferInfo {
+    raw: vk::Buffer,
+    initial: BufferState,
+}
+
+/// Resources resolved for the pass being recorded.
+pub struct PassContext<'a> {
+    pub device: &'a Device,
+    pub cb: vk::CommandBuffer,
+    images: &'a [(vk::Image, vk::ImageView)],
+    buffers: &'a [vk::Buffer],
+}
+
+impl<'a> PassContext<'a> {
+    pub fn image(&self, image: GraphImage) -> vk::Image {
+        self.images[image.0].0
+    }
+
+    pub fn view(&self, image: GraphImage) -> vk::ImageView {
+        self.images[image.0].1
+    }
+
+    pub fn buffer(&self, buffer: GraphBuffer) -> vk::Buffer {
+        self.buffers[buffer.0]
+    }
+}
+
+type PassCallback<'a> = Box<dyn FnOnce(&PassContext) + 'a>;
+
+struct Pass<'a> {
+    name: &'static str,
+    images: Vec<(GraphImage, ImageState, bool)>,
+    buffers: Vec<(GraphBuffer, BufferState, bool)>,
+    execute: Option<PassCallback<'a>>,
+}
+
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: Pass<'a>,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    /// Declarations of the same image in the same layout are merged, in
+    /// different layouts they fail [`RenderGraph::compile`].
+    pub fn read_image(mut self, image: GraphImage, state: ImageState) -> Self {
+        self.declare_image(image, state, false);
+        self
+    }
+
+    pub fn write_image(mut self, image: GraphImage, state: ImageState) -> Self {
+        self.declare_image(image, state, true);
+        self
+    }
+
+    /// Declarations of the same buffer are merged.
+    pub fn read_buffer(mut self, buffer: GraphBuffer, state: BufferState) -> Self {
+        self.declare_buffer(buffer, state, false);
+        self
+    }
+
+    pub fn write_buffer(mut self, buffer: GraphBuffer, state: BufferState) -> Self {
+        self.declare_buffer(buffer, state, true);
+        self
+    }
+
+    fn declare_image(&mut self, image: GraphImage, state: ImageState, write: bool) {
+        let existing =
+            self.pass.images.iter_mut().find(|(other, other_state, _)| {
+                *other == image && other_state.layout == state.layout
+            });
+        if let Some((_, existing, existing_write)) = existing {
+            existing.stage |= state.stage;
+            existing.access |= state.access;
+            *existing_write |= write;
+        } else {
+            self.pass.images.push((image, state, write));
+        }
+    }
+
+    fn declare_buffer(&mut self, buffer: GraphBuffer, state: BufferState, write: bool) {
+        let existing = self
+            .pass
+            .buffers
+            .iter_mut()
+            .find(|(other, ..)| *other == buffer);
+        if let Some((_, existing, existing_write)) = existing {
+            existing.stage |= state.stage;
+            existing.access |= state.access;
+            *existing_write |= write;
+        } else {
+            self.pass.buffers.push((buffer, state, write));
+        }
+    }
+
+    /// Adds the pass to the graph. The closure records into the frame's main
+    /// command buffer after all barriers for declared resources.
+    pub fn execute<F: FnOnce(&PassContext) + 'a>(mut self, cb: F) {
+        self.pass.execute = Some(Box::new(cb));
+        self.graph.passes.push(self.pass);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphImageTransition {
+    pub image: GraphImage,
+    pub from: ImageState,
+    pub to: ImageState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphBufferTransition {
+    pub buffer: GraphBuffer,
+    pub from: BufferState,
+    pub to: BufferState,
+}
+
+/// Barriers to record before the pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompiledPass {
+    pub pass: usize,
+    pub images: Vec<GraphImageTransition>,
+    pub buffers: Vec<GraphBufferTransition>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompiledGraph {
+    pub passes: Vec<CompiledPass>,
+    /// Transitions of imported images into their final states.
+    pub finish: Vec<GraphImageTransition>,
+}
+
+/// Single queue frame graph. Accesses of a resource keep the order their
+/// passes were added in: reads run after the previous write, writes after
+/// the previous write and the reads since, so a history image can be read
+/// before the pass overwriting it.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<GraphImageInfo>,
+    buffers: Vec<GraphBufferInfo>,
+    passes: Vec<Pass<'a>>,
+}
+
+const WRITE_ACCESS: vk::AccessFlags2 = vk::AccessFlags2::from_raw(
+    vk::AccessFlags2::SHADER_WRITE.as_raw()
+        | vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw()
+        | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags2::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags2::HOST_WRITE.as_raw()
+        | vk::AccessFlags2::MEMORY_WRITE.as_raw()
+        | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR.as_raw(),
+);
+
+/// Synchronization state of a resource while walking the passes.
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    layout: vk::ImageLayout,
+    /// Last write or layout transition.
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    /// Reads since the last write that are already synchronized with it.
+    read_stage: vk::PipelineStageFlags2,
+    read_access: vk::AccessFlags2,
+}
+
+impl Tracked {
+    fn new(
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags2,
+        access: vk::AccessFlags2,
+    ) -> Self {
+        if access.intersects(WRITE_ACCESS) {
+            Self {
+                layout,
+                write_stage: stage,
+                write_access: access & WRITE_ACCESS,
+                read_stage: vk::PipelineStageFlags2::NONE,
+                read_access: vk::AccessFlags2::NONE,
+            }
+        } else {
+            Self {
+                layout,
+                write_stage: vk::PipelineStageFlags2::NONE,
+                write_access: vk::AccessFlags2::NONE,
+                read_stage: stage,
+                read_access: access,
+            }
+        }
+    }
+
+    /// Returns source stage and access of the barrier needed before the
+    /// usage, if any.
+    fn transition(
+        &mut self,
+        layout: vk::ImageLayout,
+        stage: vk::PipelineStageFlags2,
+        access: vk::AccessFlags2,
+        write: bool,
+    ) -> Option<(vk::ImageLayout, vk::PipelineStageFlags2, vk::AccessFlags2)> {
+        let old_layout = self.layout;
+        if !write && layout == self.layout {
+            if self.read_stage.contains(stage) && self.read_access.contains(access) {
+                return None;
+            }
+            self.read_stage |= stage;
+            self.read_access |= access;
+            if self.write_stage.is_empty() {
+                return None;
+            }
+            return Some((old_layout, self.write_stage, self.write_access));
+        }
+        let src_stage = self.write_stage | self.read_stage;
+        let src_access = self.write_access;
+        *self = if write {
+            Self {
+                layout,
+                write_stage: stage,
+                write_access: access & WRITE_ACCESS,
+                read_stage: vk::PipelineStageFlags2::NONE,
+                read_access: vk::AccessFlags2::NONE,
+            }
+        } else {
+            // Layout transition is a write, later reads from other stages
+            // have to wait for the stage that waited for it.
+            Self {
+                layout,
+                write_stage: stage,
+                write_access: vk::AccessFlags2::NONE,
+                read_stage: stage,
+                read_access: access,
+            }
+        };
+        if old_layout == layout && src_stage.is_empty() {
+            return None;
+        }
+        Some((old_layout, src_stage, src_access))
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses image that lives outside of the graph, e.g. a swapchain image.
+    pub fn import_image(
+        &mut self,
+        raw: vk::Image,
+        view: vk::ImageView,
+        format: vk::Format,
+        initial: ImageState,
+        final_state: Option<ImageState>,
+    ) -> GraphImage {
+        self.images.push(GraphImageInfo {
+            source: ImageSource::Imported { raw, view, initial },
+            aspect: format_aspect(format),
+            final_state,
+        });
+        GraphImage(self.images.len() - 1)
+    }
+
+    /// Requests image from the render target pool for the duration of the
+    /// graph. Usage flags are derived from the passes using it.
+    pub fn create_image(&mut self, desc: ImageDesc) -> GraphImage {
+        self.images.push(GraphImageInfo {
+            source: ImageSource::Transient(desc),
+            aspect: format_aspect(desc.format),
+            final_state: None,
+        });
+        GraphImage(self.images.len() - 1)
+    }
+
+    pub fn import_buffer(&mut self, raw: vk::Buffer, initial: BufferState) -> GraphBuffer {
+        self.buffers.push(GraphBufferInfo { raw, initial });
+        GraphBuffer(self.buffers.len() - 1)
+    }
+
+    pub fn add_pass<'g>(&'g mut self, name: &'static str) -> PassBuilder<'g, 'a> {
+        PassBuilder {
+            graph: self,
+            pass: Pass {
+                name,
+                images: Vec::new(),
+                buffers: Vec::new(),
+                execute: None,
+            },
+        }
+    }
+
+    /// Derives barriers between passes, which run in the order they were
+    /// added. Fails when a pass declares an image in two layouts.
+    pub fn compile(&self) -> Result<CompiledGraph, BackendError> {
+        for pass in &self.passes {
+            for (index, (image, ..)) in pass.images.iter().enumerate() {
+                if pass.images[..index]
+                    .iter()
+                    .any(|(other, ..)| other == image)
+                {
+                    return Err(BackendError::InvalidArgument(format!(
+                        "Pass {} uses image {} in more than one layout",
+                        pass.name, image.0
+                    )));
+                }
+            }
+        }
+        let mut images = self
+            .images
+            .iter()
+            .map(|image| match image.source {
+                ImageSource::Imported { initial, .. } => {
+                    Tracked::new(initial.layout, initial.stage, initial.access)
+                }
+                ImageSource::Transient(_) => Tracked::new(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::PipelineStageFlags2::NONE,
+                    vk::AccessFlags2::NONE,
+                ),
+            })
+            .collect::<Vec<_>>();
+        let mut buffers = self
+            .buffers
+            .iter()
+            .map(|buffer| {
+                Tracked::new(
+                    vk::ImageLayout::UNDEFINED,
+                    buffer.initial.stage,
+                    buffer.initial.access,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut compiled = CompiledGraph::default();
+        for (index, pass) in self.passes.iter().enumerate() {
+            let mut compiled_pass = CompiledPass {
+                pass: index,
+                ..Default::default()
+            };
+            for &(image, state, write) in &pass.images {
+                if let Some((layout, stage, access)) =
+                    images[image.0].transition(state.layout, state.stage, state.access, write)
+                {
+                    compiled_pass.images.push(GraphImageTransition {
+                        image,
+                        from: ImageState::new(layout, stage, access),
+                        to: state,
+                    });
+                }
+            }
+            for &(buffer, state, write) in &pass.buffers {
+                if let Some((_, stage, access)) = buffers[buffer.0].transition(
+                    vk::ImageLayout::UNDEFINED,
+                    state.stage,
+                    state.access,
+                    write,
+                ) {
+                    compiled_pass.buffers.push(GraphBufferTransition {
+                        buffer,
+                        from: BufferState::new(stage, access),
+                        to: state,
+                    });
+                }
+            }
+            compiled.passes.push(compiled_pass);
+        }
+        for (index, image) in self.images.iter().enumerate() {
+            if let Some(state) = image.final_state
+                && let Some((layout, stage, access)) =
+                    images[index].transition(state.layout, state.stage, state.access, true)
+            {
+                compiled.finish.push(GraphImageTransition {
+                    image: GraphImage(index),
+                    from: ImageState::new(layout, stage, access),
+                    to: state,
+                });
+            }
+        }
+        Ok(compiled)
+    }
+
+    fn transient_usage(&self, image: usize) -> vk::ImageUsageFlags {
+        self.passes
+            .iter()
+            .flat_map(|pass| &pass.images)
+            .filter(|(id, ..)| id.0 == image)
+            .fold(vk::ImageUsageFlags::empty(), |usage, (_, state, _)| {
+                usage
+                    | match state.layout {
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => {
+                            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        }
+                        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+                        | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+                        | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL => {
+                            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                        }
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => vk::ImageUsageFlags::SAMPLED,
+                        vk::ImageLayout::GENERAL => vk::ImageUsageFlags::STORAGE,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => vk::ImageUsageFlags::TRANSFER_SRC,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL => vk::ImageUsageFlags::TRANSFER_DST,
+                        _ => vk::ImageUsageFlags::empty(),
+                    }
+            })
+    }
+
+    /// Records all passes into the main command buffer of the frame.
+    pub fn execute(mut self, frame: &Frame, pool: &RenderTargetPool) -> Result<(), BackendError> {
+        let compiled = self.compile()?;
+        let device = frame.device;
+        let mut transients = Vec::<Image>::new();
+        let mut resolved = Vec::with_capacity(self.images.len());
+        let result = (|| {
+            for index in 0..self.images.len() {
+                match self.images[index].source {
+                    ImageSource::Imported { raw, view, .. } => resolved.push((raw, view)),
+                    ImageSource::Transient(desc) => {
+                        let usage = self.transient_usage(index);
+                        let image = pool.acquire(desc.usage(usage))?;
+                        let view = image.view(image.view_desc())?;
+                        resolved.push((image.raw, view));
+                        transients.push(image);
+                    }
+                }
+            }
+            Ok::<_, BackendError>(())
+        })();
+        if result.is_ok() {
+            let buffers = self
+                .buffers
+                .iter()
+                .map(|buffer| buffer.raw)
+                .collect::<Vec<_>>();
+            let cb = frame.main_cb().raw();
+            for pass in &compiled.passes {
+                let image_barriers = pass
+                    .images
+                    .iter()
+                    .map(|transition| {
+                        image_barrier(
+                            resolved[transition.image.0].0,
+                            full_subresource_range(self.images[transition.image.0].aspect),
+                            transition.from,
+                            transition.to,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let buffer_barriers = pass
+                    .buffers
+                    .iter()
+                    .map(|transition| {
+                        buffer_barrier(buffers[transition.buffer.0], transition.from, transition.to)
+                    })
+                    .collect::<Vec<_>>();
+                if !image_barriers.is_empty() || !buffer_barriers.is_empty() {
+                    let info = vk::DependencyInfo::default()
+                        .image_memory_barriers(&image_barriers)
+                        .buffer_memory_barriers(&buffer_barriers);
+                    unsafe { device.raw.cmd_pipeline_barrier2(cb, &info) };
+                }
+                let execute = self.passes[pass.pass]
+                    .execute
+                    .take()
+                    .expect("Every pass has a callback");
+                execute(&PassContext {
+                    device,
+                    cb,
+                    images: &resolved,
+                    buffers: &buffers,
+                });
+            }
+            let image_barriers = compiled
+                .finish
+                .iter()
+                .map(|transition| {
+                    image_barrier(
+                        resolved[transition.image.0].0,
+                        full_subresource_range(self.images[transition.image.0].aspect),
+                        transition.from,
+                        transition.to,
+                    )
+                })
+                .collect::<Vec<_>>();
+            if !image_barriers.is_empty() {
+                let info = vk::DependencyInfo::default().image_memory_barriers(&image_barriers);
+                unsafe { device.raw.cmd_pipeline_barrier2(cb, &info) };
+            }
+        }
+        transients.into_iter().for_each(|image| pool.release(image));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent_desc(format: vk::Format) -> ImageDesc {
+        ImageDesc::texture_2d(format, 1920, 1080)
+    }
+
+    #[test]
+    fn five_pass_chain() {
+        let mut graph = RenderGraph::new();
+        let swapchain = graph.import_image(
+            vk::Image::null(),
+            vk::ImageView::null(),
+            vk::Format::B8G8R8A8_SRGB,
+            ImageState::UNDEFINED,
+            Some(ImageState::PRESENT),
+        );
+        let albedo = graph.create_image(extent_desc(vk::Format::R8G8B8A8_UNORM));
+        let depth = graph.create_image(extent_desc(vk::Format::D32_SFLOAT));
+        let ao = graph.create_image(extent_desc(vk::Format::R8_UNORM));
+        let hdr = graph.create_image(extent_desc(vk::Format::R16G16B16A16_SFLOAT));
+        graph
+            .add_pass("gbuffer")
+            .write_image(albedo, ImageState::COLOR_ATTACHMENT)
+            .write_image(depth, ImageState::DEPTH_ATTACHMENT)
+            .execute(|_| {});
+        graph
+            .add_pass("ssao")
+            .read_image(depth, ImageState::SHADER_READ)
+            .write_image(ao, ImageState::STORAGE)
+            .execute(|_| {});
+        graph
+            .add_pass("lighting")
+            .read_image(albedo, ImageState::SHADER_READ)
+            .read_image(depth, ImageState::SHADER_READ)
+            .read_image(ao, ImageState::SHADER_READ)
+            .write_image(hdr, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+        graph
+            .add_pass("post")
+            .read_image(hdr, ImageState::SHADER_READ)
+            .write_image(swapchain, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+        graph
+            .add_pass("ui")
+            .write_image(swapchain, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+
+        let compiled = graph.compile().unwrap();
+        let order = compiled
+            .passes
+            .iter()
+            .map(|pass| pass.pass)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [0, 1, 2, 3, 4]);
+
+        let gbuffer = &compiled.passes[0].images;
+        assert_eq!(gbuffer.len(), 2);
+        assert!(
+            gbuffer
+                .iter()
+                .all(|t| t.from.layout == vk::ImageLayout::UNDEFINED)
+        );
+
+        let ssao = &compiled.passes[1].images;
+        assert_eq!(
+            ssao[0],
+            GraphImageTransition {
+                image: depth,
+                from: ImageState::new(
+                    vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                    ImageState::DEPTH_ATTACHMENT.stage,
+                    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ),
+                to: ImageState::SHADER_READ,
+            }
+        );
+        assert_eq!(ssao[1].image, ao);
+        assert_eq!(ssao[1].to, ImageState::STORAGE);
+
+        // Depth is already readable by the same stages after ssao.
+        let lighting = &compiled.passes[2].images;
+        assert!(lighting.iter().all(|t| t.image != depth));
+        assert!(lighting.iter().any(|t| t.image == albedo
+            && t.from.access == vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+            && t.to == ImageState::SHADER_READ));
+        assert!(lighting.iter().any(|t| t.image == ao
+            && t.from.access == vk::AccessFlags2::SHADER_STORAGE_WRITE
+            && t.to == ImageState::SHADER_READ));
+
+        // Write after write on the same layout still needs a barrier.
+        let ui = &compiled.passes[4].images;
+        assert_eq!(ui.len(), 1);
+        assert_eq!(ui[0].from.layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(ui[0].from.access, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+
+        assert_eq!(compiled.finish.len(), 1);
+        assert_eq!(compiled.finish[0].image, swapchain);
+        assert_eq!(compiled.finish[0].to, ImageState::PRESENT);
+    }
+
+    #[test]
+    fn history_is_read_before_overwrite() {
+        let mut graph = RenderGraph::new();
+        let history = graph.import_image(
+            vk::Image::null(),
+            vk::ImageView::null(),
+            vk::Format::R16G16B16A16_SFLOAT,
+            ImageState::SHADER_READ,
+            None,
+        );
+        let color = graph.create_image(extent_desc(vk::Format::R16G16B16A16_SFLOAT));
+        graph
+            .add_pass("taa")
+            .read_image(history, ImageState::SHADER_READ)
+            .write_image(color, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+        graph
+            .add_pass("store history")
+            .read_image(color, ImageState::SHADER_READ)
+            .write_image(history, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+        let compiled = graph.compile().unwrap();
+        let order = compiled
+            .passes
+            .iter()
+            .map(|pass| pass.pass)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [0, 1]);
+        // The initial state is already readable, the overwrite waits for the read.
+        assert!(compiled.passes[0].images.iter().all(|t| t.image != history));
+        let store = &compiled.passes[1].images;
+        assert!(store.iter().any(|t| t.image == history
+            && t.from.layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            && t.to == ImageState::COLOR_ATTACHMENT));
+    }
+
+    #[test]
+    fn mutual_dependencies_keep_declaration_order() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create_image(extent_desc(vk::Format::R8G8B8A8_UNORM));
+        let b = graph.create_image(extent_desc(vk::Format::R8G8B8A8_UNORM));
+        graph
+            .add_pass("first")
+            .read_image(a, ImageState::SHADER_READ)
+            .write_image(b, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+        graph
+            .add_pass("second")
+            .read_image(b, ImageState::SHADER_READ)
+            .write_image(a, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.passes[0].pass, 0);
+        assert_eq!(compiled.passes[1].pass, 1);
+        // Second pass overwrites the image the first one read.
+        assert!(
+            compiled.passes[1]
+                .images
+                .iter()
+                .any(|t| t.image == a && t.from.layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        );
+    }
+
+    #[test]
+    fn repeated_declarations_are_merged() {
+        let mut graph = RenderGraph::new();
+        let image = graph.create_image(extent_desc(vk::Format::R8G8B8A8_UNORM));
+        graph
+            .add_pass("blur")
+            .read_image(image, ImageState::STORAGE)
+            .write_image(image, ImageState::STORAGE)
+            .execute(|_| {});
+        graph
+            .add_pass("sample")
+            .read_image(image, ImageState::SHADER_READ)
+            .execute(|_| {});
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.passes[0].images.len(), 1);
+        assert!(
+            compiled.passes[1].images[0]
+                .from
+                .access
+                .contains(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+        );
+    }
+
+    #[test]
+    fn image_in_two_layouts_is_rejected() {
+        let mut graph = RenderGraph::new();
+        let image = graph.create_image(extent_desc(vk::Format::R8G8B8A8_UNORM));
+        graph
+            .add_pass("feedback")
+            .read_image(image, ImageState::SHADER_READ)
+            .write_image(image, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| {});
+        assert!(matches!(
+            graph.compile(),
+            Err(BackendError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn buffer_read_from_new_stage_waits_for_write() {
+        let mut graph = RenderGraph::new();
+        let buffer = graph.import_buffer(vk::Buffer::null(), BufferState::NONE);
+        graph
+            .add_pass("simulate")
+            .write_buffer(buffer, BufferState::COMPUTE_WRITE)
+            .execute(|_| {});
+        graph
+            .add_pass("cull")
+            .read_buffer(buffer, BufferState::COMPUTE_READ)
+            .execute(|_| {});
+        graph
+            .add_pass("draw")
+            .read_buffer(buffer, BufferState::VERTEX)
+            .execute(|_| {});
+        let compiled = graph.compile().unwrap();
+        assert!(compiled.passes[0].buffers.is_empty());
+        assert_eq!(
+            compiled.passes[1].buffers[0].from,
+            BufferState::COMPUTE_WRITE
+        );
+        assert_eq!(
+            compiled.passes[2].buffers[0].from,
+            BufferState::COMPUTE_WRITE
+        );
+        assert_eq!(compiled.passes[2].buffers[0].to, BufferState::VERTEX);
+    }
+}
@@ -0,0 +1,485 @@
+use std::sync::atomic::Ordering;
+
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, Frame, Image},
+};
+
+/// Maps color, depth and stencil attachments of dynamic rendering to input
+/// attachment indices of fragment shaders, for reading attachments written
+/// earlier in the same rendering. Requires `VK_KHR_dynamic_rendering_local_read`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputAttachmentIndices {
+    /// Input index of every color attachment, `vk::ATTACHMENT_UNUSED` for
+    /// attachments that aren't read.
+    pub color: Vec<u32>,
+    pub depth: Option<u32>,
+    pub stencil: Option<u32>,
+}
+
+impl InputAttachmentIndices {
+    pub(crate) fn info(&self) -> vk::RenderingInputAttachmentIndexInfoKHR<'_> {
+        let mut info = vk::RenderingInputAttachmentIndexInfoKHR::default()
+            .color_attachment_input_indices(&self.color);
+        if let Some(depth) = &self.depth {
+            info = info.depth_input_attachment_index(depth);
+        }
+        if let Some(stencil) = &self.stencil {
+            info = info.stencil_input_attachment_index(stencil);
+        }
+        info
+    }
+}
+
+/// Attachment added to [`RenderPassBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AttachmentRef(u32);
+
+impl AttachmentRef {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+/// Subpass added to [`RenderPassBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubpassRef(u32);
+
+impl SubpassRef {
+    /// Commands before or after the render pass, for dependencies.
+    pub const EXTERNAL: Self = Self(vk::SUBPASS_EXTERNAL);
+
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SubpassAttachments {
+    color: Vec<vk::AttachmentReference2<'static>>,
+    depth: Option<vk::AttachmentReference2<'static>>,
+}
+
+/// Attachment of [`Device::create_framebuffer`].
+#[derive(Debug, Clone, Copy)]
+pub enum FramebufferAttachment<'a> {
+    View(vk::ImageView),
+    /// View of the first mip level is created with the attachment format and
+    /// cached by the image.
+    Image(&'a Image),
+}
+
+/// Builds a render pass for [`Device::create_render_pass2`] without
+/// indexing attachment arrays by hand. Single sampled attachments only.
+#[derive(Debug, Clone, Default)]
+pub struct RenderPassBuilder {
+    attachments: Vec<vk::AttachmentDescription2<'static>>,
+    subpasses: Vec<SubpassAttachments>,
+    dependencies: Vec<vk::SubpassDependency2<'static>>,
+}
+
+impl RenderPassBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_color_attachment(
+        &mut self,
+        format: vk::Format,
+        load_op: vk::AttachmentLoadOp,
+        store_op: vk::AttachmentStoreOp,
+        initial_layout: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    ) -> AttachmentRef {
+        self.add_attachment(
+            vk::AttachmentDescription2::default()
+                .format(format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(load_op)
+                .store_op(store_op)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(initial_layout)
+                .final_layout(final_layout),
+        )
+    }
+
+    /// Depth attachment stays in `DEPTH_STENCIL_ATTACHMENT_OPTIMAL`, its
+    /// contents are discarded on entry unless loaded. Stencil uses the same
+    /// operations.
+    pub fn add_depth_attachment(
+        &mut self,
+        format: vk::Format,
+        load_op: vk::AttachmentLoadOp,
+        store_op: vk::AttachmentStoreOp,
+    ) -> AttachmentRef {
+        let initial_layout = if load_op == vk::AttachmentLoadOp::LOAD {
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+        self.add_attachment(
+            vk::AttachmentDescription2::default()
+                .format(format)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .load_op(load_op)
+                .store_op(store_op)
+                .stencil_load_op(load_op)
+                .stencil_store_op(store_op)
+                .initial_layout(initial_layout)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+        )
+    }
+
+    fn add_attachment(&mut self, desc: vk::AttachmentDescription2<'static>) -> AttachmentRef {
+        self.attachments.push(desc);
+        AttachmentRef(self.attachments.len() as u32 - 1)
+    }
+
+    pub fn add_subpass(
+        &mut self,
+        color_refs: &[AttachmentRef],
+        depth_ref: Option<AttachmentRef>,
+    ) -> SubpassRef {
+        let reference = |attachment: AttachmentRef, layout, aspect| {
+            vk::AttachmentReference2::default()
+                .attachment(attachment.0)
+                .layout(layout)
+                .aspect_mask(aspect)
+        };
+        self.subpasses.push(SubpassAttachments {
+            color: color_refs
+                .iter()
+                .map(|attachment| {
+                    reference(
+                        *attachment,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        vk::ImageAspectFlags::COLOR,
+                    )
+                })
+                .collect(),
+            depth: depth_ref.map(|attachment| {
+                reference(
+                    attachment,
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    vk::ImageAspectFlags::DEPTH,
+                )
+            }),
+        });
+        SubpassRef(self.subpasses.len() as u32 - 1)
+    }
+
+    /// Stages and accesses are `(src, dst)` pairs.
+    pub fn add_dependency(
+        &mut self,
+        src: SubpassRef,
+        dst: SubpassRef,
+        stages: (vk::PipelineStageFlags, vk::PipelineStageFlags),
+        accesses: (vk::AccessFlags, vk::AccessFlags),
+    ) -> &mut Self {
+        self.dependencies.push(
+            vk::SubpassDependency2::default()
+                .src_subpass(src.0)
+                .dst_subpass(dst.0)
+                .src_stage_mask(stages.0)
+                .dst_stage_mask(stages.1)
+                .src_access_mask(accesses.0)
+                .dst_access_mask(accesses.1),
+        );
+        self
+    }
+
+    /// Caller owns the render pass and should release it through the drop
+    /// list.
+    pub fn build(&self, device: &Device) -> Result<vk::RenderPass, BackendError> {
+        let subpasses = self
+            .subpasses
+            .iter()
+            .map(|subpass| {
+                let desc = vk::SubpassDescription2::default()
+                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                    .color_attachments(&subpass.color);
+                match &subpass.depth {
+                    Some(depth) => desc.depth_stencil_attachment(depth),
+                    None => desc,
+                }
+            })
+            .collect::<Vec<_>>();
+        device.create_render_pass2(&self.attachments, &subpasses, &self.dependencies)
+    }
+}
+
+impl Device {
+    pub(crate) fn local_read_loader(
+        &self,
+    ) -> Result<&ash::khr::dynamic_rendering_local_read::Device, BackendError> {
+        self.local_read
+            .as_ref()
+            .ok_or(BackendError::ExtensionNotEnabled(
+                ash::khr::dynamic_rendering_local_read::NAME,
+            ))
+    }
+
+    /// Creates render pass through the Vulkan 1.2 `vkCreateRenderPass2` entry
+    /// point. Caller owns the render pass and should release it through the
+    /// drop list.
+    pub fn create_render_pass2(
+        &self,
+        attachments: &[vk::AttachmentDescription2],
+        subpasses: &[vk::SubpassDescription2],
+        dependencies: &[vk::SubpassDependency2],
+    ) -> Result<vk::RenderPass, BackendError> {
+        let info = vk::RenderPassCreateInfo2::default()
+            .attachments(attachments)
+            .subpasses(subpasses)
+            .dependencies(dependencies);
+        Ok(unsafe { self.raw.create_render_pass2(&info, None) }?)
+    }
+
+    /// Creates render pass that renders every subpass into the views set in
+    /// `view_mask`, e.g. both eyes of a stereo image. `correlation_mask`
+    /// marks views that are spatially close, as a hint for the driver.
+    pub fn create_multiview_render_pass(
+        &self,
+        attachments: &[vk::AttachmentDescription2],
+        subpasses: &[vk::SubpassDescription2],
+        dependencies: &[vk::SubpassDependency2],
+        view_mask: u32,
+        correlation_mask: u32,
+    ) -> Result<vk::RenderPass, BackendError> {
+        if !self.is_extension_enabled(ash::khr::multiview::NAME) {
+            return Err(BackendError::ExtensionNotEnabled(ash::khr::multiview::NAME));
+        }
+        let max_views = self.max_multiview_view_count();
+        for mask in [view_mask, correlation_mask] {
+            if 32 - mask.leading_zeros() > max_views {
+                return Err(BackendError::InvalidViewMask { mask, max_views });
+            }
+        }
+        let subpasses = subpasses
+            .iter()
+            .map(|subpass| subpass.view_mask(view_mask))
+            .collect::<Vec<_>>();
+        let correlation_masks = [correlation_mask];
+        let info = vk::RenderPassCreateInfo2::default()
+            .attachments(attachments)
+            .subpasses(&subpasses)
+            .dependencies(dependencies)
+            .correlated_view_masks(&correlation_masks);
+        Ok(unsafe { self.raw.create_render_pass2(&info, None) }?)
+    }
+
+    pub fn max_multiview_view_count(&self) -> u32 {
+        let mut multiview = vk::PhysicalDeviceMultiviewProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::default().push_next(&mut multiview);
+        unsafe {
+            self.instance
+                .raw
+                .get_physical_device_properties2(self.pdevice.raw, &mut properties)
+        };
+        multiview.max_multiview_view_count
+    }
+
+    /// Creates framebuffer for a render pass from [`Device::create_render_pass2`].
+    /// Each attachment comes with the format of its render pass attachment.
+    /// Attachment views must outlive the framebuffer: release the framebuffer
+    /// through the drop list no later than the views, or the images views were
+    /// created on, it's destroyed first.
+    pub fn create_framebuffer(
+        &self,
+        render_pass: vk::RenderPass,
+        attachments: &[(FramebufferAttachment, vk::Format)],
+        extent: vk::Extent2D,
+        layers: u32,
+    ) -> Result<vk::Framebuffer, BackendError> {
+        self.pdevice
+            .validate_image_size(extent.width, extent.height, layers)?;
+        let ty = if layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+        let views = attachments
+            .iter()
+            .map(|(attachment, format)| match attachment {
+                FramebufferAttachment::View(view) => Ok(*view),
+                FramebufferAttachment::Image(image) => image.view(
+                    image
+                        .view_desc()
+                        .ty(ty)
+                        .format(*format)
+                        .mip_levels(0, 1)
+                        .array_layers(0, layers),
+                ),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let info = vk::FramebufferCreateInfo::default()
+            .render_pass(render_pass)
+            .attachments(&views)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(layers);
+        Ok(unsafe { self.raw.create_framebuffer(&info, None) }?)
+    }
+
+    /// Modes usable for resolving multisampled depth attachments.
+    pub fn supported_depth_resolve_modes(&self) -> vk::ResolveModeFlags {
+        self.depth_stencil_resolve_properties()
+            .supported_depth_resolve_modes
+    }
+
+    /// Modes usable for resolving multisampled stencil attachments.
+    pub fn supported_stencil_resolve_modes(&self) -> vk::ResolveModeFlags {
+        self.depth_stencil_resolve_properties()
+            .supported_stencil_resolve_modes
+    }
+
+    fn depth_stencil_resolve_properties(
+        &self,
+    ) -> vk::PhysicalDeviceDepthStencilResolveProperties<'static> {
+        let mut resolve = vk::PhysicalDeviceDepthStencilResolveProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::default().push_next(&mut resolve);
+        unsafe {
+            self.instance
+                .raw
+                .get_physical_device_properties2(self.pdevice.raw, &mut properties)
+        };
+        resolve
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Begins dynamic rendering in the main command buffer of the frame.
+    pub fn begin_rendering(&self, info: &vk::RenderingInfo) {
+        debug_assert!(
+            !self.rendering.load(Ordering::Relaxed),
+            "Rendering is already active"
+        );
+        unsafe {
+            self.device
+                .raw
+                .cmd_begin_rendering(self.main_cb().raw(), info)
+        };
+        self.rendering.store(true, Ordering::Relaxed);
+    }
+
+    pub fn end_rendering(&self) {
+        debug_assert!(
+            self.rendering.load(Ordering::Relaxed),
+            "Rendering isn't active"
+        );
+        unsafe { self.device.raw.cmd_end_rendering(self.main_cb().raw()) };
+        self.rendering.store(false, Ordering::Relaxed);
+    }
+
+    /// Begins render pass created with [`Device::create_render_pass2`], for
+    /// targets without dynamic rendering. `contents` tells whether the first
+    /// subpass is recorded inline or in secondary command buffers.
+    pub fn begin_render_pass(&self, info: &vk::RenderPassBeginInfo, contents: vk::SubpassContents) {
+        debug_assert!(
+            !self.rendering.load(Ordering::Relaxed),
+            "Rendering is already active"
+        );
+        let subpass = vk::SubpassBeginInfo::default().contents(contents);
+        unsafe {
+            self.device
+                .raw
+                .cmd_begin_render_pass2(self.main_cb().raw(), info, &subpass)
+        };
+        self.rendering.store(true, Ordering::Relaxed);
+    }
+
+    /// Advances to the next subpass of the current render pass.
+    pub fn next_subpass(&self, contents: vk::SubpassContents) {
+        debug_assert!(
+            self.rendering.load(Ordering::Relaxed),
+            "Render pass isn't active"
+        );
+        let begin = vk::SubpassBeginInfo::default().contents(contents);
+        let end = vk::SubpassEndInfo::default();
+        unsafe {
+            self.device
+                .raw
+                .cmd_next_subpass2(self.main_cb().raw(), &begin, &end)
+        };
+    }
+
+    pub fn end_render_pass(&self) {
+        debug_assert!(
+            self.rendering.load(Ordering::Relaxed),
+            "Render pass isn't active"
+        );
+        let end = vk::SubpassEndInfo::default();
+        unsafe {
+            self.device
+                .raw
+                .cmd_end_render_pass2(self.main_cb().raw(), &end)
+        };
+        self.rendering.store(false, Ordering::Relaxed);
+    }
+
+    /// Clears regions of the current attachments, unlike load op clears it
+    /// can be done in the middle of rendering. Must be called between
+    /// [`Frame::begin_rendering`] and [`Frame::end_rendering`].
+    pub fn clear_attachments(&self, clears: &[vk::ClearAttachment], rects: &[vk::ClearRect]) {
+        debug_assert!(
+            self.rendering.load(Ordering::Relaxed),
+            "Attachments are cleared outside of Frame::begin_rendering"
+        );
+        unsafe {
+            self.device
+                .raw
+                .cmd_clear_attachments(self.main_cb().raw(), clears, rects)
+        };
+    }
+
+    /// Remaps input attachment indices of the current rendering, must match
+    /// indices the bound pipeline was built with.
+    pub fn set_input_attachment_indices(
+        &self,
+        indices: &InputAttachmentIndices,
+    ) -> Result<(), BackendError> {
+        debug_assert!(
+            self.rendering.load(Ordering::Relaxed),
+            "Input attachment indices are set outside of Frame::begin_rendering"
+        );
+        let loader = self.device.local_read_loader()?;
+        unsafe {
+            loader.cmd_set_rendering_input_attachment_indices(self.main_cb().raw(), &indices.info())
+        };
+        Ok(())
+    }
+
+    /// Makes attachment writes of the current rendering visible to input
+    /// attachment reads of following draws in the same pixel. Attachments
+    /// must be in `RENDERING_LOCAL_READ_KHR` layout.
+    pub fn local_read_barrier(&self) -> Result<(), BackendError> {
+        debug_assert!(
+            self.rendering.load(Ordering::Relaxed),
+            "Local read barrier is issued outside of Frame::begin_rendering"
+        );
+        self.device.local_read_loader()?;
+        let barrier = [vk::MemoryBarrier2::default()
+            .src_stage_mask(
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            )
+            .src_access_mask(
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .dst_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags2::INPUT_ATTACHMENT_READ)];
+        let info = vk::DependencyInfo::default()
+            .dependency_flags(vk::DependencyFlags::BY_REGION)
+            .memory_barriers(&barrier);
+        unsafe {
+            self.device
+                .raw
+                .cmd_pipeline_barrier2(self.main_cb().raw(), &info)
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use std::{fmt::Debug, sync::Arc};
+
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, Image, ImageDesc},
+};
+
+/// Reuses transient images between frames. Released images become available
+/// again once the frames that could use them are finished.
+pub struct RenderTargetPool {
+    free: Mutex<Vec<(u64, Image)>>,
+    device: Arc<Device>,
+}
+
+impl Debug for RenderTargetPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderTargetPool")
+            .field("free", &self.free.lock().len())
+            .finish()
+    }
+}
+
+impl RenderTargetPool {
+    pub fn new(device: &Arc<Device>) -> Self {
+        Self {
+            free: Mutex::default(),
+            device: device.clone(),
+        }
+    }
+
+    pub fn acquire(&self, desc: ImageDesc) -> Result<Image, BackendError> {
+        let frame_index = self.device.frame_index();
+        let mut free = self.free.lock();
+        if let Some(index) = free
+            .iter()
+            .position(|(released, image)| image.desc == desc && released + 2 <= frame_index)
+        {
+            return Ok(free.swap_remove(index).1);
+        }
+        drop(free);
+        self.device.create_image(desc)
+    }
+
+    pub fn release(&self, image: Image) {
+        self.free.lock().push((self.device.frame_index(), image));
+    }
+
+    /// Destroys every image that isn't in use.
+    pub fn clear(&self) {
+        self.free.lock().clear();
+    }
+}
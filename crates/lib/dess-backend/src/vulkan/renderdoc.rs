@@ -0,0 +1,110 @@
+use std::{ffi::c_void, fmt::Debug};
+
+use ash::vk::{self, Handle};
+use log::info;
+
+use crate::vulkan::Device;
+
+/// `eRENDERDOC_API_Version_1_1_2`, the oldest version with everything used here.
+const API_VERSION: i32 = 10102;
+
+type GetApi = unsafe extern "C" fn(version: i32, api: *mut *mut c_void) -> i32;
+
+/// Prefix of `RENDERDOC_API_1_1_2`, only the entries up to `EndFrameCapture`.
+#[repr(C)]
+struct Api {
+    _unused: [*const c_void; 19],
+    start_frame_capture: unsafe extern "C" fn(device: *mut c_void, window: *mut c_void),
+    is_frame_capturing: unsafe extern "C" fn() -> u32,
+    end_frame_capture: unsafe extern "C" fn(device: *mut c_void, window: *mut c_void) -> u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use]
+pub enum CaptureResult {
+    Captured,
+    Failed,
+}
+
+/// RenderDoc in-application API, available when the process runs under
+/// RenderDoc. Captures cover everything submitted between start and end on
+/// the device's instance, regardless of the window.
+pub struct RenderDocCapture {
+    api: *const Api,
+    device: *mut c_void,
+    _library: libloading::Library,
+}
+
+// RenderDoc API is safe to call from any thread.
+unsafe impl Send for RenderDocCapture {}
+unsafe impl Sync for RenderDocCapture {}
+
+impl Debug for RenderDocCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RenderDocCapture")
+            .field("capturing", &self.is_capturing())
+            .finish()
+    }
+}
+
+impl RenderDocCapture {
+    /// Returns `None` unless RenderDoc is already injected, it's never loaded
+    /// by the application itself.
+    pub(crate) fn load(instance: vk::Instance) -> Option<Self> {
+        let library = Self::open_injected()?;
+        let api = unsafe {
+            let get_api = library.get::<GetApi>(b"RENDERDOC_GetAPI\0").ok()?;
+            let mut api = std::ptr::null_mut();
+            if get_api(API_VERSION, &mut api) != 1 || api.is_null() {
+                return None;
+            }
+            api as *const Api
+        };
+        info!("RenderDoc is attached, programmatic captures are available");
+        Some(Self {
+            api,
+            // RENDERDOC_DEVICEPOINTER_FROM_VKINSTANCE, the dispatch table
+            // pointer of the instance.
+            device: unsafe { *(instance.as_raw() as *const *mut c_void) },
+            _library: library,
+        })
+    }
+
+    #[cfg(windows)]
+    fn open_injected() -> Option<libloading::Library> {
+        libloading::os::windows::Library::open_already_loaded("renderdoc.dll")
+            .ok()
+            .map(Into::into)
+    }
+
+    #[cfg(unix)]
+    fn open_injected() -> Option<libloading::Library> {
+        use libloading::os::unix::{Library, RTLD_NOW};
+
+        unsafe { Library::open(Some("librenderdoc.so"), RTLD_NOW | libc::RTLD_NOLOAD) }
+            .ok()
+            .map(Into::into)
+    }
+
+    pub fn start_capture(&self) {
+        unsafe { ((*self.api).start_frame_capture)(self.device, std::ptr::null_mut()) }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        unsafe { ((*self.api).is_frame_capturing)() != 0 }
+    }
+
+    pub fn end_capture(&self) -> CaptureResult {
+        match unsafe { ((*self.api).end_frame_capture)(self.device, std::ptr::null_mut()) } {
+            1 => CaptureResult::Captured,
+            _ => CaptureResult::Failed,
+        }
+    }
+}
+
+impl Device {
+    /// Capture helper, `None` when the process isn't running under RenderDoc.
+    pub fn renderdoc(&self) -> Option<&RenderDocCapture> {
+        self.renderdoc.as_ref()
+    }
+}
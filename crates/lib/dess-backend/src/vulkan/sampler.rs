@@ -0,0 +1,169 @@
+use std::sync::atomic::Ordering;
+
+use ash::vk;
+
+use crate::{
+    BackendError,
+    vulkan::{Device, SamplerDesc},
+};
+
+/// Level of detail controls of a sampler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerLod {
+    /// Added to the computed level, negative values sharpen textures. Limited
+    /// by `maxSamplerLodBias`.
+    pub bias: f32,
+    /// Most detailed level that's sampled, e.g. the first streamed in mip.
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for SamplerLod {
+    fn default() -> Self {
+        Self {
+            bias: 0.0,
+            min: 0.0,
+            max: vk::LOD_CLAMP_NONE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderColor {
+    TransparentBlack,
+    OpaqueBlack,
+    OpaqueWhite,
+    /// Requires `VK_EXT_custom_border_color`, falls back to the closest of
+    /// standard colors when it isn't available. With the extension the
+    /// device must also support `customBorderColorWithoutFormat`.
+    Custom([f32; 4]),
+}
+
+impl BorderColor {
+    fn standard(self) -> vk::BorderColor {
+        match self {
+            BorderColor::TransparentBlack => vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            BorderColor::OpaqueBlack => vk::BorderColor::FLOAT_OPAQUE_BLACK,
+            BorderColor::OpaqueWhite => vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            BorderColor::Custom([r, g, b, a]) => {
+                if a < 0.5 {
+                    vk::BorderColor::FLOAT_TRANSPARENT_BLACK
+                } else if (r + g + b) / 3.0 < 0.5 {
+                    vk::BorderColor::FLOAT_OPAQUE_BLACK
+                } else {
+                    vk::BorderColor::FLOAT_OPAQUE_WHITE
+                }
+            }
+        }
+    }
+}
+
+impl Device {
+    /// Number of live samplers, including the predefined ones.
+    pub fn sampler_count(&self) -> u32 {
+        self.sampler_count.load(Ordering::Relaxed)
+    }
+
+    /// Creates sampler for `desc` that isn't shared through the cache. Users
+    /// go through [`Device::get_or_create_sampler`], or
+    /// [`Device::create_sampler_with_lod`] for a sampler of their own.
+    pub(crate) fn create_sampler(&self, desc: SamplerDesc) -> Result<vk::Sampler, BackendError> {
+        let max_bias = self.pdevice.properties.limits.max_sampler_lod_bias;
+        let bias = desc.lod_range().bias;
+        if bias.abs() > max_bias {
+            return Err(BackendError::InvalidSamplerLodBias {
+                bias,
+                max: max_bias,
+            });
+        }
+        self.create_raw_sampler(&desc.info(self.max_sampler_anisotropy()))
+    }
+
+    fn create_raw_sampler(
+        &self,
+        info: &vk::SamplerCreateInfo,
+    ) -> Result<vk::Sampler, BackendError> {
+        let sampler = unsafe { self.raw.create_sampler(info, None) }?;
+        let count = self.sampler_count.fetch_add(1, Ordering::Relaxed) + 1;
+        debug_assert!(
+            count <= self.pdevice.max_sampler_allocation_count(),
+            "{count} samplers exceed maxSamplerAllocationCount"
+        );
+        Ok(sampler)
+    }
+
+    /// Releases sampler created by the device once the current frame is
+    /// finished on the GPU.
+    pub fn destroy_sampler(&self, sampler: vk::Sampler) {
+        self.sampler_count.fetch_sub(1, Ordering::Relaxed);
+        self.with_drop_list(|drop_list| drop_list.drop_sampler(sampler));
+    }
+
+    /// Creates sampler like the preset one for `desc` with custom level of
+    /// detail. Caller owns the sampler and should release it with
+    /// [`Device::destroy_sampler`].
+    pub fn create_sampler_with_lod(
+        &self,
+        desc: SamplerDesc,
+        lod: SamplerLod,
+    ) -> Result<vk::Sampler, BackendError> {
+        self.create_sampler(desc.lod(lod))
+    }
+
+    /// Creates `CLAMP_TO_BORDER` sampler. Caller owns the sampler and should
+    /// release it with [`Device::destroy_sampler`].
+    pub fn create_border_sampler(
+        &self,
+        filter: vk::Filter,
+        mipmap_mode: vk::SamplerMipmapMode,
+        border: BorderColor,
+    ) -> Result<vk::Sampler, BackendError> {
+        let info = vk::SamplerCreateInfo::default()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(mipmap_mode)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let mut custom_color = vk::SamplerCustomBorderColorCreateInfoEXT::default();
+        let info = match border {
+            BorderColor::Custom(color)
+                if self.is_extension_enabled(ash::ext::custom_border_color::NAME) =>
+            {
+                if !self.custom_border_color_without_format {
+                    return Err(BackendError::FeatureNotEnabled(
+                        "customBorderColorWithoutFormat",
+                    ));
+                }
+                custom_color = custom_color
+                    .custom_border_color(vk::ClearColorValue { float32: color })
+                    .format(vk::Format::UNDEFINED);
+                info.border_color(vk::BorderColor::FLOAT_CUSTOM_EXT)
+                    .push_next(&mut custom_color)
+            }
+            border => info.border_color(border.standard()),
+        };
+        self.create_raw_sampler(&info)
+    }
+
+    /// Creates sampler addressed with texel coordinates instead of normalized
+    /// ones. Such samplers can only access the base mip level. Caller owns
+    /// the sampler and should release it with [`Device::destroy_sampler`].
+    pub fn create_unnormalized_sampler(
+        &self,
+        filter: vk::Filter,
+    ) -> Result<vk::Sampler, BackendError> {
+        let info = vk::SamplerCreateInfo::default()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .min_lod(0.0)
+            .max_lod(0.0)
+            .unnormalized_coordinates(true);
+        self.create_raw_sampler(&info)
+    }
+}
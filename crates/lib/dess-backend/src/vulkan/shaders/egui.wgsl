@@ -0,0 +1,61 @@
+struct Screen {
+    size: vec2<f32>,
+}
+
+var<immediate> screen: Screen;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(
+        2.0 * position.x / screen.size.x - 1.0,
+        1.0 - 2.0 * position.y / screen.size.y,
+        0.0,
+        1.0,
+    );
+    out.uv = uv;
+    out.color = color;
+    return out;
+}
+
+@group(0) @binding(0) var texture: texture_2d<f32>;
+@group(0) @binding(1) var texture_sampler: sampler;
+
+fn linear_from_gamma(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower = srgb / vec3<f32>(12.92);
+    let higher = pow((srgb + vec3<f32>(0.055)) / vec3<f32>(1.055), vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+fn gamma_from_linear(rgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = rgb < vec3<f32>(0.0031308);
+    let lower = rgb * vec3<f32>(12.92);
+    let higher = vec3<f32>(1.055) * pow(rgb, vec3<f32>(1.0 / 2.4)) - vec3<f32>(0.055);
+    return select(higher, lower, cutoff);
+}
+
+// Vertex colors are in gamma space, textures are sRGB images and are sampled
+// as linear.
+
+@fragment
+fn fs_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = vec4<f32>(linear_from_gamma(in.color.rgb), in.color.a);
+    return color * textureSample(texture, texture_sampler, in.uv);
+}
+
+@fragment
+fn fs_gamma(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(texture, texture_sampler, in.uv);
+    return in.color * vec4<f32>(gamma_from_linear(texel.rgb), texel.a);
+}
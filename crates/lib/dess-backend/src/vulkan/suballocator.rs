@@ -0,0 +1,140 @@
+use std::{fmt::Debug, marker::PhantomData, mem::size_of, slice, sync::Arc};
+
+use ash::vk;
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError,
+    vulkan::{Buffer, BufferDesc, Device, as_bytes},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubAllocation {
+    pub buffer: vk::Buffer,
+    pub offset: u64,
+    pub size: u32,
+}
+
+struct Page {
+    buffer: Buffer,
+    frame_index: u64,
+    offset: u64,
+}
+
+/// Bump allocator for small per-frame data, e.g. uniforms. Every frame in
+/// flight writes into its own host visible buffer, which is reset when the
+/// frame slot is reused.
+pub struct BufferSuballocator {
+    pages: [Mutex<Page>; 2],
+    alignment: u64,
+    device: Arc<Device>,
+}
+
+impl Debug for BufferSuballocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferSuballocator")
+            .field("alignment", &self.alignment)
+            .finish()
+    }
+}
+
+impl BufferSuballocator {
+    /// Creates a buffer of `size` bytes for each frame in flight.
+    pub fn new(
+        device: &Arc<Device>,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Self, BackendError> {
+        let desc = BufferDesc::new(size, usage)
+            .memory(gpu_alloc::UsageFlags::HOST_ACCESS | gpu_alloc::UsageFlags::UPLOAD);
+        let create_page = || {
+            let buffer = device.create_buffer(desc)?;
+            Ok::<_, BackendError>(Mutex::new(Page {
+                buffer,
+                frame_index: device.frame_index(),
+                offset: 0,
+            }))
+        };
+        let limits = &device.pdevice.properties.limits;
+        let mut alignment = limits.min_uniform_buffer_offset_alignment;
+        if usage.contains(vk::BufferUsageFlags::STORAGE_BUFFER) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment);
+        }
+        Ok(Self {
+            pages: [create_page()?, create_page()?],
+            alignment,
+            device: device.clone(),
+        })
+    }
+
+    pub fn alloc<T: Copy>(&self, data: &T) -> Result<SubAllocation, BackendError> {
+        self.alloc_bytes(as_bytes(slice::from_ref(data)))
+    }
+
+    /// Copies data into the buffer of the current frame. Must be called while
+    /// the frame is recorded, allocations are valid until it's finished on
+    /// the GPU.
+    pub fn alloc_bytes(&self, data: &[u8]) -> Result<SubAllocation, BackendError> {
+        let frame_index = self.device.frame_index();
+        let mut page = self.pages[(frame_index % 2) as usize].lock();
+        if page.frame_index != frame_index {
+            page.frame_index = frame_index;
+            page.offset = 0;
+        }
+        let offset = page.offset.next_multiple_of(self.alignment);
+        let size = data.len() as u64;
+        let capacity = page.buffer.desc.size;
+        if offset + size > capacity {
+            return Err(BackendError::SuballocationFailed { size, capacity });
+        }
+        page.buffer.write(offset, data)?;
+        page.offset = offset + size;
+        Ok(SubAllocation {
+            buffer: page.buffer.raw,
+            offset,
+            size: size as u32,
+        })
+    }
+}
+
+/// Uniform buffer with a copy for every frame in flight, e.g. camera
+/// matrices. Writing the copy of the current frame never touches data the
+/// GPU may still read for the previous one.
+pub struct FrameUniform<T: Copy> {
+    buffers: [Mutex<Buffer>; 2],
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> Debug for FrameUniform<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameUniform")
+            .field("size", &size_of::<T>())
+            .finish()
+    }
+}
+
+impl<T: Copy> FrameUniform<T> {
+    pub fn new(device: &Arc<Device>) -> Result<Self, BackendError> {
+        Ok(Self {
+            buffers: [
+                Mutex::new(device.create_uniform_buffer::<T>(None)?),
+                Mutex::new(device.create_uniform_buffer::<T>(None)?),
+            ],
+            _marker: PhantomData,
+        })
+    }
+
+    /// `frame_index` is [`Device::frame_index`] of the frame being recorded.
+    pub fn write(&self, frame_index: u64, value: &T) -> Result<(), BackendError> {
+        self.buffers[(frame_index % 2) as usize]
+            .lock()
+            .write_uniform(value)
+    }
+
+    pub fn descriptor_info(&self, frame_index: u64) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffers[(frame_index % 2) as usize].lock().raw)
+            .offset(0)
+            .range(size_of::<T>() as u64)
+    }
+}
@@ -3,11 +3,17 @@ use std::{fmt::Debug, sync::Arc};
 use ash::vk;
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
-use crate::{BackendError, vulkan::Instance};
+use crate::{
+    BackendError,
+    vulkan::{Instance, PhysicalDevice},
+};
 
 pub struct Surface {
     pub raw: vk::SurfaceKHR,
-    loader: ash::khr::surface::Instance,
+    pub(crate) loader: ash::khr::surface::Instance,
+    instance: Arc<Instance>,
+    /// Destroy the surface on drop.
+    owned: bool,
 }
 
 impl Debug for Surface {
@@ -18,7 +24,7 @@ impl Debug for Surface {
 
 impl Instance {
     pub fn create_surface(
-        &self,
+        self: &Arc<Self>,
         display: &impl HasDisplayHandle,
         window: &impl HasWindowHandle,
     ) -> Result<Arc<Surface>, BackendError> {
@@ -28,20 +34,90 @@ impl Instance {
                 &self.raw,
                 display.display_handle()?.as_raw(),
                 window.window_handle()?.as_raw(),
-                None,
+                self.allocation_callbacks(),
             )
         }?;
         let loader = ash::khr::surface::Instance::new(&self.entry, &self.raw);
         Ok(Surface {
             raw: surface,
             loader,
+            instance: self.clone(),
+            owned: true,
+        }
+        .into())
+    }
+
+    /// Creates surface that isn't shown anywhere, to run swapchain code
+    /// without a display. Its extent is undefined, so swapchains use the
+    /// requested one. Requires `InstanceBuilder::headless_surface`.
+    pub fn create_headless_surface(self: &Arc<Self>) -> Result<Arc<Surface>, BackendError> {
+        if !self.headless_surface {
+            return Err(BackendError::ExtensionNotEnabled(
+                ash::ext::headless_surface::NAME,
+            ));
+        }
+        let headless = ash::ext::headless_surface::Instance::new(&self.entry, &self.raw);
+        let info = vk::HeadlessSurfaceCreateInfoEXT::default();
+        let surface =
+            unsafe { headless.create_headless_surface(&info, self.allocation_callbacks()) }?;
+        let loader = ash::khr::surface::Instance::new(&self.entry, &self.raw);
+        Ok(Surface {
+            raw: surface,
+            loader,
+            instance: self.clone(),
+            owned: true,
         }
         .into())
     }
+
+    /// Adopts surface created outside of the crate, e.g. by SDL or GLFW. With
+    /// `owned` the surface is destroyed on drop, otherwise the caller keeps
+    /// destroying it.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid surface created from this instance and must
+    /// outlive the returned [`Surface`] when it isn't owned.
+    pub unsafe fn wrap_surface(self: &Arc<Self>, raw: vk::SurfaceKHR, owned: bool) -> Arc<Surface> {
+        let loader = ash::khr::surface::Instance::new(&self.entry, &self.raw);
+        Surface {
+            raw,
+            loader,
+            instance: self.clone(),
+            owned,
+        }
+        .into()
+    }
+}
+
+impl Surface {
+    pub fn present_modes(
+        &self,
+        pdevice: &PhysicalDevice,
+    ) -> Result<Vec<vk::PresentModeKHR>, BackendError> {
+        Ok(unsafe {
+            self.loader
+                .get_physical_device_surface_present_modes(pdevice.raw, self.raw)
+        }?)
+    }
+
+    pub fn supports_present_mode(
+        &self,
+        pdevice: &PhysicalDevice,
+        mode: vk::PresentModeKHR,
+    ) -> Result<bool, BackendError> {
+        Ok(self.present_modes(pdevice)?.contains(&mode))
+    }
 }
 
 impl Drop for Surface {
     fn drop(&mut self) {
-        unsafe { self.loader.destroy_surface(self.raw, None) };
+        if !self.owned {
+            return;
+        }
+        unsafe {
+            self.loader
+                .destroy_surface(self.raw, self.instance.allocation_callbacks())
+        };
     }
 }
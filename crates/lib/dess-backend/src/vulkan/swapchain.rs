@@ -0,0 +1,357 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use ash::{prelude::VkResult, vk};
+use log::{debug, info, warn};
+
+use crate::{
+    BackendError,
+    vulkan::{Device, ImageViewDesc, Surface},
+};
+
+/// What to do with the old swapchain when the surface changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SwapchainRecreation {
+    /// Create the new swapchain from the old one so presentation doesn't
+    /// stall. Images of both swapchains stay alive until the old one is
+    /// retired.
+    #[default]
+    Overlap,
+    /// Destroy the old swapchain and its images before creating the new one.
+    /// Keeps peak memory low at the cost of a longer hitch.
+    DestroyFirst,
+}
+
+pub struct Swapchain {
+    pub raw: vk::SwapchainKHR,
+    pub format: vk::SurfaceFormatKHR,
+    /// Extent the swapchain was created with, may differ from the requested
+    /// one, see [`Device::create_swapchain`].
+    pub extent: vk::Extent2D,
+    pub images: Vec<vk::Image>,
+    pub views: Vec<vk::ImageView>,
+    pub recreation: SwapchainRecreation,
+    /// Present modes in order of preference, the first one supported by the
+    /// surface is used. Falls back to `FIFO`, which is always supported.
+    /// Takes effect when the swapchain is recreated.
+    pub present_modes: Vec<vk::PresentModeKHR>,
+    /// Present mode the swapchain was created with.
+    pub present_mode: vk::PresentModeKHR,
+    last_present_id: AtomicU64,
+    loader: ash::khr::swapchain::Device,
+    surface: Arc<Surface>,
+    device: Arc<Device>,
+}
+
+impl Debug for Swapchain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Swapchain")
+            .field("raw", &self.raw)
+            .field("format", &self.format)
+            .field("extent", &self.extent)
+            .field("images", &self.images)
+            .field("recreation", &self.recreation)
+            .field("present_mode", &self.present_mode)
+            .finish()
+    }
+}
+
+impl Device {
+    /// Creates swapchain for the surface. `extent` is usually the window
+    /// framebuffer size. It's only used when the surface lets the swapchain
+    /// define its size, and then is clamped to the supported range. Otherwise
+    /// the current surface extent is used.
+    pub fn create_swapchain(
+        self: &Arc<Self>,
+        surface: &Arc<Surface>,
+        extent: vk::Extent2D,
+    ) -> Result<Swapchain, BackendError> {
+        let loader = ash::khr::swapchain::Device::new(&self.instance.raw, &self.raw);
+        let mut swapchain = Swapchain {
+            raw: vk::SwapchainKHR::null(),
+            format: vk::SurfaceFormatKHR::default(),
+            extent,
+            images: Vec::new(),
+            views: Vec::new(),
+            recreation: SwapchainRecreation::default(),
+            present_modes: vec![vk::PresentModeKHR::FIFO],
+            present_mode: vk::PresentModeKHR::FIFO,
+            last_present_id: AtomicU64::new(0),
+            loader,
+            surface: surface.clone(),
+            device: self.clone(),
+        };
+        swapchain.create(extent)?;
+        Ok(swapchain)
+    }
+}
+
+impl Swapchain {
+    /// Whether there is anything to render into. Minimized windows report
+    /// zero extent, skip rendering and presenting until it's recreated with
+    /// non-zero size.
+    pub fn is_renderable(&self) -> bool {
+        self.raw != vk::SwapchainKHR::null() && self.extent.width > 0 && self.extent.height > 0
+    }
+
+    /// Recreates swapchain after resize or when it went out of date. Zero
+    /// extent keeps the old swapchain around, but makes it non-renderable.
+    pub fn recreate(&mut self, extent: vk::Extent2D) -> Result<(), BackendError> {
+        if extent.width == 0 || extent.height == 0 {
+            self.extent = extent;
+            return Ok(());
+        }
+        unsafe { self.device.raw.device_wait_idle() }?;
+        match self.recreation {
+            SwapchainRecreation::Overlap => {
+                let old_views = std::mem::take(&mut self.views);
+                let old = self.raw;
+                match self.create(extent) {
+                    Ok(true) => {
+                        self.destroy(old, &old_views);
+                        Ok(())
+                    }
+                    Ok(false) => {
+                        self.views = old_views;
+                        Ok(())
+                    }
+                    Err(err) => {
+                        self.destroy(old, &old_views);
+                        self.raw = vk::SwapchainKHR::null();
+                        self.images.clear();
+                        Err(err)
+                    }
+                }
+            }
+            SwapchainRecreation::DestroyFirst => {
+                let views = std::mem::take(&mut self.views);
+                self.destroy(self.raw, &views);
+                self.raw = vk::SwapchainKHR::null();
+                self.images.clear();
+                self.create(extent).map(|_| ())
+            }
+        }
+    }
+
+    /// Returns image index and whether the swapchain is suboptimal, or `None`
+    /// when the swapchain [isn't renderable](Self::is_renderable).
+    pub fn acquire_next_image(
+        &self,
+        signal: vk::Semaphore,
+    ) -> Result<Option<(u32, bool)>, BackendError> {
+        if !self.is_renderable() {
+            return Ok(None);
+        }
+        let result = unsafe {
+            self.loader
+                .acquire_next_image(self.raw, u64::MAX, signal, vk::Fence::null())
+        };
+        Ok(Some(suboptimal_status(result)?))
+    }
+
+    /// Presents image on the main queue. Returns whether the swapchain is
+    /// suboptimal.
+    ///
+    /// Every present gets the next present id, it is passed to the driver
+    /// when `VK_KHR_present_id` is enabled.
+    pub fn present(&self, index: u32, wait: vk::Semaphore) -> Result<bool, BackendError> {
+        let swapchains = [self.raw];
+        let indices = [index];
+        let wait = [wait];
+        let mut info = vk::PresentInfoKHR::default()
+            .swapchains(&swapchains)
+            .image_indices(&indices)
+            .wait_semaphores(&wait);
+        let _lock = self.device.submit_lock.lock();
+        let present_ids = [self.last_present_id.fetch_add(1, Ordering::Relaxed) + 1];
+        let mut present_id = vk::PresentIdKHR::default().present_ids(&present_ids);
+        if self.device.is_extension_enabled(ash::khr::present_id::NAME) {
+            info = info.push_next(&mut present_id);
+        }
+        let result = unsafe { self.loader.queue_present(self.device.main_queue.raw, &info) };
+        suboptimal_status(result.map(|suboptimal| ((), suboptimal)))
+            .map(|(_, suboptimal)| suboptimal)
+    }
+
+    /// Id of the last presented image, zero if nothing was presented yet.
+    pub fn last_presented_id(&self) -> u64 {
+        self.last_present_id.load(Ordering::Relaxed)
+    }
+
+    /// Returns false when the surface has zero size and nothing was created.
+    fn create(&mut self, extent: vk::Extent2D) -> Result<bool, BackendError> {
+        let pdevice = self.device.pdevice.raw;
+        let surface = &self.surface;
+        let capabilities = unsafe {
+            surface
+                .loader
+                .get_physical_device_surface_capabilities(pdevice, surface.raw)
+        }?;
+        let formats = unsafe {
+            surface
+                .loader
+                .get_physical_device_surface_formats(pdevice, surface.raw)
+        }?;
+        let format = formats
+            .iter()
+            .copied()
+            .find(|format| {
+                format.format == vk::Format::B8G8R8A8_SRGB
+                    && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            })
+            .or_else(|| formats.first().copied())
+            .ok_or(BackendError::NoSurfaceFormats)?;
+        let present_modes = surface.present_modes(&self.device.pdevice)?;
+        let present_mode = self
+            .present_modes
+            .iter()
+            .copied()
+            .find(|mode| present_modes.contains(mode))
+            .unwrap_or(vk::PresentModeKHR::FIFO);
+        // 0xFFFFFFFF means that surface size is determined by the swapchain.
+        let extent = if capabilities.current_extent.width != u32::MAX {
+            capabilities.current_extent
+        } else {
+            let clamped = vk::Extent2D {
+                width: extent.width.clamp(
+                    capabilities.min_image_extent.width,
+                    capabilities.max_image_extent.width,
+                ),
+                height: extent.height.clamp(
+                    capabilities.min_image_extent.height,
+                    capabilities.max_image_extent.height,
+                ),
+            };
+            if clamped != extent {
+                warn!(
+                    "Swapchain extent {}x{} is out of supported range {}x{}..={}x{}, clamped to {}x{}",
+                    extent.width,
+                    extent.height,
+                    capabilities.min_image_extent.width,
+                    capabilities.min_image_extent.height,
+                    capabilities.max_image_extent.width,
+                    capabilities.max_image_extent.height,
+                    clamped.width,
+                    clamped.height
+                );
+            }
+            clamped
+        };
+        if extent.width == 0 || extent.height == 0 {
+            self.extent = extent;
+            return Ok(false);
+        }
+        let mut image_count = capabilities.min_image_count + 1;
+        if capabilities.max_image_count > 0 {
+            image_count = image_count.min(capabilities.max_image_count);
+        }
+        let info = vk::SwapchainCreateInfoKHR::default()
+            .surface(surface.raw)
+            .min_image_count(image_count)
+            .image_format(format.format)
+            .image_color_space(format.color_space)
+            .image_extent(extent)
+            .image_array_layers(1)
+            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST)
+            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .pre_transform(capabilities.current_transform)
+            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .present_mode(present_mode)
+            .clipped(true)
+            .old_swapchain(self.raw);
+        let raw = unsafe {
+            self.loader
+                .create_swapchain(&info, self.device.instance.allocation_callbacks())
+        }?;
+        let mut views = Vec::new();
+        let images = (|| {
+            let images = unsafe { self.loader.get_swapchain_images(raw) }?;
+            let desc = ImageViewDesc {
+                ty: vk::ImageViewType::TYPE_2D,
+                format: format.format,
+                aspect: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                mip_levels: 1,
+                base_array_layer: 0,
+                array_layers: 1,
+            };
+            for image in &images {
+                views.push(self.device.create_image_view_with_swizzle(
+                    *image,
+                    &desc,
+                    vk::ComponentMapping::default(),
+                )?);
+            }
+            Ok::<_, BackendError>(images)
+        })();
+        let images = match images {
+            Ok(images) => images,
+            Err(err) => {
+                self.destroy(raw, &views);
+                return Err(err);
+            }
+        };
+        self.raw = raw;
+        self.format = format;
+        self.present_mode = present_mode;
+        self.extent = extent;
+        self.images = images;
+        self.views = views;
+        info!(
+            "Created swapchain {}x{} with {} images, {:?}",
+            extent.width,
+            extent.height,
+            self.images.len(),
+            present_mode
+        );
+        Ok(true)
+    }
+
+    fn destroy(&self, raw: vk::SwapchainKHR, views: &[vk::ImageView]) {
+        for view in views {
+            unsafe { self.device.raw.destroy_image_view(*view, None) };
+        }
+        unsafe {
+            self.loader
+                .destroy_swapchain(raw, self.device.instance.allocation_callbacks())
+        };
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        unsafe { self.device.raw.device_wait_idle() }.ok();
+        self.destroy(self.raw, &self.views);
+    }
+}
+
+/// `SUBOPTIMAL_KHR` is a success code, the swapchain still works but should be
+/// recreated, so it's returned along with the value. `ERROR_OUT_OF_DATE_KHR`
+/// stays an error.
+fn suboptimal_status<T>(result: VkResult<(T, bool)>) -> Result<(T, bool), BackendError> {
+    let (value, suboptimal) = result?;
+    if suboptimal {
+        debug!("Swapchain is suboptimal");
+    }
+    Ok((value, suboptimal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suboptimal_is_surfaced() {
+        assert!(matches!(suboptimal_status(Ok((2, true))), Ok((2, true))));
+        assert!(matches!(suboptimal_status(Ok((1, false))), Ok((1, false))));
+        assert!(matches!(
+            suboptimal_status::<u32>(Err(vk::Result::ERROR_OUT_OF_DATE_KHR)),
+            Err(BackendError::VulkanError(vk::Result::ERROR_OUT_OF_DATE_KHR))
+        ));
+    }
+}
@@ -0,0 +1,361 @@
+#[cfg(target_os = "linux")]
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+#[cfg(target_os = "windows")]
+use std::os::windows::io::{AsRawHandle, BorrowedHandle, FromRawHandle, OwnedHandle};
+use std::{fmt::Debug, sync::Arc};
+
+use ash::vk;
+
+use crate::{BackendError, vulkan::Device};
+
+/// Device sync objects are released with. Objects of the device itself,
+/// e.g. frame fences, can't keep it alive, so they hold its loader and are
+/// destroyed explicitly together with the device.
+enum Owner {
+    Shared(Arc<Device>),
+    Device(Box<ash::Device>),
+}
+
+impl Owner {
+    fn raw(&self) -> &ash::Device {
+        match self {
+            Self::Shared(device) => &device.raw,
+            Self::Device(device) => device,
+        }
+    }
+}
+
+pub struct Fence {
+    pub raw: vk::Fence,
+    owner: Owner,
+}
+
+impl Debug for Fence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Fence").field("raw", &self.raw).finish()
+    }
+}
+
+impl Fence {
+    pub(crate) fn create_raw(
+        device: &ash::Device,
+        signaled: bool,
+    ) -> Result<vk::Fence, BackendError> {
+        let flags = if signaled {
+            vk::FenceCreateFlags::SIGNALED
+        } else {
+            vk::FenceCreateFlags::empty()
+        };
+        let info = vk::FenceCreateInfo::default().flags(flags);
+        Ok(unsafe { device.create_fence(&info, None) }?)
+    }
+
+    /// Fence of the device itself, not released on drop but with
+    /// [`Fence::destroy`].
+    pub(crate) fn device_owned(device: &ash::Device, signaled: bool) -> Result<Self, BackendError> {
+        Ok(Self {
+            raw: Self::create_raw(device, signaled)?,
+            owner: Owner::Device(Box::new(device.clone())),
+        })
+    }
+
+    /// Destroys device owned fence right away, GPU must be done with it.
+    pub(crate) fn destroy(&self) {
+        if let Owner::Device(device) = &self.owner {
+            unsafe { device.destroy_fence(self.raw, None) };
+        }
+    }
+
+    pub(crate) fn wait_raw(
+        device: &ash::Device,
+        fence: vk::Fence,
+        timeout: u64,
+    ) -> Result<(), BackendError> {
+        unsafe { device.wait_for_fences(&[fence], true, timeout) }?;
+        Ok(())
+    }
+
+    /// Returns [`BackendError::Timeout`] if the fence isn't signaled in `timeout` nanoseconds.
+    pub fn wait(&self, timeout: u64) -> Result<(), BackendError> {
+        Self::wait_raw(self.owner.raw(), self.raw, timeout)
+    }
+
+    pub fn is_signaled(&self) -> Result<bool, BackendError> {
+        Ok(unsafe { self.owner.raw().get_fence_status(self.raw) }?)
+    }
+
+    pub fn reset(&self) -> Result<(), BackendError> {
+        unsafe { self.owner.raw().reset_fences(&[self.raw]) }?;
+        Ok(())
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        if let Owner::Shared(device) = &self.owner {
+            device.with_drop_list(|drop_list| drop_list.drop_fence(self.raw));
+        }
+    }
+}
+
+pub struct Semaphore {
+    pub raw: vk::Semaphore,
+    owner: Owner,
+}
+
+impl Debug for Semaphore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Semaphore").field("raw", &self.raw).finish()
+    }
+}
+
+impl Semaphore {
+    pub(crate) fn create_raw(device: &ash::Device) -> Result<vk::Semaphore, BackendError> {
+        Ok(unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }?)
+    }
+
+    /// Binary semaphore of the device itself, not released on drop but with
+    /// [`Semaphore::destroy`].
+    pub(crate) fn device_owned(device: &ash::Device) -> Result<Self, BackendError> {
+        Ok(Self {
+            raw: Self::create_raw(device)?,
+            owner: Owner::Device(Box::new(device.clone())),
+        })
+    }
+
+    /// Destroys device owned semaphore right away, GPU must be done with it.
+    pub(crate) fn destroy(&self) {
+        if let Owner::Device(device) = &self.owner {
+            unsafe { device.destroy_semaphore(self.raw, None) };
+        }
+    }
+}
+
+impl Semaphore {
+    /// Current counter value of a timeline semaphore.
+    pub fn value(&self) -> Result<u64, BackendError> {
+        Ok(unsafe { self.owner.raw().get_semaphore_counter_value(self.raw) }?)
+    }
+
+    /// Waits until timeline semaphore reaches `value`. Returns
+    /// [`BackendError::Timeout`] if it doesn't happen in `timeout` nanoseconds.
+    pub fn wait_value(&self, value: u64, timeout: u64) -> Result<(), BackendError> {
+        let semaphores = [self.raw];
+        let values = [value];
+        let info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        unsafe { self.owner.raw().wait_semaphores(&info, timeout) }?;
+        Ok(())
+    }
+}
+
+impl From<&Semaphore> for vk::Semaphore {
+    fn from(value: &Semaphore) -> Self {
+        value.raw
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        if let Owner::Shared(device) = &self.owner {
+            device.with_drop_list(|drop_list| drop_list.drop_semaphore(self.raw));
+        }
+    }
+}
+
+impl Device {
+    /// Creates unnamed binary semaphore owned by the caller, same as frame
+    /// semaphores. Release it with [`DropList::drop_semaphore`] through
+    /// [`Device::with_drop_list`], or use [`Device::create_semaphore`] to
+    /// have it released on drop.
+    ///
+    /// [`DropList::drop_semaphore`]: crate::DropList::drop_semaphore
+    pub fn create_binary_semaphore(&self) -> Result<vk::Semaphore, BackendError> {
+        Semaphore::create_raw(&self.raw)
+    }
+
+    /// Timeline counterpart of [`Device::create_binary_semaphore`], the
+    /// caller releases it through the drop list as well.
+    pub fn create_raw_timeline_semaphore(
+        &self,
+        initial_value: u64,
+    ) -> Result<vk::Semaphore, BackendError> {
+        let mut timeline = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+        let info = vk::SemaphoreCreateInfo::default().push_next(&mut timeline);
+        Ok(unsafe { self.raw.create_semaphore(&info, None) }?)
+    }
+
+    pub fn create_fence(
+        self: &Arc<Self>,
+        signaled: bool,
+        name: Option<&str>,
+    ) -> Result<Fence, BackendError> {
+        let raw = Fence::create_raw(&self.raw, signaled)?;
+        if let Some(name) = name {
+            self.set_object_name(raw, name);
+        }
+        Ok(Fence {
+            raw,
+            owner: Owner::Shared(self.clone()),
+        })
+    }
+
+    /// Creates binary semaphore, the kind swapchain acquire and present
+    /// require. Every signal must be waited on exactly once before it can be
+    /// signaled again.
+    pub fn create_semaphore(
+        self: &Arc<Self>,
+        name: Option<&str>,
+    ) -> Result<Semaphore, BackendError> {
+        let raw = self.create_binary_semaphore()?;
+        if let Some(name) = name {
+            self.set_object_name(raw, name);
+        }
+        Ok(Semaphore {
+            raw,
+            owner: Owner::Shared(self.clone()),
+        })
+    }
+
+    /// Creates timeline semaphore with a counter starting at `initial_value`.
+    /// Prefer it for GPU to GPU and GPU to host sync between queues: values
+    /// can be waited on any number of times and from the host. Submits may
+    /// wait on binary and timeline semaphores at once, presentation can't
+    /// use timeline ones.
+    pub fn create_timeline_semaphore(
+        self: &Arc<Self>,
+        initial_value: u64,
+        name: Option<&str>,
+    ) -> Result<Semaphore, BackendError> {
+        let raw = self.create_raw_timeline_semaphore(initial_value)?;
+        if let Some(name) = name {
+            self.set_object_name(raw, name);
+        }
+        Ok(Semaphore {
+            raw,
+            owner: Owner::Shared(self.clone()),
+        })
+    }
+
+    /// Creates binary semaphore whose payload can be exported with
+    /// `handle_types`.
+    pub fn create_exportable_semaphore(
+        self: &Arc<Self>,
+        handle_types: vk::ExternalSemaphoreHandleTypeFlags,
+        name: Option<&str>,
+    ) -> Result<Semaphore, BackendError> {
+        let mut export = vk::ExportSemaphoreCreateInfo::default().handle_types(handle_types);
+        let info = vk::SemaphoreCreateInfo::default().push_next(&mut export);
+        let raw = unsafe { self.raw.create_semaphore(&info, None) }?;
+        if let Some(name) = name {
+            self.set_object_name(raw, name);
+        }
+        Ok(Semaphore {
+            raw,
+            owner: Owner::Shared(self.clone()),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Device {
+    fn external_semaphore_loader(
+        &self,
+    ) -> Result<&ash::khr::external_semaphore_fd::Device, BackendError> {
+        self.external_semaphore
+            .as_ref()
+            .ok_or(BackendError::ExtensionNotEnabled(
+                ash::khr::external_semaphore_fd::NAME,
+            ))
+    }
+
+    /// Exports semaphore payload as a file descriptor. The semaphore must be
+    /// created with `handle_type` in its export handle types. `SYNC_FD`
+    /// exports of an already signaled semaphore have no descriptor and
+    /// return `None`.
+    pub fn export_semaphore_fd(
+        &self,
+        semaphore: vk::Semaphore,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+    ) -> Result<Option<OwnedFd>, BackendError> {
+        let info = vk::SemaphoreGetFdInfoKHR::default()
+            .semaphore(semaphore)
+            .handle_type(handle_type);
+        let fd = unsafe { self.external_semaphore_loader()?.get_semaphore_fd(&info) }?;
+        // -1 is a valid SYNC_FD payload meaning "already signaled".
+        Ok((fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+
+    /// Imports payload from `fd` into `semaphore`. Vulkan takes ownership of
+    /// the descriptor on success, on failure it is closed.
+    pub fn import_semaphore_fd(
+        &self,
+        semaphore: vk::Semaphore,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+        fd: OwnedFd,
+        flags: vk::SemaphoreImportFlags,
+    ) -> Result<(), BackendError> {
+        let info = vk::ImportSemaphoreFdInfoKHR::default()
+            .semaphore(semaphore)
+            .handle_type(handle_type)
+            .fd(fd.as_raw_fd())
+            .flags(flags);
+        unsafe { self.external_semaphore_loader()?.import_semaphore_fd(&info) }?;
+        let _ = fd.into_raw_fd();
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Device {
+    fn external_semaphore_loader(
+        &self,
+    ) -> Result<&ash::khr::external_semaphore_win32::Device, BackendError> {
+        self.external_semaphore
+            .as_ref()
+            .ok_or(BackendError::ExtensionNotEnabled(
+                ash::khr::external_semaphore_win32::NAME,
+            ))
+    }
+
+    /// Exports semaphore payload as a Win32 handle. The semaphore must be
+    /// created with `handle_type` in its export handle types.
+    pub fn export_semaphore_win32_handle(
+        &self,
+        semaphore: vk::Semaphore,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+    ) -> Result<OwnedHandle, BackendError> {
+        let info = vk::SemaphoreGetWin32HandleInfoKHR::default()
+            .semaphore(semaphore)
+            .handle_type(handle_type);
+        let handle = unsafe {
+            self.external_semaphore_loader()?
+                .get_semaphore_win32_handle(&info)
+        }?;
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle as _) })
+    }
+
+    /// Imports payload from `handle` into `semaphore`. Unlike file
+    /// descriptors, Vulkan doesn't take ownership of Win32 handles.
+    pub fn import_semaphore_win32_handle(
+        &self,
+        semaphore: vk::Semaphore,
+        handle_type: vk::ExternalSemaphoreHandleTypeFlags,
+        handle: BorrowedHandle,
+        flags: vk::SemaphoreImportFlags,
+    ) -> Result<(), BackendError> {
+        let info = vk::ImportSemaphoreWin32HandleInfoKHR::default()
+            .semaphore(semaphore)
+            .handle_type(handle_type)
+            .handle(handle.as_raw_handle() as _)
+            .flags(flags);
+        unsafe {
+            self.external_semaphore_loader()?
+                .import_semaphore_win32_handle(&info)
+        }?;
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use ash::vk;
+
+use crate::{BackendError, vulkan::Device};
+
+impl Device {
+    pub fn transfer_queue_family(&self) -> Option<u32> {
+        self.transfer_queue.map(|queue| queue.queue_family_index)
+    }
+
+    /// Granularity of image copies on the dedicated transfer queue. Queues
+    /// supporting graphics or compute always report `(1, 1, 1)`, so it's only
+    /// restrictive when a dedicated transfer queue exists.
+    pub fn transfer_granularity(&self) -> vk::Extent3D {
+        self.transfer_queue
+            .map(|queue| {
+                self.pdevice.queue_families[queue.queue_family_index as usize]
+                    .properties
+                    .min_image_transfer_granularity
+            })
+            .unwrap_or(vk::Extent3D {
+                width: 1,
+                height: 1,
+                depth: 1,
+            })
+    }
+
+    /// Checks that copy region can be executed on the transfer queue. Offsets
+    /// and extents are in texels, `mip_extent` is the extent of the copied mip
+    /// level.
+    pub fn validate_transfer_region(
+        &self,
+        offset: vk::Offset3D,
+        extent: vk::Extent3D,
+        mip_extent: vk::Extent3D,
+    ) -> Result<(), BackendError> {
+        let granularity = self.transfer_granularity();
+        let valid = |granularity: u32, offset: i32, extent: u32, mip_extent: u32| {
+            let offset = offset as u32;
+            if granularity == 0 {
+                offset == 0 && extent == mip_extent
+            } else {
+                offset.is_multiple_of(granularity)
+                    && (extent.is_multiple_of(granularity) || offset + extent == mip_extent)
+            }
+        };
+        if valid(granularity.width, offset.x, extent.width, mip_extent.width)
+            && valid(
+                granularity.height,
+                offset.y,
+                extent.height,
+                mip_extent.height,
+            )
+            && valid(granularity.depth, offset.z, extent.depth, mip_extent.depth)
+        {
+            Ok(())
+        } else {
+            Err(BackendError::TransferGranularity {
+                offset,
+                extent,
+                granularity,
+            })
+        }
+    }
+}
@@ -0,0 +1,570 @@
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use ash::vk;
+use parking_lot::Mutex;
+
+use crate::{
+    BackendError,
+    vulkan::{
+        Buffer, BufferDesc, BufferState, Device, Frame, Image, ImageState, Semaphore,
+        buffer_barrier, device::Queue, image_barrier,
+    },
+};
+
+#[derive(Debug)]
+struct Timeline {
+    semaphore: Semaphore,
+    /// Last batch whose acquire barriers were recorded on the main queue.
+    acquired: AtomicU64,
+}
+
+/// Completion of a single upload batch.
+#[derive(Debug, Clone)]
+pub struct UploadTicket {
+    timeline: Arc<Timeline>,
+    value: u64,
+    needs_acquire: bool,
+}
+
+impl UploadTicket {
+    /// Uploaded resource can be used by the main queue. When the upload went
+    /// through a dedicated transfer queue this also means the ownership
+    /// acquire was recorded by [`UploadManager::record_acquires`].
+    pub fn is_ready(&self, device: &Device) -> bool {
+        let completed = unsafe {
+            device
+                .raw
+                .get_semaphore_counter_value(self.timeline.semaphore.raw)
+        }
+        .is_ok_and(|value| value >= self.value);
+        completed
+            && (!self.needs_acquire || self.timeline.acquired.load(Ordering::Acquire) >= self.value)
+    }
+}
+
+enum UploadTarget {
+    Buffer(Arc<Buffer>, BufferState),
+    Image(Arc<Image>, ImageState),
+}
+
+struct Batch {
+    value: u64,
+    cb: vk::CommandBuffer,
+    staging: Vec<Buffer>,
+    // Keeps resources alive until the copy is done, so dropping them while
+    // the upload is in flight (cancelled load) is safe.
+    targets: Vec<UploadTarget>,
+}
+
+/// Batches uploads and submits them to the dedicated transfer queue, falling
+/// back to the main queue when the device doesn't have one.
+pub struct UploadManager {
+    device: Arc<Device>,
+    queue: Queue,
+    pool: Mutex<vk::CommandPool>,
+    timeline: Arc<Timeline>,
+    pending: Mutex<Option<Batch>>,
+    in_flight: Mutex<VecDeque<Batch>>,
+    submitted: AtomicU64,
+}
+
+impl Debug for UploadManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UploadManager")
+            .field("queue", &self.queue)
+            .field("submitted", &self.submitted)
+            .field("in_flight", &self.in_flight.lock().len())
+            .finish()
+    }
+}
+
+impl UploadManager {
+    pub fn new(device: &Arc<Device>) -> Result<Self, BackendError> {
+        let queue = device.transfer_queue.unwrap_or(device.main_queue);
+        let pool = unsafe {
+            device.raw.create_command_pool(
+                &vk::CommandPoolCreateInfo::default()
+                    .queue_family_index(queue.queue_family_index)
+                    .flags(
+                        vk::CommandPoolCreateFlags::TRANSIENT
+                            | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+                    ),
+                None,
+            )
+        }?;
+        let semaphore = match device.create_timeline_semaphore(0, Some("upload timeline")) {
+            Ok(semaphore) => semaphore,
+            Err(err) => {
+                unsafe { device.raw.destroy_command_pool(pool, None) };
+                return Err(err);
+            }
+        };
+        Ok(Self {
+            device: device.clone(),
+            queue,
+            pool: Mutex::new(pool),
+            timeline: Arc::new(Timeline {
+                semaphore,
+                acquired: AtomicU64::new(0),
+            }),
+            pending: Mutex::default(),
+            in_flight: Mutex::default(),
+            submitted: AtomicU64::new(0),
+        })
+    }
+
+    fn needs_acquire(&self) -> bool {
+        self.queue.queue_family_index != self.device.main_queue.queue_family_index
+    }
+
+    fn ticket(&self, value: u64) -> UploadTicket {
+        UploadTicket {
+            timeline: self.timeline.clone(),
+            value,
+            needs_acquire: self.needs_acquire(),
+        }
+    }
+
+    fn staging(&self, data: &[u8]) -> Result<Buffer, BackendError> {
+        let mut staging = self.device.create_buffer(
+            BufferDesc::new(data.len() as u64, vk::BufferUsageFlags::TRANSFER_SRC).memory(
+                gpu_alloc::UsageFlags::HOST_ACCESS
+                    | gpu_alloc::UsageFlags::UPLOAD
+                    | gpu_alloc::UsageFlags::TRANSIENT,
+            ),
+        )?;
+        staging.write(0, data)?;
+        Ok(staging)
+    }
+
+    /// Records into the pending batch, opening a new one if needed. Returns
+    /// the timeline value the batch will signal.
+    fn record<F: FnOnce(&ash::Device, vk::CommandBuffer)>(
+        &self,
+        staging: Buffer,
+        target: UploadTarget,
+        cb: F,
+    ) -> Result<u64, BackendError> {
+        let mut pending = self.pending.lock();
+        if pending.is_none() {
+            let pool = self.pool.lock();
+            let info = vk::CommandBufferAllocateInfo::default()
+                .command_buffer_count(1)
+                .command_pool(*pool)
+                .level(vk::CommandBufferLevel::PRIMARY);
+            let raw = unsafe { self.device.raw.allocate_command_buffers(&info) }?[0];
+            let begin = vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            if let Err(err) = unsafe { self.device.raw.begin_command_buffer(raw, &begin) } {
+                unsafe { self.device.raw.free_command_buffers(*pool, &[raw]) };
+                return Err(err.into());
+            }
+            *pending = Some(Batch {
+                value: self.submitted.load(Ordering::Relaxed) + 1,
+                cb: raw,
+                staging: Vec::new(),
+                targets: Vec::new(),
+            });
+        }
+        let batch = pending.as_mut().unwrap();
+        cb(&self.device.raw, batch.cb);
+        batch.staging.push(staging);
+        batch.targets.push(target);
+        Ok(batch.value)
+    }
+
+    /// Queues copy of `data` into the buffer at `offset`. The buffer must not
+    /// be used by the GPU until the ticket is ready, after that it's visible
+    /// in `state`.
+    pub fn upload_buffer(
+        &self,
+        buffer: &Arc<Buffer>,
+        offset: u64,
+        data: &[u8],
+        state: BufferState,
+    ) -> Result<UploadTicket, BackendError> {
+        if offset
+            .checked_add(data.len() as u64)
+            .is_none_or(|end| end > buffer.desc.size)
+        {
+            return Err(BackendError::InvalidArgument(format!(
+                "Upload of {} bytes at {offset} is out of buffer bounds ({})",
+                data.len(),
+                buffer.desc.size
+            )));
+        }
+        let staging = self.staging(data)?;
+        let raw = buffer.raw;
+        let staging_raw = staging.raw;
+        let release = self.buffer_release(raw, state);
+        let value = self.record(
+            staging,
+            UploadTarget::Buffer(buffer.clone(), state),
+            |device, cb| unsafe {
+                let region = vk::BufferCopy::default()
+                    .dst_offset(offset)
+                    .size(data.len() as u64);
+                device.cmd_copy_buffer(cb, staging_raw, raw, &[region]);
+                let barriers = [release];
+                let info = vk::DependencyInfo::default().buffer_memory_barriers(&barriers);
+                device.cmd_pipeline_barrier2(cb, &info);
+            },
+        )?;
+        Ok(self.ticket(value))
+    }
+
+    /// Queues copy of `data` into the image, previous contents are discarded.
+    /// After the ticket is ready the whole image is in `state`. Regions must
+    /// match [`Device::transfer_granularity`] of the upload queue.
+    pub fn upload_image(
+        &self,
+        image: &Arc<Image>,
+        data: &[u8],
+        regions: &[vk::BufferImageCopy],
+        state: ImageState,
+    ) -> Result<UploadTicket, BackendError> {
+        for region in regions {
+            self.device.validate_transfer_region(
+                region.image_offset,
+                region.image_extent,
+                image.desc.mip_extent(region.image_subresource.mip_level),
+            )?;
+        }
+        let staging = self.staging(data)?;
+        let raw = image.raw;
+        let range = image.subresource_range();
+        let staging_raw = staging.raw;
+        let release = self.image_release(raw, range, state);
+        let value = self.record(
+            staging,
+            UploadTarget::Image(image.clone(), state),
+            |device, cb| unsafe {
+                let barriers = [image_barrier(
+                    raw,
+                    range,
+                    ImageState::UNDEFINED,
+                    ImageState::TRANSFER_DST,
+                )];
+                let info = vk::DependencyInfo::default().image_memory_barriers(&barriers);
+                device.cmd_pipeline_barrier2(cb, &info);
+                device.cmd_copy_buffer_to_image(
+                    cb,
+                    staging_raw,
+                    raw,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    regions,
+                );
+                let barriers = [release];
+                let info = vk::DependencyInfo::default().image_memory_barriers(&barriers);
+                device.cmd_pipeline_barrier2(cb, &info);
+            },
+        )?;
+        Ok(self.ticket(value))
+    }
+
+    fn buffer_release(
+        &self,
+        buffer: vk::Buffer,
+        state: BufferState,
+    ) -> vk::BufferMemoryBarrier2<'static> {
+        if self.needs_acquire() {
+            buffer_barrier(buffer, BufferState::TRANSFER_DST, BufferState::NONE)
+                .src_queue_family_index(self.queue.queue_family_index)
+                .dst_queue_family_index(self.device.main_queue.queue_family_index)
+        } else {
+            buffer_barrier(buffer, BufferState::TRANSFER_DST, state)
+        }
+    }
+
+    fn image_release(
+        &self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        state: ImageState,
+    ) -> vk::ImageMemoryBarrier2<'static> {
+        if self.needs_acquire() {
+            let to = ImageState::new(
+                state.layout,
+                vk::PipelineStageFlags2::NONE,
+                vk::AccessFlags2::NONE,
+            );
+            image_barrier(image, range, ImageState::TRANSFER_DST, to)
+                .src_queue_family_index(self.queue.queue_family_index)
+                .dst_queue_family_index(self.device.main_queue.queue_family_index)
+        } else {
+            image_barrier(image, range, ImageState::TRANSFER_DST, state)
+        }
+    }
+
+    /// Submits pending uploads as a single batch.
+    pub fn flush(&self) -> Result<(), BackendError> {
+        let Some(batch) = self.pending.lock().take() else {
+            return Ok(());
+        };
+        let result = (|| {
+            unsafe { self.device.raw.end_command_buffer(batch.cb) }?;
+            let command_buffers = [vk::CommandBufferSubmitInfo::default().command_buffer(batch.cb)];
+            let signal = [vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.timeline.semaphore.raw)
+                .value(batch.value)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+            let info = vk::SubmitInfo2::default()
+                .command_buffer_infos(&command_buffers)
+                .signal_semaphore_infos(&signal);
+            let _lock = self.device.submit_lock.lock();
+            unsafe {
+                self.device
+                    .raw
+                    .queue_submit2(self.queue.raw, &[info], vk::Fence::null())
+            }?;
+            Ok::<_, BackendError>(())
+        })();
+        if let Err(err) = result {
+            unsafe {
+                self.device
+                    .raw
+                    .free_command_buffers(*self.pool.lock(), &[batch.cb])
+            };
+            return Err(err);
+        }
+        self.submitted.store(batch.value, Ordering::Relaxed);
+        self.in_flight.lock().push_back(batch);
+        Ok(())
+    }
+
+    /// Retires finished batches and records their acquire barriers into the
+    /// main command buffer of the frame. Call it every frame after beginning
+    /// the main command buffer and before recording work that uses uploaded
+    /// resources.
+    pub fn record_acquires(&self, frame: &Frame) -> Result<(), BackendError> {
+        let completed = self.timeline.semaphore.value()?;
+        let mut finished = Vec::new();
+        {
+            let mut in_flight = self.in_flight.lock();
+            while in_flight
+                .front()
+                .is_some_and(|batch| batch.value <= completed)
+            {
+                finished.extend(in_flight.pop_front());
+            }
+        }
+        if finished.is_empty() {
+            return Ok(());
+        }
+        {
+            let pool = self.pool.lock();
+            let command_buffers = finished.iter().map(|batch| batch.cb).collect::<Vec<_>>();
+            unsafe {
+                self.device
+                    .raw
+                    .free_command_buffers(*pool, &command_buffers)
+            };
+        }
+        if self.needs_acquire() {
+            let src_family = self.queue.queue_family_index;
+            let dst_family = self.device.main_queue.queue_family_index;
+            let mut image_barriers = Vec::new();
+            let mut buffer_barriers = Vec::new();
+            for target in finished.iter().flat_map(|batch| &batch.targets) {
+                match target {
+                    UploadTarget::Buffer(buffer, state) => buffer_barriers.push(
+                        buffer_barrier(buffer.raw, BufferState::NONE, *state)
+                            .src_queue_family_index(src_family)
+                            .dst_queue_family_index(dst_family),
+                    ),
+                    UploadTarget::Image(image, state) => {
+                        let from = ImageState::new(
+                            ImageState::TRANSFER_DST.layout,
+                            vk::PipelineStageFlags2::NONE,
+                            vk::AccessFlags2::NONE,
+                        );
+                        image_barriers.push(
+                            image_barrier(image.raw, image.subresource_range(), from, *state)
+                                .src_queue_family_index(src_family)
+                                .dst_queue_family_index(dst_family),
+                        )
+                    }
+                }
+            }
+            let info = vk::DependencyInfo::default()
+                .image_memory_barriers(&image_barriers)
+                .buffer_memory_barriers(&buffer_barriers);
+            unsafe {
+                self.device
+                    .raw
+                    .cmd_pipeline_barrier2(frame.main_cb().raw(), &info)
+            };
+        }
+        let last = finished.last().map(|batch| batch.value).unwrap_or_default();
+        self.timeline.acquired.fetch_max(last, Ordering::Release);
+        // Resources dropped here go through the drop list, so they outlive the
+        // frame recording the acquire barriers.
+        drop(finished);
+        Ok(())
+    }
+}
+
+impl Drop for UploadManager {
+    fn drop(&mut self) {
+        let pool = *self.pool.get_mut();
+        if let Some(batch) = self.pending.get_mut().take() {
+            unsafe { self.device.raw.free_command_buffers(pool, &[batch.cb]) };
+        }
+        let submitted = *self.submitted.get_mut();
+        if let Err(err) = self.timeline.semaphore.wait_value(submitted, u64::MAX) {
+            log::error!("Failed to wait for uploads: {err}");
+        }
+        self.in_flight.get_mut().clear();
+        unsafe { self.device.raw.destroy_command_pool(pool, None) };
+    }
+}
+
+/// Upload of a single image subresource split into chunks of rows, one chunk
+/// per frame, see [`Device::upload_image_streamed`].
+pub struct ImageStream {
+    image: Arc<Image>,
+    mip: u32,
+    layer: u32,
+    data: Vec<u8>,
+    row_bytes: usize,
+    rows_per_chunk: u32,
+    next_row: u32,
+    staging: [Buffer; 2],
+}
+
+impl Debug for ImageStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImageStream")
+            .field("image", &self.image)
+            .field("mip", &self.mip)
+            .field("layer", &self.layer)
+            .field("next_row", &self.next_row)
+            .finish()
+    }
+}
+
+impl Device {
+    /// Starts uploading tightly packed uncompressed pixels of one mip level
+    /// and array layer. Staging memory is bounded by two buffers of
+    /// `chunk_size` bytes, though at least one row is copied per frame.
+    pub fn upload_image_streamed(
+        self: &Arc<Self>,
+        dst: &Arc<Image>,
+        mip: u32,
+        layer: u32,
+        data: Vec<u8>,
+        chunk_size: u64,
+    ) -> Result<ImageStream, BackendError> {
+        let height = dst.desc.mip_extent(mip).height;
+        if data.is_empty() {
+            return Err(BackendError::EmptyImage);
+        }
+        let row_bytes = data.len() / height as usize;
+        if row_bytes * height as usize != data.len() {
+            return Err(BackendError::PixelDataSize {
+                expected: row_bytes * height as usize,
+                actual: data.len(),
+            });
+        }
+        let rows_per_chunk = (chunk_size / row_bytes as u64).clamp(1, height as u64) as u32;
+        let staging_size = rows_per_chunk as u64 * row_bytes as u64;
+        let create_staging = || {
+            self.create_buffer(
+                BufferDesc::new(staging_size, vk::BufferUsageFlags::TRANSFER_SRC)
+                    .memory(gpu_alloc::UsageFlags::HOST_ACCESS | gpu_alloc::UsageFlags::UPLOAD),
+            )
+        };
+        Ok(ImageStream {
+            image: dst.clone(),
+            mip,
+            layer,
+            data,
+            row_bytes,
+            rows_per_chunk,
+            next_row: 0,
+            staging: [create_staging()?, create_staging()?],
+        })
+    }
+}
+
+impl ImageStream {
+    pub fn is_finished(&self) -> bool {
+        self.next_row >= self.image.desc.mip_extent(self.mip).height
+    }
+
+    /// Records copy of the next chunk into the main command buffer of the
+    /// frame. The subresource is in `TRANSFER_DST_OPTIMAL` layout until the
+    /// last chunk leaves it in `SHADER_READ_ONLY_OPTIMAL`. Returns whether the
+    /// upload is finished.
+    pub fn record(&mut self, frame: &Frame) -> Result<bool, BackendError> {
+        if self.is_finished() {
+            return Ok(true);
+        }
+        let extent = self.image.desc.mip_extent(self.mip);
+        let range = vk::ImageSubresourceRange::default()
+            .aspect_mask(self.image.aspect())
+            .base_mip_level(self.mip)
+            .level_count(1)
+            .base_array_layer(self.layer)
+            .layer_count(1);
+        let rows = self.rows_per_chunk.min(extent.height - self.next_row);
+        let start = self.next_row as usize * self.row_bytes;
+        let end = start + rows as usize * self.row_bytes;
+        let staging = &mut self.staging[(frame.device.frame_index() % 2) as usize];
+        staging.write(0, &self.data[start..end])?;
+        let device = &frame.device.raw;
+        let cb = frame.main_cb().raw();
+        if self.next_row == 0 {
+            frame.transition_image_layout(
+                self.image.raw,
+                range,
+                ImageState::UNDEFINED,
+                ImageState::TRANSFER_DST,
+            );
+        }
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(range.aspect_mask)
+                    .mip_level(self.mip)
+                    .base_array_layer(self.layer)
+                    .layer_count(1),
+            )
+            .image_offset(vk::Offset3D {
+                x: 0,
+                y: self.next_row as i32,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: rows,
+                depth: 1,
+            });
+        unsafe {
+            device.cmd_copy_buffer_to_image(
+                cb,
+                staging.raw,
+                self.image.raw,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            )
+        };
+        self.next_row += rows;
+        if self.is_finished() {
+            frame.transition_image_layout(
+                self.image.raw,
+                range,
+                ImageState::TRANSFER_DST,
+                ImageState::SHADER_READ,
+            );
+        }
+        Ok(self.is_finished())
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    fmt::Debug,
+    ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use ash::vk;
+
+use crate::{
+    BackendError, GpuMemory,
+    vulkan::{BufferSlice, Device, Frame, device::Queue},
+};
+
+pub(crate) struct VideoDecode {
+    pub queue: Queue,
+    pub video_queue: ash::khr::video_queue::Device,
+    pub decode_queue: ash::khr::video_decode_queue::Device,
+}
+
+impl VideoDecode {
+    pub fn new(instance: &ash::Instance, device: &ash::Device, queue: Queue) -> Self {
+        Self {
+            queue,
+            video_queue: ash::khr::video_queue::Device::new(instance, device),
+            decode_queue: ash::khr::video_decode_queue::Device::new(instance, device),
+        }
+    }
+}
+
+pub struct VideoSession {
+    pub raw: vk::VideoSessionKHR,
+    pub max_coded_extent: vk::Extent2D,
+    memory: Vec<GpuMemory>,
+    // Session state is undefined until the first coding scope resets it.
+    needs_reset: AtomicBool,
+    device: Arc<Device>,
+}
+
+impl Debug for VideoSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VideoSession")
+            .field("raw", &self.raw)
+            .field("max_coded_extent", &self.max_coded_extent)
+            .finish()
+    }
+}
+
+pub struct VideoSessionParameters {
+    pub raw: vk::VideoSessionParametersKHR,
+    device: Arc<Device>,
+}
+
+impl Debug for VideoSessionParameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VideoSessionParameters")
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+impl VideoSession {
+    /// Allocates and binds every memory binding the session requires. Must be
+    /// called once before the session is used.
+    pub fn bind_memory(&mut self) -> Result<(), BackendError> {
+        let loader = &self.device.video_decode()?.video_queue;
+        let get_requirements = loader.fp().get_video_session_memory_requirements_khr;
+        let mut count = 0;
+        unsafe { get_requirements(loader.device(), self.raw, &mut count, ptr::null_mut()) }
+            .result()?;
+        let mut requirements =
+            vec![vk::VideoSessionMemoryRequirementsKHR::default(); count as usize];
+        unsafe {
+            get_requirements(
+                loader.device(),
+                self.raw,
+                &mut count,
+                requirements.as_mut_ptr(),
+            )
+        }
+        .result()?;
+        let mut binds = Vec::with_capacity(requirements.len());
+        for requirement in &requirements {
+            let memory = self.device.allocate_memory(gpu_alloc::Request {
+                size: requirement.memory_requirements.size,
+                align_mask: requirement.memory_requirements.alignment - 1,
+                usage: gpu_alloc::UsageFlags::FAST_DEVICE_ACCESS,
+                memory_types: requirement.memory_requirements.memory_type_bits,
+            })?;
+            binds.push(
+                vk::BindVideoSessionMemoryInfoKHR::default()
+                    .memory_bind_index(requirement.memory_bind_index)
+                    .memory(*memory.memory())
+                    .memory_offset(memory.offset())
+                    .memory_size(requirement.memory_requirements.size),
+            );
+            self.memory.push(memory);
+        }
+        unsafe {
+            (loader.fp().bind_video_session_memory_khr)(
+                loader.device(),
+                self.raw,
+                binds.len() as u32,
+                binds.as_ptr(),
+            )
+        }
+        .result()?;
+        Ok(())
+    }
+
+    /// Creates session parameters, `codec_info` holds codec specific
+    /// parameter sets, e.g. `VideoDecodeH264SessionParametersCreateInfoKHR`.
+    pub fn create_parameters<T: vk::ExtendsVideoSessionParametersCreateInfoKHR + ?Sized>(
+        &self,
+        codec_info: &mut T,
+    ) -> Result<VideoSessionParameters, BackendError> {
+        let loader = &self.device.video_decode()?.video_queue;
+        let info = vk::VideoSessionParametersCreateInfoKHR::default()
+            .video_session(self.raw)
+            .push_next(codec_info);
+        let mut raw = vk::VideoSessionParametersKHR::null();
+        unsafe {
+            (loader.fp().create_video_session_parameters_khr)(
+                loader.device(),
+                &info,
+                ptr::null(),
+                &mut raw,
+            )
+        }
+        .result()?;
+        Ok(VideoSessionParameters {
+            raw,
+            device: self.device.clone(),
+        })
+    }
+}
+
+impl Device {
+    /// Queue family of the dedicated video decode queue, `None` when the
+    /// device has no such queue or doesn't support `VK_KHR_video_decode_queue`.
+    pub fn video_decode_queue_family(&self) -> Option<u32> {
+        self.video_decode
+            .as_ref()
+            .map(|video_decode| video_decode.queue.queue_family_index)
+    }
+
+    pub(crate) fn video_decode(&self) -> Result<&VideoDecode, BackendError> {
+        self.video_decode
+            .as_ref()
+            .ok_or(BackendError::ExtensionNotEnabled(
+                ash::khr::video_decode_queue::NAME,
+            ))
+    }
+
+    /// Creates decode session, `profile` must chain the codec profile, e.g.
+    /// `VideoDecodeH264ProfileInfoKHR`.
+    pub fn create_video_session(
+        self: &Arc<Self>,
+        profile: &vk::VideoProfileInfoKHR,
+        format: vk::Format,
+        max_coded_extent: vk::Extent2D,
+        reference_format: vk::Format,
+        max_dpb_slots: u32,
+    ) -> Result<VideoSession, BackendError> {
+        let video_decode = self.video_decode()?;
+        let std_header_version;
+        let max_active_reference_pictures;
+        {
+            let instance =
+                ash::khr::video_queue::Instance::new(&self.instance.entry, &self.instance.raw);
+            let mut h264 = vk::VideoDecodeH264CapabilitiesKHR::default();
+            let mut h265 = vk::VideoDecodeH265CapabilitiesKHR::default();
+            let mut av1 = vk::VideoDecodeAV1CapabilitiesKHR::default();
+            let mut decode = vk::VideoDecodeCapabilitiesKHR::default();
+            let mut capabilities = vk::VideoCapabilitiesKHR::default().push_next(&mut decode);
+            capabilities = match profile.video_codec_operation {
+                vk::VideoCodecOperationFlagsKHR::DECODE_H264 => capabilities.push_next(&mut h264),
+                vk::VideoCodecOperationFlagsKHR::DECODE_H265 => capabilities.push_next(&mut h265),
+                vk::VideoCodecOperationFlagsKHR::DECODE_AV1 => capabilities.push_next(&mut av1),
+                _ => capabilities,
+            };
+            unsafe {
+                (instance.fp().get_physical_device_video_capabilities_khr)(
+                    self.pdevice.raw,
+                    profile,
+                    &mut capabilities,
+                )
+            }
+            .result()?;
+            std_header_version = capabilities.std_header_version;
+            max_active_reference_pictures = capabilities
+                .max_active_reference_pictures
+                .min(max_dpb_slots);
+        }
+        let info = vk::VideoSessionCreateInfoKHR::default()
+            .queue_family_index(video_decode.queue.queue_family_index)
+            .video_profile(profile)
+            .picture_format(format)
+            .max_coded_extent(max_coded_extent)
+            .reference_picture_format(reference_format)
+            .max_dpb_slots(max_dpb_slots)
+            .max_active_reference_pictures(max_active_reference_pictures)
+            .std_header_version(&std_header_version);
+        let loader = &video_decode.video_queue;
+        let mut raw = vk::VideoSessionKHR::null();
+        unsafe {
+            (loader.fp().create_video_session_khr)(loader.device(), &info, ptr::null(), &mut raw)
+        }
+        .result()?;
+        Ok(VideoSession {
+            raw,
+            max_coded_extent,
+            memory: Vec::new(),
+            needs_reset: AtomicBool::new(true),
+            device: self.clone(),
+        })
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Records decode of a single picture into the video decode command buffer
+    /// of the frame. `dst` is the DPB slot the picture is decoded into, its
+    /// picture resource is also the decode output. Pictures must already be
+    /// in `VIDEO_DECODE_DPB_KHR` layout, `codec_info` holds codec specific
+    /// picture info, e.g. `VideoDecodeH264PictureInfoKHR`.
+    pub fn decode_video<T: vk::ExtendsVideoDecodeInfoKHR + ?Sized>(
+        &self,
+        session: &VideoSession,
+        parameters: &VideoSessionParameters,
+        src: &BufferSlice,
+        dst: &vk::VideoReferenceSlotInfoKHR,
+        reference_slots: &[vk::VideoReferenceSlotInfoKHR],
+        codec_info: &mut T,
+    ) -> Result<(), BackendError> {
+        let video_decode = self.device.video_decode()?;
+        let cb = self
+            .video_decode_cb()
+            .expect("Video decode is enabled for every frame")
+            .raw();
+        let mut bound_slots = reference_slots.to_vec();
+        // Negative index binds the setup picture without activating its slot.
+        let mut setup_slot = *dst;
+        setup_slot.slot_index = -1;
+        bound_slots.push(setup_slot);
+        let begin = vk::VideoBeginCodingInfoKHR::default()
+            .video_session(session.raw)
+            .video_session_parameters(parameters.raw)
+            .reference_slots(&bound_slots);
+        let loader = &video_decode.video_queue;
+        unsafe { (loader.fp().cmd_begin_video_coding_khr)(cb, &begin) };
+        if session.needs_reset.swap(false, Ordering::Relaxed) {
+            let control = vk::VideoCodingControlInfoKHR::default()
+                .flags(vk::VideoCodingControlFlagsKHR::RESET);
+            unsafe { (loader.fp().cmd_control_video_coding_khr)(cb, &control) };
+        }
+        assert!(
+            !dst.p_picture_resource.is_null(),
+            "Decode target slot must have a picture resource"
+        );
+        let dst_picture = unsafe { *dst.p_picture_resource };
+        let info = vk::VideoDecodeInfoKHR::default()
+            .src_buffer(src.buffer.raw)
+            .src_buffer_offset(src.offset)
+            .src_buffer_range(src.size)
+            .dst_picture_resource(dst_picture)
+            .setup_reference_slot(dst)
+            .reference_slots(reference_slots)
+            .push_next(codec_info);
+        unsafe {
+            (video_decode.decode_queue.fp().cmd_decode_video_khr)(cb, &info);
+            (loader.fp().cmd_end_video_coding_khr)(cb, &vk::VideoEndCodingInfoKHR::default());
+        }
+        Ok(())
+    }
+
+    /// Submits video decode command buffer of the frame to the video decode
+    /// queue.
+    pub fn submit_video_decode(
+        &self,
+        waits: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+        signals: &[(vk::Semaphore, vk::PipelineStageFlags2)],
+    ) -> Result<(), BackendError> {
+        let video_decode = self.device.video_decode()?;
+        let cb = self
+            .video_decode_cb()
+            .expect("Video decode is enabled for every frame");
+        self.submit_to_queue(video_decode.queue, cb, waits, signals)
+    }
+}
+
+impl Drop for VideoSessionParameters {
+    fn drop(&mut self) {
+        let raw = self.raw;
+        self.device
+            .with_drop_list(|drop_list| drop_list.drop_video_session_parameters(raw));
+    }
+}
+
+impl Drop for VideoSession {
+    fn drop(&mut self) {
+        let raw = self.raw;
+        self.device
+            .with_drop_list(|drop_list| drop_list.drop_video_session(raw));
+        for memory in self.memory.drain(..) {
+            self.device.free_memory(memory);
+        }
+    }
+}
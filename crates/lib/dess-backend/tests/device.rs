@@ -0,0 +1,303 @@
+use std::sync::Arc;
+
+use dess_backend::{
+    BackendError,
+    test_utils::{test_device_or_skip, validated_test_device_or_skip},
+    vk,
+    vulkan::{
+        BufferDesc, BufferState, DescriptorSetLayoutBuilder, Device, Frame, FramebufferAttachment,
+        ImageDesc, ImageState, QueueKind, RenderGraph, RenderPassBuilder, RenderTargetPool,
+        SamplerDesc, SamplerLod, UploadManager,
+    },
+};
+
+#[test]
+fn buffer_write_read() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let desc = BufferDesc::new(256, vk::BufferUsageFlags::STORAGE_BUFFER)
+        .memory(gpu_alloc::UsageFlags::HOST_ACCESS | gpu_alloc::UsageFlags::DOWNLOAD);
+    let mut buffer = device.create_buffer(desc).unwrap();
+    let data = (0..=255).collect::<Vec<u8>>();
+    buffer.write(0, &data).unwrap();
+    let mut read = vec![0; data.len()];
+    buffer.read(0, &mut read).unwrap();
+    assert_eq!(read, data);
+}
+
+#[test]
+fn buffer_from_data_is_uploaded() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let data = (0..64).collect::<Vec<u8>>();
+    let buffer = device
+        .create_buffer_from_data(&data, vk::BufferUsageFlags::TRANSFER_SRC)
+        .unwrap();
+    assert_eq!(buffer.desc.size, data.len() as u64);
+    let read = device
+        .readback_buffer(&buffer, 0, data.len() as u64)
+        .unwrap();
+    assert_eq!(read, data);
+    for (offset, size) in [(1, data.len() as u64), (u64::MAX, 2)] {
+        assert!(matches!(
+            device.readback_buffer(&buffer, offset, size),
+            Err(BackendError::InvalidArgument(_))
+        ));
+    }
+}
+
+#[test]
+fn upload_out_of_bounds_is_rejected() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let uploads = UploadManager::new(&device).unwrap();
+    let buffer = Arc::new(
+        device
+            .create_buffer(BufferDesc::new(64, vk::BufferUsageFlags::TRANSFER_DST))
+            .unwrap(),
+    );
+    let data = [0u8; 16];
+    for offset in [60, u64::MAX] {
+        assert!(matches!(
+            uploads.upload_buffer(&buffer, offset, &data, BufferState::COMPUTE_READ),
+            Err(BackendError::InvalidArgument(_))
+        ));
+    }
+}
+
+#[test]
+fn sampler_count_is_tracked() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let count = device.sampler_count();
+    let sampler = device
+        .create_sampler_with_lod(SamplerDesc::linear_clamp(), SamplerLod::default())
+        .unwrap();
+    assert_eq!(device.sampler_count(), count + 1);
+    device.destroy_sampler(sampler);
+    assert_eq!(device.sampler_count(), count);
+}
+
+#[test]
+fn descriptor_sets_match_layout() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let builder = DescriptorSetLayoutBuilder::new()
+        .binding(
+            0,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            1,
+            vk::ShaderStageFlags::COMPUTE,
+        )
+        .binding(
+            1,
+            vk::DescriptorType::STORAGE_IMAGE,
+            4,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+    let counts = builder.descriptor_count();
+    assert_eq!(counts.uniform_buffer, 1);
+    assert_eq!(counts.storage_image, 4);
+    let layout = builder.build(&device).unwrap();
+    assert_eq!(layout.allocate(3).unwrap().len(), 3);
+}
+
+#[test]
+fn frames_reuse_slots() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    for index in 0..4 {
+        assert_eq!(device.frame_index(), index);
+        let frame = device.frame().unwrap();
+        frame.begin_main_cb().unwrap();
+        frame.end_main_cb().unwrap();
+        frame.submit_pass(frame.main_cb(), &[], &[]).unwrap();
+        frame.end();
+    }
+    assert!(device.wait_idle_with_timeout(u64::MAX).unwrap());
+}
+
+#[test]
+fn frame_end_closures_can_use_device() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    {
+        let frame = device.frame().unwrap();
+        let closure_device = device.clone();
+        frame.on_frame_end(move || {
+            let buffer = closure_device
+                .create_buffer(BufferDesc::new(256, vk::BufferUsageFlags::STORAGE_BUFFER));
+            sender.send(buffer.is_ok()).unwrap();
+        });
+        frame.end();
+    }
+    for _ in 0..3 {
+        device.frame().unwrap().end();
+    }
+    assert!(receiver.try_recv().unwrap());
+}
+
+#[test]
+fn compute_command_buffers_need_compute_queue() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let has_compute = device
+        .queues()
+        .iter()
+        .any(|queue| queue.kind == QueueKind::Compute);
+    for _ in 0..3 {
+        let frame = device.frame().unwrap();
+        let first = frame.allocate_compute_command_buffer();
+        let second = frame.allocate_compute_command_buffer();
+        assert_eq!(first.is_some(), has_compute);
+        if let (Some(first), Some(second)) = (first, second) {
+            assert_ne!(first.raw(), second.raw());
+        } else {
+            assert!(matches!(
+                frame.submit_compute(frame.main_cb(), &[], &[]),
+                Err(BackendError::NoComputeQueue)
+            ));
+        }
+        frame.end();
+    }
+}
+
+fn record_five_pass_chain(device: &Arc<Device>) {
+    let pool = RenderTargetPool::new(device);
+    let target_desc = ImageDesc::texture_2d(vk::Format::R8G8B8A8_UNORM, 64, 64)
+        .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC);
+    let target = device.create_image(target_desc).unwrap();
+    let target_view = target.view(target.view_desc()).unwrap();
+    let desc = |format| ImageDesc::texture_2d(format, 64, 64);
+    let executed = std::cell::RefCell::new(Vec::new());
+    for _ in 0..3 {
+        let frame = device.frame().unwrap();
+        frame.begin_main_cb().unwrap();
+        let mut graph = RenderGraph::new();
+        let output = graph.import_image(
+            target.raw,
+            target_view,
+            target_desc.format,
+            ImageState::UNDEFINED,
+            Some(ImageState::TRANSFER_SRC),
+        );
+        let albedo = graph.create_image(desc(vk::Format::R8G8B8A8_UNORM));
+        let depth = graph.create_image(desc(vk::Format::D32_SFLOAT));
+        let ao = graph.create_image(desc(vk::Format::R32_SFLOAT));
+        let hdr = graph.create_image(desc(vk::Format::R16G16B16A16_SFLOAT));
+        graph
+            .add_pass("gbuffer")
+            .write_image(albedo, ImageState::COLOR_ATTACHMENT)
+            .write_image(depth, ImageState::DEPTH_ATTACHMENT)
+            .execute(|_| executed.borrow_mut().push("gbuffer"));
+        graph
+            .add_pass("ssao")
+            .read_image(depth, ImageState::SHADER_READ)
+            .write_image(ao, ImageState::STORAGE)
+            .execute(|_| executed.borrow_mut().push("ssao"));
+        graph
+            .add_pass("lighting")
+            .read_image(albedo, ImageState::SHADER_READ)
+            .read_image(depth, ImageState::SHADER_READ)
+            .read_image(ao, ImageState::SHADER_READ)
+            .write_image(hdr, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| executed.borrow_mut().push("lighting"));
+        graph
+            .add_pass("post")
+            .read_image(hdr, ImageState::SHADER_READ)
+            .write_image(output, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| executed.borrow_mut().push("post"));
+        graph
+            .add_pass("ui")
+            .write_image(output, ImageState::COLOR_ATTACHMENT)
+            .execute(|_| executed.borrow_mut().push("ui"));
+        graph.execute(&frame, &pool).unwrap();
+        frame.end_main_cb().unwrap();
+        frame.submit_pass(frame.main_cb(), &[], &[]).unwrap();
+        frame.end();
+        assert_eq!(
+            executed.take(),
+            ["gbuffer", "ssao", "lighting", "post", "ui"]
+        );
+    }
+    assert!(device.wait_idle_with_timeout(u64::MAX).unwrap());
+}
+
+#[test]
+fn render_graph_records_five_pass_chain() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    record_five_pass_chain(&device);
+}
+
+#[test]
+fn render_graph_barriers_pass_synchronization_validation() {
+    let Some(device) = validated_test_device_or_skip() else {
+        return;
+    };
+    record_five_pass_chain(&device);
+    assert_eq!(device.instance().debug_errors(), 0);
+    assert_eq!(device.instance().debug_warnings(), 0);
+}
+
+#[test]
+fn framebuffer_creates_missing_views() {
+    let Some(device) = test_device_or_skip() else {
+        return;
+    };
+    let format = vk::Format::R8G8B8A8_UNORM;
+    let mut builder = RenderPassBuilder::new();
+    let color = builder.add_color_attachment(
+        format,
+        vk::AttachmentLoadOp::CLEAR,
+        vk::AttachmentStoreOp::STORE,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+    builder.add_subpass(&[color], None);
+    let render_pass = builder.build(&device).unwrap();
+    let image = device
+        .create_image(
+            ImageDesc::texture_2d(format, 32, 32).usage(vk::ImageUsageFlags::COLOR_ATTACHMENT),
+        )
+        .unwrap();
+    let extent = vk::Extent2D::default().width(32).height(32);
+    let from_image = device
+        .create_framebuffer(
+            render_pass,
+            &[(FramebufferAttachment::Image(&image), format)],
+            extent,
+            1,
+        )
+        .unwrap();
+    let view = image.view(image.view_desc()).unwrap();
+    let from_view = device
+        .create_framebuffer(
+            render_pass,
+            &[(FramebufferAttachment::View(view), format)],
+            extent,
+            1,
+        )
+        .unwrap();
+    device.with_drop_list(|drop_list| {
+        drop_list.drop_framebuffer(from_image);
+        drop_list.drop_framebuffer(from_view);
+        drop_list.drop_render_pass(render_pass);
+    });
+}
+
+#[test]
+fn frame_can_be_shared_with_workers() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Frame<'static>>();
+}